@@ -0,0 +1,28 @@
+// screen space wobble with a bit of chromatic aberration, stronger the less oxygen is left
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0) var screen_texture: texture_2d<f32>;
+@group(0) @binding(1) var texture_sampler: sampler;
+
+struct UnderwaterDistortion {
+    strength: f32,
+    time: f32,
+}
+@group(0) @binding(2) var<uniform> distortion: UnderwaterDistortion;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let wobble = vec2<f32>(
+        sin(in.uv.y * 23.0 + distortion.time * 1.9),
+        cos(in.uv.x * 17.0 + distortion.time * 1.3),
+    ) * 0.004 * distortion.strength;
+    let uv = in.uv + wobble;
+    let split = vec2<f32>(0.003 * distortion.strength, 0.0);
+
+    return vec4<f32>(
+        textureSample(screen_texture, texture_sampler, uv + split).r,
+        textureSample(screen_texture, texture_sampler, uv).g,
+        textureSample(screen_texture, texture_sampler, uv - split).b,
+        1.0,
+    );
+}
@@ -0,0 +1,187 @@
+//developer console for testing, toggled with the backtick key; only compiled into debug builds
+//while it is open all keyboard input goes to the console instead of the game
+
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState, InputSystem,
+    },
+    prelude::*,
+};
+
+use super::{
+    spawn_bubble, ActiveEffects, BubbleModels, BubbleType, EffectKind, GodMode, OxygenLevel,
+    Player, Settings, BUBBLE_EFFECT_FREEZE_DURATION, BUBBLE_HOVER_OFFSET,
+};
+
+const CONSOLE_HISTORY_LINES: usize = 8; //output lines kept on screen
+const CONSOLE_SPAWN_DISTANCE: f32 = 2.5; //how far from the player the spawn command places a bubble
+
+const CONSOLE_HELP: &str =
+    "commands: oxygen <amount>, spawn <bubble type>, freeze, kill, godmode <on|off>, help";
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .add_systems(Startup, spawn_console_text)
+            .add_systems(PreUpdate, console_input.after(InputSystem))
+            .add_systems(Update, update_console_text);
+    }
+}
+
+#[derive(Resource, Default)]
+struct Console {
+    open: bool,
+    input: String,
+    history: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, line: String) {
+        self.history.push(line);
+        if self.history.len() > CONSOLE_HISTORY_LINES {
+            self.history.remove(0);
+        }
+    }
+}
+
+#[derive(Component)]
+struct ConsoleText;
+
+fn spawn_console_text(mut commands: Commands) {
+    commands.spawn((
+        ConsoleText,
+        Text::default(),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            left: Val::Percent(30.0),
+            width: Val::Percent(40.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+        Visibility::Hidden,
+    ));
+}
+
+//runs right after the input is collected, so the game never sees keys typed into the console
+#[allow(clippy::too_many_arguments)]
+fn console_input(
+    mut commands: Commands,
+    mut console: ResMut<Console>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut keyboard_input: ResMut<ButtonInput<KeyCode>>,
+    player_query: Single<(&Transform, &mut OxygenLevel), With<Player>>,
+    bubble_models: Res<BubbleModels>,
+    mut active_effects: ResMut<ActiveEffects>,
+    mut god_mode: ResMut<GodMode>,
+    settings: Res<Settings>,
+) {
+    let (player_transform, mut oxygen_level) = player_query.into_inner();
+    //keys that close the console (like escape) should not reach the game either
+    let was_open = console.open;
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        if event.key_code == KeyCode::Backquote {
+            console.open = !console.open;
+            console.input.clear();
+            continue;
+        }
+        if !console.open {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Character(characters) => console.input.push_str(characters),
+            Key::Space => console.input.push(' '),
+            Key::Backspace => {
+                console.input.pop();
+            }
+            Key::Escape => console.open = false,
+            Key::Enter => {
+                let line = std::mem::take(&mut console.input);
+                console.print(format!("> {}", line));
+                let mut arguments = line.split_whitespace();
+                let output = match (arguments.next(), arguments.next()) {
+                    (Some("oxygen"), Some(amount)) => match amount.parse::<f32>() {
+                        Ok(amount) => {
+                            oxygen_level.0 = amount;
+                            format!("oxygen set to {}", amount)
+                        }
+                        Err(_) => format!("{} is not a number", amount),
+                    },
+                    (Some("spawn"), Some(name)) => {
+                        match BubbleType::from_name(name) {
+                            Some(bubble_type) if bubble_models.is_ready(bubble_type) => {
+                                //next to the player, drifting towards them
+                                let spawn_location = player_transform.translation
+                                    + Vec3::new(CONSOLE_SPAWN_DISTANCE, BUBBLE_HOVER_OFFSET, 0.0);
+                                spawn_bubble(
+                                    &mut commands,
+                                    &bubble_models,
+                                    bubble_type,
+                                    spawn_location,
+                                    Vec2::NEG_X,
+                                    &settings,
+                                );
+                                format!("spawned a {} bubble", name)
+                            }
+                            Some(_) => format!("the {} bubble model is not loaded yet", name),
+                            None => format!("there is no {} bubble", name),
+                        }
+                    }
+                    (Some("freeze"), None) => {
+                        active_effects.refresh(EffectKind::Freeze, BUBBLE_EFFECT_FREEZE_DURATION);
+                        "bubbles frozen".to_string()
+                    }
+                    (Some("kill"), None) => {
+                        oxygen_level.0 = 0.0;
+                        "out of oxygen".to_string()
+                    }
+                    (Some("godmode"), Some(state @ ("on" | "off"))) => {
+                        god_mode.0 = state == "on";
+                        format!("god mode {}", state)
+                    }
+                    (None, _) => continue,
+                    _ => CONSOLE_HELP.to_string(),
+                };
+                console.print(output);
+            }
+            _ => {}
+        }
+    }
+
+    if console.open || was_open {
+        keyboard_input.reset_all();
+    }
+}
+
+fn update_console_text(
+    console: Res<Console>,
+    console_text: Single<(&mut Text, &mut Visibility), With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+
+    let (mut text, mut visibility) = console_text.into_inner();
+    *visibility = if console.open {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    text.0 = console.history.join("\n");
+    if !text.0.is_empty() {
+        text.0.push('\n');
+    }
+    text.0.push_str(&format!("> {}_", console.input));
+}
@@ -1,751 +1,9193 @@
 use bevy::diagnostic::LogDiagnosticsPlugin;
 use bevy::{
+    asset::io::file::FileAssetReader,
     audio::*,
     color::palettes::css::*,
+    ecs::schedule::SystemConfigs,
     gltf::GltfMesh,
+    input::{
+        gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest},
+        mouse::{MouseScrollUnit, MouseWheel},
+    },
     math::bounding::{BoundingSphere, IntersectsVolume},
     prelude::*,
+    render::primitives::Frustum,
+    window::{PresentMode, PrimaryWindow, WindowMode},
+    winit::WinitWindows,
 };
+#[cfg(debug_assertions)]
+use console::ConsolePlugin;
+use menu_navigation::{MenuActivated, MenuDefault, MenuItem, MenuNavigationPlugin};
 use ops::powf;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::f32::consts::PI;
+use std::f32::consts::{FRAC_PI_2, PI};
+use underwater_distortion::{UnderwaterDistortion, UnderwaterDistortionPlugin};
+use winit::window::Icon;
+
+#[cfg(debug_assertions)]
+mod console;
+mod menu_navigation;
+mod underwater_distortion;
 
 const PLAYER_MOVEMENT_SPEED: f32 = 7.0;
-const PLAYER_RADIUS: f32 = 0.35;
+const PLAYER_RADIUS: f32 = 0.35; //default collision radius, see PlayerStats
+const PLAYER_RADIUS_STEP: f32 = 0.05; //how much [ and ] change the radius while the hitbox is shown
 const PLAYER_OXYGEN_START_SUPPLY: f32 = 15.0;
 const PLAYER_OXYGEN_DECREASE_PER_SECOND: f32 = 1.0;
+const HARDCORE_DRAIN_MULTIPLIER: f32 = 1.5; //hardcore runs lose oxygen this much faster, everywhere
+const EASY_START_RESERVE: f32 = 5.0; //the easy preset starts with this much more than the chosen starting oxygen
+const EASY_DRAIN_MULTIPLIER: f32 = 0.8;
+const HARD_START_OXYGEN_FRACTION: f32 = 2.0 / 3.0; //the hard preset starts with this share of the chosen starting oxygen
+const START_OXYGEN_MIN: f32 = 5.0; //range of the starting oxygen that can be chosen in the main menu
+const START_OXYGEN_MAX: f32 = 30.0;
+const START_OXYGEN_STEP: f32 = 5.0;
+const HARD_DRAIN_MULTIPLIER: f32 = 1.25;
+const DIFFICULTY_PRESET_BIAS: f32 = 0.25; //harmful spawn bias of the hard preset, the easy one gets the negative
+const DENSITY_TARGET_EASY: usize = 10; //with density_fill, bubbles kept around at the start of an easy run
+const DENSITY_TARGET_NORMAL: usize = 14;
+const DENSITY_TARGET_HARD: usize = 18;
+const DENSITY_TARGET_RAMP: f32 = 0.1; //the density target grows by this many bubbles per second survived
+const DENSITY_FILL_PER_TICK: usize = 1; //extra bubbles queued per fixed update while below the target
+const DENSITY_MAX_BUBBLES: usize = 60; //density_fill never tops the bubbles up beyond this
+const PLAYER_TURN_SPEED: f32 = 12.0; //how quickly the player model turns towards the movement direction
+const OXYGEN_DISPLAY_SMOOTHING: f32 = 10.0; //how quickly the gauge and the player model follow the oxygen level
+const PLAYER_MODEL_YAW_OFFSET: f32 = 0.0; //extra yaw in case the model does not face +z by default
+const PLAYER_OXYGEN_MAX: f32 = PLAYER_OXYGEN_START_SUPPLY; //anything collected above this goes into the bonus reserve
+const BONUS_RESERVE_TRANSFER_PER_SECOND: f32 = 0.5; //how fast the reserve refills the tank when it is below max
+const DASH_SPEED: f32 = 24.0; //movement speed while dashing
+const DASH_DURATION: f32 = 0.15;
+const DASH_COOLDOWN: f32 = 1.0; //seconds from the start of one dash until the next one is possible
+const DASH_OXYGEN_COST: f32 = 1.0; //every dash takes this much oxygen, there is no dash with less than this left
+const DASH_POP_RADIUS_BONUS: f32 = 0.3; //the player pops bubbles this much further away while dashing
+const PANIC_CLEAR_RADIUS: f32 = 2.5; //harmful bubbles closer than this are cleared by the panic button
+const PANIC_CLEAR_OXYGEN_COST: f32 = 4.0;
+const PANIC_CLEAR_MIN_OXYGEN_LEFT: f32 = 1.0; //the panic button refuses to leave the player with less than this
+const PANIC_CLEAR_COOLDOWN: f32 = 15.0;
+const PLAYER_DEFLATED_SCALE: f32 = 0.8; //model scale relative to normal when the tank is empty; the hitbox stays the same
+const PLAYER_DEFLATED_EMISSIVE: f32 = 0.6; //strength of the red glow when the tank is empty
+
+//bevy spot light angles are in radians measured from the center of the cone to its edge:
+//0 <= inner_angle <= outer_angle <= PI / 2; the light fades out between the two angles
+const FLASHLIGHT_HEIGHT: f32 = 2.0; //the diver flashlight hangs this far above the player
+const FLASHLIGHT_REACH: f32 = 1.5; //distance ahead of the player the flashlight points at
+const FLASHLIGHT_INNER_ANGLE: f32 = 0.35;
+const FLASHLIGHT_OUTER_ANGLE: f32 = 0.6;
+const GAME_OVER_LIGHT_INNER_ANGLE: f32 = 1.0; //the game over screen light is wide, it only has to cover the screen
+const GAME_OVER_LIGHT_OUTER_ANGLE: f32 = 1.4;
 
 const PLATEAU_RADIUS: f32 = 4.0;
 const PLATEAU_MINIMUM_PLANTS: u32 = 24;
 const PLATEAU_MAXIMUM_PLANTS: u32 = 64;
+const PLATEAU_GROW_FACTOR: f32 = 1.5; //a grow bubble scales the safe zone up by this much
+const PLATEAU_GROW_DURATION: f32 = 8.0; //seconds the safe zone stays grown, collecting another one restarts this
+const WARP_MIN_DISTANCE: f32 = 3.0; //a warp bubble moves the player at least this far if the arena allows it
+const WARP_FLASH_PARTICLES: usize = 12; //particles in the ring at both ends of a warp
+const WARP_FLASH_RADIUS: f32 = 0.6; //radius of that ring
+const REPEL_IMPULSE: f32 = 2.5; //speed a repel bubble adds to every bubble, pointing away from the player
+const REPEL_MAX_SPEED: f32 = 4.0; //pushed bubbles never get faster than this
+const REPEL_RING_PARTICLES: usize = 24; //particles in the shockwave ring around the player
+const REPEL_RING_RADIUS: f32 = 1.2;
+const THIEF_SPEED: f32 = 1.6; //thieves roam a bit slower than the player can swim
+const THIEF_TURN_RATE: f32 = 2.0; //how quickly a thief turns towards its target
+const TIMED_FUSE: f32 = 6.0; //seconds until a timed bubble that was not collected goes off
+const TIMED_BLAST_RADIUS: f32 = 1.8; //the player loses oxygen if this close to a timed bubble when it goes off
+const TIMED_BLAST_OXYGEN_DECREASE: f32 = 3.0;
+const TIMED_BLAST_PARTICLES: usize = 16;
+const TIMED_PULSE_START_FREQUENCY: f32 = 1.0; //pulses per second right after spawning
+const TIMED_PULSE_END_FREQUENCY: f32 = 8.0; //pulses per second just before going off
+const TIMED_PULSE_SCALE: f32 = 0.12; //how much bigger a timed bubble gets at the top of a pulse
+const INVERT_DURATION: f32 = 6.0; //seconds an invert bubble turns the plateau around, collecting another one restarts this
+const RUSH_DURATION: f32 = 4.0; //seconds a rush bubble speeds the player up, collecting another one restarts this
+const RUSH_SPEED_MULTIPLIER: f32 = 1.6; //movement speed during a rush relative to normal
+const RUSH_MAX_DASH_MULTIPLIER: f32 = 1.2; //a dash during a rush is at most this much faster than a normal dash
+const RUSH_STREAK_INTERVAL: f32 = 0.04; //seconds between two streaks behind a rushing player
+const RUSH_STREAK_LIFETIME: f32 = 0.3;
+const RUSH_STREAK_LENGTH: f32 = 0.6;
+const PLATEAU_GROW_SPEED: f32 = 0.5; //how fast (scale per second) the safe zone grows and shrinks back
+const ARENA_BOUNDARY_HEIGHT: f32 = 0.02; //the boundary is drawn just above the sea floor
+const PLATEAU_REGEN_RADIUS: f32 = 1.5; //oxygen slowly comes back while this close to the center of the plateau
+const PLATEAU_REGEN_PER_SECOND: f32 = 1.5; //on top of the normal drain, so the tank fills by 0.5 per second
+const PLATEAU_REGEN_CAP: f32 = PLAYER_OXYGEN_MAX * 0.5; //the center never fills the tank beyond this
 
 const BUBBLE_RADIUS: f32 = 0.6; //defines size of the bubbles
+const BUBBLE_RADIUS_BIG: f32 = 0.75; //size of the heavy harmful bubbles, see BubbleType::radius
+const BUBBLE_RADIUS_SMALL: f32 = 0.45; //size of the power-up bubbles that are meant to be hard to catch
 const BUBBLE_SPAWN_RADIUS: f32 = 6.0; //defines the radius of the circle on which bubbles are spawned
+const BUBBLE_SPAWN_RADIUS_OPEN: f32 = 9.0; //spawn circle on the open playfield, where there is more room to roam
 const BUBBLE_HOVER_OFFSET: f32 = 0.25; //added to player_translation.y, so bubbles are slightly higher than player mesh; emphasizes transparency
+const BUBBLE_SHADOW_HEIGHT: f32 = 0.02; //shadows lie just above the sea floor so they don't flicker with it
+const BUBBLE_SHADOW_ALPHA: f32 = 0.35; //shadows are faint, they only hint at where a bubble is
+const BUBBLE_ORB_ALPHA: f32 = 0.8; //orbs are nearly opaque, they have no light to show them off
+const BUBBLE_BOB_AMPLITUDE: f32 = 0.04; //how far bubbles bob up and down around the hover offset, small enough to not look like a height change
+const BUBBLE_BOB_FREQUENCY: f32 = 0.6; //bobs per second
+const OFFSCREEN_UPDATE_INTERVAL: u32 = 4; //with offscreen_throttling, bubbles off screen only move every this many ticks
+const OFFSCREEN_MARGIN: f32 = 1.0; //bubbles this close to the edge of the view still count as on screen
+const SPAWN_ARC_BIAS_FRACTION: f32 = 0.5; //this share of the spawns comes from the arc the camera looks towards
+const SPAWN_ARC_WIDTH: f32 = PI; //radians of the spawn circle that count as in front of the camera
+const SPAWN_MIN_ANGLE_SEPARATION: f32 = 0.35; //radians between spawns that happen close together, so they don't overlap
+const SPAWN_ANGLE_MEMORY: f32 = 0.5; //seconds a spawn angle is kept for the separation check
+const SPAWN_ANGLE_MAX_RESAMPLES: u32 = 4; //after this many new tries the angle is taken as it is
 const BUBBLE_SPAWN_INTERVAL: f32 = 0.4; // spwan a bubble every <Spawn-interval> seconds
-const BUBBLE_MOVEMENT_SPEED: f32 = 0.3;
+const BUBBLE_MOVEMENT_SPEED: f32 = 0.3; //fraction of the distance to the player a new bubble covers per second
+const BUBBLE_MOVEMENT_SPEED_RAMP: f32 = 0.001; //added to the speed of new bubbles for every second survived
+const BUBBLE_MOVEMENT_MAX_SPEED: f32 = 0.6;
+const BUBBLE_LIGHT_INTENSITY: f32 = 10_000.0;
+const WAVE_FIRST_DELAY: f32 = 1.0; //seconds before the first wave starts
+const WAVE_FIRST_BUDGET: u32 = 20; //bubbles spawned in the first wave
+const WAVE_BUDGET_INCREASE: u32 = 6; //every wave spawns this many more bubbles than the last one
+const WAVE_REST_DURATION: f32 = 4.0; //breather between waves; oxygen keeps draining
+const WAVE_SPAWN_INTERVAL_FACTOR: f32 = 0.9; //every wave spawns faster by this factor
+const WAVE_MINIMUM_SPAWN_INTERVAL: f32 = 0.15;
+const WAVE_BANNER_DURATION: f32 = 2.0;
+const SPAWN_THEME_DURATION: f32 = 3.0; //seconds one bubble type dominates the spawns
+const SPAWN_THEME_MIN_GAP: f32 = 8.0; //seconds of normal mixed spawning between two themes
+const SPAWN_THEME_MAX_GAP: f32 = 16.0;
+const SPAWN_THEME_WEIGHT_MULTIPLIER: f32 = 10.0; //how much more likely the theme type is during a theme
+const SPAWN_THEME_TYPES: [BubbleType; 4] = [
+    BubbleType::Blood,
+    BubbleType::Dirt,
+    BubbleType::Leech,
+    BubbleType::Freeze,
+];
+const ADAPTIVE_MAX_BIAS: f32 = 0.35; //the adaptive difficulty never scales the spawn weights by more than this
+const RING_BURST_INTERVAL: f32 = 15.0; //seconds between two ring bursts
+const RING_BURST_COUNT: u32 = 16; //bubbles in one ring burst
+const RING_BURST_GAP_SIZE: u32 = 3; //neighbouring regular bubbles that form the escape gap
+const SPAWN_QUEUE_PER_TICK: usize = 4; //bubbles created from the spawn queue per fixed tick at most
+const SPAWN_QUEUE_MAX_PLAYER_MOVE: f32 = 3.0; //queued bubbles are dropped once the player moved this far away from where they were aimed
+const FINALE_START_TIME: f32 = 240.0; //seconds of survival after which the bubble hell finale starts
+const FINALE_DURATION: f32 = 30.0; //normal spawning pauses for this long and resumes afterwards
+const FINALE_SPIRAL_DURATION: f32 = 15.0; //the first part of the finale is a spiral, the rest are walls
+const FINALE_SPIRAL_INTERVAL: f32 = 0.15; //seconds between two spiral shots
+const FINALE_SPIRAL_ARMS: u32 = 3;
+const FINALE_SPIRAL_TURN_SPEED: f32 = 1.2; //radians per second the spiral turns
+const FINALE_WALL_INTERVAL: f32 = 1.6; //seconds between two walls
+const FINALE_WALL_LENGTH: u32 = 11; //bubbles in one wall
+const FINALE_WALL_SPACING: f32 = BUBBLE_RADIUS * 1.6; //distance between neighbouring bubbles in a wall
+const FINALE_WALL_TURN: f32 = 2.4; //radians each wall comes from further around than the last one
+const FINALE_REGULAR_EVERY: u32 = 3; //every third finale bubble is a regular one
+const FINALE_MAX_BUBBLES: usize = 150; //the finale holds off while this many bubbles are out
+const OFF_PLATEAU_BORDER_WIDTH: f32 = 12.0; //pixels of the red border shown while off the plateau
+const OFF_PLATEAU_PULSE_FREQUENCY: f32 = 2.0; //pulses per second of the border
+const OFF_PLATEAU_BORDER_MIN_ALPHA: f32 = 0.2;
+const OFF_PLATEAU_BORDER_MAX_ALPHA: f32 = 0.7; //also the steady alpha when reduced motion is on
+const OFF_PLATEAU_ALARM_FREQUENCY: f32 = 880.0; //the alarm is a synthesized tone, so it can't be mistaken for any clip of the game
+const SPAWN_SOUND_VOLUME: f32 = 0.15; //spawn blips are quiet next to the hit sounds, scaled by the sfx volume on top
+const SPAWN_SOUND_SPEED_HARMFUL: f32 = 0.55; //the collect sound pitched down for harmful spawns
+const SPAWN_SOUND_SPEED_HARMLESS: f32 = 1.9; //and up for harmless ones
+const SPAWN_SOUND_MAX_CONCURRENT: usize = 3; //ring bursts spawn a lot at once, more blips than this are dropped
+const OFF_PLATEAU_ALARM_VOLUME: f32 = 0.3; //relative to the sound effect volume, it should stay subtle
+const TUTORIAL_SPAWN_INTERVAL: f32 = 1.0; //the tutorial spawns a lot slower than a real run
+const TUTORIAL_BUBBLES_PER_STEP: u32 = 5; //yellow bubbles to collect before the next step
+const TUTORIAL_BLOOD_CHANCE: f32 = 0.4; //share of blood bubbles once they are introduced
+const TUTORIAL_SKIP_KEY: KeyCode = KeyCode::Tab;
+const TRAINING_LEAVE_KEY: KeyCode = KeyCode::KeyM; //ends a training run and goes back to the main menu
+const BUBBLE_MERGING_ENABLED: bool = true; //overlapping regular bubbles merge, harmful bubbles pop regular ones
+const BUBBLE_MERGE_MAX_VALUE: f32 = 4.0; //a merged bubble is never worth more than this many regular bubbles
+const BUBBLE_MAX_RADIUS: f32 = BUBBLE_RADIUS * 2.0; //radius of a fully merged bubble (BUBBLE_RADIUS * sqrt(BUBBLE_MERGE_MAX_VALUE)), bigger than any unmerged type
+const BUBBLE_GRID_CELL_SIZE: f32 = BUBBLE_MAX_RADIUS * 2.0; //one cell fits the biggest bubble
+const BUBBLE_SPAWN_FADE_DURATION: f32 = 0.5; //bubbles grow in over this many seconds; set to 0.0 to pop them in at full size
+const BUBBLE_SPAWN_FADE_MIN_SCALE: f32 = 0.01; //fraction of the full size a fading bubble starts at
 const BUBBLE_EFFECT_OXYGEN_INCREASE: f32 = 2.0;
 const BUBBLE_EFFECT_OXYGEN_DECREASE_SMALL: f32 = 1.0;
 const BUBBLE_EFFECT_OXYGEN_DECREASE_BIG: f32 = 4.0;
 const BUBBLE_EFFECT_FREEZE_DURATION: f32 = 0.8;
-#[derive(Resource)]
-struct BubbleFreezeEffect {
-    time_remaining: f32,
+const BUBBLE_EFFECT_OXYGEN_DECREASE_MINE: f32 = 6.0;
+const BUBBLE_LIFETIME: f32 = 12.0; //bubbles disappear after this many seconds; crossing the whole spawn circle takes ~6.7s
+const BUBBLE_EFFECT_OXYGEN_DECREASE_BOMB: f32 = 2.0;
+const BOMB_BLAST_RADIUS: f32 = 2.5; //bubbles within this distance of an exploding bomb pop; bombs among them explode too
+const CHAIN_POINTS_PER_POP: u32 = 10; //each bubble in a chain is worth this times the chain length
+const NEAR_MISS_MARGIN: f32 = 0.4; //harmful bubbles passing this close without touching count as near misses
+const NEAR_MISS_POINTS: u32 = 5;
+const OXYGEN_BONUS_POINTS_PER_UNIT: f32 = 10.0; //oxygen left in the bonus reserve at game over turns into this many points per unit
+const LEVEL_UP_FLASH_DURATION: f32 = 0.6; //the level bar shows full for this long after a level up
+const AUDIO_LISTENER_EAR_GAP: f32 = 4.0; //wide ears on the camera, so hits left and right of the player pan clearly
+const RUMBLE_DURATION: f32 = 0.25; //seconds a gamepad rumbles after a harmful hit
+const HIT_STOP_DURATION: f32 = 0.08; //seconds the gameplay stands still after a blood or bomb hit
+const MINE_LIFETIME: f32 = 12.0; //mines that were not touched disappear after this many seconds
+const MINE_MINIMUM_PLAYER_DISTANCE: f32 = 1.5; //mines never spawn closer than this to the player
+const MINE_PULSE_FREQUENCY: f32 = 3.0; //how fast (radians per second) the mine light pulses
+const LEECH_DURATION: f32 = 5.0; //seconds a leech stays attached unless it is shaken off
+const LEECH_DRAIN_PER_SECOND: f32 = 0.6; //oxygen each attached leech drains per second
+const LEECH_SHAKE_OFF_REVERSALS: u32 = 6; //movement reversals needed to shake off the leeches
+const LEECH_SHAKE_OFF_WINDOW: f32 = 1.5; //seconds in which the reversals have to happen
+
+//timed effects the player picked up; each entry is the time the effect has left
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum EffectKind {
+    Freeze, //the player and the current stop moving
+    Grow,   //the safe zone is bigger, see PlateauSize
+    Rush,   //the player moves faster
+    Invert, //the plateau drains and the water around it is safe, see InvertArena
 }
-#[derive(Component)]
-struct BubbleHitSound;
 
-const GAME_OVER_SCREEN_DISTANCE: f32 = 1.2;
+impl EffectKind {
+    //how the bubble legend describes the effect
+    fn description(self) -> &'static str {
+        match self {
+            EffectKind::Freeze => "frozen",
+            EffectKind::Grow => "bigger safe zone",
+            EffectKind::Rush => "faster",
+            EffectKind::Invert => "inverted plateau",
+        }
+    }
+}
 
-const ASSET_SCALE: f32 = 0.3; //we scale all 3D models with this because of reasons
+#[derive(Resource, Default)]
+struct ActiveEffects(HashMap<EffectKind, f32>);
 
-#[derive(Resource)]
-struct BubbleHitAudioSource(Handle<AudioSource>);
+impl ActiveEffects {
+    fn is_active(&self, kind: EffectKind) -> bool {
+        self.0.contains_key(&kind)
+    }
 
-#[derive(Event)]
-struct GameOverEvent;
+    fn remaining(&self, kind: EffectKind) -> f32 {
+        self.0.get(&kind).copied().unwrap_or(0.0)
+    }
 
-#[derive(Event)]
-struct BubbleHitEvent {
-    bubble_type: BubbleType,
+    //starts the effect, or starts it over with the full duration when it is already active
+    fn refresh(&mut self, kind: EffectKind, duration: f32) {
+        self.0.insert(kind, duration);
+    }
 }
 
+#[derive(Component)]
+struct BubbleHitSound;
+
+const FREEZE_INDICATOR_SIZE: f32 = 48.0; //pixel size of the freeze indicator when the freeze just started
+
+//icy ring around the player while frozen
 #[derive(Resource)]
-struct IsGameOver(bool);
+struct FreezeHalo {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
 
 #[derive(Component)]
-struct Player;
+struct FreezeHaloRing;
 
+//ring on the screen that shrinks as the freeze runs out
 #[derive(Component)]
-struct Zeiger;
+struct FreezeIndicator;
 
-fn guage_quat() -> Quat {
-    let quat = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 0.0);
-    quat
-}
+const CURRENT_STRENGTH: f32 = 0.6; //drift speed the current adds to the player and bubbles
+const CURRENT_CHANGE_INTERVAL: f32 = 6.0; //the current picks a new direction every <change-interval> seconds
+const CURRENT_ALGAE_SWAY_ANGLE: f32 = 0.15; //how far (radians) the algae lean with the current
+
+const OXYGEN_TRAIL_PARTICLES_PER_DRAIN: f32 = 4.0; //particles emitted per second for each point of oxygen drained per second
+const OXYGEN_TRAIL_MAX_PARTICLES: usize = 40;
+const OXYGEN_TRAIL_PARTICLE_LIFETIME: f32 = 1.2;
+const OXYGEN_TRAIL_RISE_SPEED: f32 = 1.5;
+const OXYGEN_TRAIL_PARTICLE_RADIUS: f32 = 0.05;
+
+const SPAWN_PREVIEW_SIZE: f32 = 28.0; //pixel size of the ghost shown at the screen edge for off-screen spawns
+const SPAWN_PREVIEW_DURATION: f32 = 0.8;
+const SPAWN_PREVIEW_ALPHA: f32 = 0.45; //opacity of the ghost when it appears, it fades out from there
+const SPAWN_PREVIEW_EDGE_MARGIN: f32 = 8.0; //pixels between the ghost and the screen edge
+
+const TRAJECTORY_LOOKAHEAD: f32 = 1.5; //trajectory lines show where a bubble will be in this many seconds
+const TRAJECTORY_ASSIST_ALPHA: f32 = 0.25; //the assist lines are faint, the debug lines are drawn at full strength
+
+const FLOATING_TEXT_LIFETIME: f32 = 1.0;
+const FLOATING_TEXT_RISE_SPEED: f32 = 1.0;
+const FLOATING_NUMBER_MAX: usize = 5; //the oldest oxygen number makes room once this many are shown
+const FLOATING_NUMBER_HEIGHT: f32 = 0.8; //oxygen numbers start this far above the player
+
+const REDUCED_MOTION_MAX_PARTICLE_SPEED: f32 = 0.5; //particles and popups never move faster than this with reduced motion
+
+const GAME_OVER_SCREEN_DISTANCE: f32 = 1.2;
 
+const CAMERA_PITCH: f32 = 1.279; //radians below the horizon the camera looks down at the player (~73 degrees)
+const CAMERA_DISTANCE: f32 = 10.44; //default distance from the camera to the player
+const CAMERA_MIN_DISTANCE: f32 = 5.0; //closest the mouse wheel zooms in, still well above the player
+const CAMERA_MAX_DISTANCE: f32 = 18.0; //farthest the mouse wheel zooms out
+const CAMERA_ZOOM_STEP: f32 = 0.8; //distance change per line scrolled
+const MOUSE_WHEEL_PIXELS_PER_LINE: f32 = 100.0; //touchpads scroll in pixels, this turns them into lines
+const CAMERA_FOLLOW_STIFFNESS: f32 = 6.0; //how quickly the camera catches up with the player
+const CAMERA_LOOK_AHEAD_DISTANCE: f32 = 1.5; //how far ahead of a moving player the camera looks
+
+const MUSIC_CALM_FILE: &str = "Music.ogg";
+const MUSIC_INTENSE_FILE: &str = "Beaty Soundtrack.ogg"; //a separate track, not a stem of the calm loop, so the two are crossfaded and restarted together
+const MUSIC_LOW_OXYGEN_FRACTION: f32 = 0.4; //the intense loop starts fading in below this fraction of the tank
+const MUSIC_FULL_INTENSITY_TIME: f32 = 180.0; //seconds of survival after which the intense loop plays at full volume
+const MUSIC_CROSSFADE_SPEED: f32 = 0.5; //how quickly (volume per second) the loops follow the intensity
+
+const WINDOW_TITLE: &str = "Bubble Hell";
+const WINDOW_TITLE_UPDATE_INTERVAL: f32 = 1.0; //the title with the oxygen and time is refreshed this often
+const WINDOW_ICON_PATH: &str = "Regular Bubble.png";
+
+const ASSET_SCALE: f32 = 0.3; //we scale all 3D models with this because of reasons
+
+const UNDERWATER_DISTORTION_MIN_STRENGTH: f32 = 0.15; //distortion with a full tank
+const UNDERWATER_DISTORTION_MAX_STRENGTH: f32 = 1.0; //distortion with an empty tank
+const BUBBLE_MANIFEST_PATH: &str = "bubbles.ron"; //model and light color of every bubble type, in the asset folder
+const BUBBLE_ASSET_PREFIX: &str = "bubble:"; //AssetsLoadingGltf keys of bubble models are this plus the type name
+const BUBBLE_MODEL_LOAD_TIMEOUT: f32 = 10.0; //seconds after startup until bubble types still without a model get a plain sphere, failed loads get one right away
+const SETTINGS_FILE_PATH: &str = "settings.ron"; //ron file next to the executable
+
+const REPLAY_FILE_PATH: &str = "replay.txt"; //written on game over when started with --record-replay
+const EVENT_LOG_FILE_PATH: &str = "event_log.txt"; //written on game over when started with --event-log
+#[cfg(feature = "stats_file")]
+const STATS_FILE_PATH: &str = "game_stats.ron"; //GameStats for external tools, see write_stats_file
+#[cfg(feature = "stats_file")]
+const STATS_FILE_INTERVAL: f32 = 0.25; //seconds between two writes of the stats file
+const EVENT_LOG_DEATH_CAUSE_WINDOW: f32 = 3.0; //a harmful hit this many seconds before the end counts as the cause of death
+const BENCHMARK_BUBBLE_COUNT: usize = 400; //bubbles kept alive during a --benchmark run
+const BENCHMARK_DURATION: f32 = 20.0; //seconds of frame times measured before the benchmark quits
+const BENCHMARK_WARMUP: f32 = 2.0; //seconds after the bubbles are created that are not measured
+const BENCHMARK_SEED: u64 = 1; //the benchmark level and bubbles are the same on every run
+const REPLAY_FORMAT_VERSION: u32 = 28; //bump whenever a change makes older replays play back differently
+
+//sound that keeps playing until it is despawned, see stop_all_loops_on_game_over
 #[derive(Component)]
-struct Velocity(Vec2);
+struct LoopingSound;
 
+//the rumble after death, the only sound that survives the game over
 #[derive(Component)]
-struct Bubble {
-    bubble_type: BubbleType,
+struct GameOverSound;
+
+//the calm and the intense music loop; they play together and are crossfaded by update_music_layers
+#[derive(Resource)]
+struct MusicLayers {
+    calm: Entity,
+    intense: Entity,
+    intensity: f32,
+    started: bool,
 }
 
-#[derive(Component)]
-struct Environment;
+//hit sound of every bubble type, played at its own speed, see BubbleType::hit_sound_speed
+#[derive(Resource)]
+struct BubbleHitAudioSource(Handle<AudioSource>);
 
+//blip telling that a bubble was spawned, see play_spawn_sounds
 #[derive(Component)]
-struct OxygenLevel(f32);
+struct SpawnSound;
 
 #[derive(Resource)]
-struct BubbleSpawnTimer(Timer);
+struct WindowTitleTimer(Timer);
 
+//icon image that is handed to the window once it has loaded
 #[derive(Resource)]
-struct AssetsLoadingGltf(HashMap<String, Handle<Gltf>>);
+struct WindowIcon(Handle<Image>);
 
-#[derive(Debug, PartialEq, Eq, Hash)]
-//the derive above is needed so we can use the enum as a key in the HashMap
-//Debug is for logging
-enum BubbleType {
-    Regular, //Oxygen
-    Blood,   //Death
-    Dirt,
-    Freeze,
+#[derive(Event)]
+struct GameOverEvent;
+
+#[derive(Event)]
+struct BubbleHitEvent {
+    bubble_type: BubbleType,
+    value: f32,
+    position: Vec3,
 }
 
+//a bomb blast popped <length> bubbles (including bombs it set off) around <center>
+#[derive(Event)]
+struct ChainEvent {
+    length: u32,
+    center: Vec3,
+}
+
+#[derive(Resource, Default)]
+struct Score(u32);
+
+//points the oxygen left in the reserve was worth at game over, see convert_oxygen_to_score
+#[derive(Resource, Default)]
+struct OxygenBonus(u32);
+
+//reached by scoring, see Settings::level_thresholds; starts at 1
 #[derive(Resource)]
-struct BubbleModels(HashMap<BubbleType, Option<Handle<Scene>>>);
+struct Level(u32);
+
+impl Default for Level {
+    fn default() -> Self {
+        Level(1)
+    }
+}
 
+#[derive(Event)]
+struct LevelUpEvent {
+    level: u32,
+}
+
+//"X / Y" progress towards the next level and the bar below it
 #[derive(Component)]
-struct Background;
+struct LevelText;
 
 #[derive(Component)]
-struct Plateau;
+struct LevelBar {
+    flash: Timer,
+}
 
-fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugins(LogDiagnosticsPlugin::default())
-        .insert_resource(BubbleSpawnTimer(Timer::from_seconds(
-            BUBBLE_SPAWN_INTERVAL,
-            TimerMode::Repeating,
-        )))
-        .add_systems(Startup, setup)
-        .add_systems(
-            FixedUpdate,
-            (
-                bubble_spawns,
-                move_bubbles,
-                player_effects,
-                check_collisions,
-            )
-                .chain(),
-        )
-        .add_systems(
-            Update,
-            (
-                on_asset_loaded,
-                reduce_oxygen_level,
-                play_game_over_sound,
-                show_game_over_screen,
-                handle_bubble_hit,
-                run_bubble_freeze_timer,
-                clear_old_sounds,
-                enforce_plateau_limits,
-            ),
-        )
-        .add_event::<GameOverEvent>()
-        .add_event::<BubbleHitEvent>()
-        .run();
+//how many harmful bubbles were dodged by a narrow margin this run
+#[derive(Resource, Default)]
+struct NearMisses(u32);
+
+//leech bubble clinging to the player
+#[derive(Component)]
+struct Leeching {
+    timer: Timer,
 }
 
-fn on_asset_loaded(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    gltf_assets: Res<Assets<Gltf>>,
-    gltf_meshes: Res<Assets<GltfMesh>>,
-    assets_loading: ResMut<AssetsLoadingGltf>,
-    player_entity: Single<Entity, With<Player>>,
-    mut bubble_models: ResMut<BubbleModels>,
-) {
-    let assets_loading = assets_loading.into_inner();
-    if !assets_loading.0.is_empty() {
-        let mut processed_assets: HashSet<String> = HashSet::from([]);
+//movement reversals the player made recently, enough of them shake off all leeches
+#[derive(Resource, Default)]
+struct LeechShake {
+    reversals: u32,
+    window: Timer,
+    last_direction: Vec2,
+}
 
-        for gltf_handle in assets_loading.0.iter() {
-            if asset_server.is_loaded_with_dependencies(gltf_handle.1.id()) {
-                info!("handling loaded asset: {}", gltf_handle.0);
+//emergency button that clears the harmful bubbles around the player for a chunk of oxygen
+//pressed is the input of the last fixed update, held the one before, so a press only counts once
+#[derive(Resource)]
+struct PanicClear {
+    cooldown: Timer,
+    pressed: bool,
+    held: bool,
+}
 
-                let loaded_asset = gltf_assets.get(gltf_handle.1.id());
+impl Default for PanicClear {
+    fn default() -> Self {
+        //ready right away
+        let mut cooldown = Timer::from_seconds(PANIC_CLEAR_COOLDOWN, TimerMode::Once);
+        cooldown.tick(cooldown.duration());
+        PanicClear {
+            cooldown,
+            pressed: false,
+            held: false,
+        }
+    }
+}
 
-                if loaded_asset.is_some() {
-                    let gltf_asset = loaded_asset.unwrap();
+impl PanicClear {
+    //a clear is free in god mode, see GodMode
+    fn oxygen_cost(god_mode: bool) -> f32 {
+        if god_mode {
+            0.0
+        } else {
+            PANIC_CLEAR_OXYGEN_COST
+        }
+    }
+}
 
-                    let asset_name = gltf_handle.0.to_string();
-                    match asset_name.as_str() {
-                        "player_character" => {
-                            //create mesh and add as child of player entity
-                            let player_character_id = commands
-                                .spawn((
-                                    SceneRoot(gltf_asset.default_scene.clone().unwrap()),
-                                    Transform::from_scale(Vec3::splat(ASSET_SCALE)),
-                                    InheritedVisibility::VISIBLE,
-                                ))
-                                .id();
+//short burst of speed in the movement direction; bubbles along the way are popped
+#[derive(Resource)]
+struct Dash {
+    active: Timer,
+    cooldown: Timer,
+    direction: Vec2,
+    //where the player was before this tick's movement, for the swept collision test
+    segment_start: Vec3,
+}
 
-                            commands
-                                .entity(*player_entity)
-                                .add_child(player_character_id);
+impl Default for Dash {
+    fn default() -> Self {
+        let mut active = Timer::from_seconds(DASH_DURATION, TimerMode::Once);
+        active.tick(active.duration());
+        let mut cooldown = Timer::from_seconds(DASH_COOLDOWN, TimerMode::Once);
+        cooldown.tick(cooldown.duration());
+        Dash {
+            active,
+            cooldown,
+            direction: Vec2::ZERO,
+            segment_start: Vec3::ZERO,
+        }
+    }
+}
 
-                            //start animation
-                            //screw this; there is not reasonable example of how to get this going
-                            /*
-                            for mut player in &mut animation_players {
-                                let (graph, node_indices) =
-                                    AnimationGraph::from_clips(gltf_asset.animations.clone());
+impl Dash {
+    fn is_active(&self) -> bool {
+        !self.active.finished()
+    }
 
-                                player.play(node_indices[0]);
-                            }
-                             */
-                        }
+    //a dash is free in god mode, see GodMode
+    fn oxygen_cost(god_mode: bool) -> f32 {
+        if god_mode {
+            0.0
+        } else {
+            DASH_OXYGEN_COST
+        }
+    }
 
-                        "alge" => {
-                            let mut rng = rand::thread_rng();
-                            let mut number_of_plants_to_spawn =
-                                rng.gen_range(PLATEAU_MINIMUM_PLANTS..PLATEAU_MAXIMUM_PLANTS);
-                            while number_of_plants_to_spawn > 0 {
-                                let random_rotation = rng.gen::<f32>();
-                                let random_distances =
-                                    Vec2::from([rng.gen::<f32>(), rng.gen::<f32>()]);
-                                let rotation_vector = Rot2::degrees(random_rotation * 360.0);
-
-                                let transform =
-                                    Transform::from_matrix(Mat4::from_scale_rotation_translation(
-                                        Vec3::splat(ASSET_SCALE),
-                                        Quat::from_euler(
-                                            EulerRot::XYZ,
-                                            rng.gen::<f32>() * PI / 0.1,
-                                            rng.gen::<f32>() * PI / 0.3,
-                                            rng.gen::<f32>() * PI / 0.1,
-                                        ),
-                                        Vec3::from([
-                                            rotation_vector.cos
-                                                * random_distances.x
-                                                * PLATEAU_RADIUS,
-                                            0.0_f32, //do not change y unless intentionally letting it hover
-                                            rotation_vector.sin
-                                                * random_distances.y
-                                                * PLATEAU_RADIUS,
-                                        ]),
-                                    ));
-
-                                commands.spawn((
-                                    Environment,
-                                    SceneRoot(gltf_asset.default_scene.clone().unwrap()),
-                                    transform,
-                                ));
+    fn can_dash(&self, oxygen_level: f32, god_mode: bool) -> bool {
+        self.cooldown.finished() && oxygen_level >= Dash::oxygen_cost(god_mode)
+    }
+}
 
-                                number_of_plants_to_spawn -= 1;
-                            }
-                        }
+//movement input of the last fixed update, after replay playback was applied
+#[derive(Resource, Default)]
+struct MovementInput(Vec2);
 
-                        "sand" => {
-                            commands.spawn((
-                                Background,
-                                SceneRoot(gltf_asset.default_scene.clone().unwrap()),
-                                Transform::from_translation(Vec3::splat(0.0_f32))
-                                    .with_scale(Vec3::splat(ASSET_SCALE)),
-                            ));
-                        }
+//how fast the player moved in the last fixed update, in units per second on the x-z-plane
+#[derive(Resource, Default)]
+struct PlayerVelocity(Vec2);
 
-                        "plateau" => {
-                            commands.spawn((
-                                Plateau,
-                                Transform::from_translation(Vec3::splat(0.0_f32))
-                                    .with_scale(Vec3::splat(ASSET_SCALE)),
-                                SceneRoot(gltf_asset.default_scene.clone().unwrap()),
-                            ));
-                        }
+//a bubble that already counted as a near miss, so it isn't counted again on later frames
+#[derive(Component)]
+struct NearMissCounted;
 
-                        "bubble_rot" => {
-                            bubble_models
-                                .0
-                                .insert(BubbleType::Blood, gltf_asset.default_scene.clone());
-                        }
+//bubbles come in waves with a fixed budget and a rest in between
+#[derive(Resource)]
+struct Wave {
+    number: u32,
+    bubbles_remaining: u32,
+    rest_timer: Timer,
+    resting: bool, //set once the budget ran out and the wave was announced as cleared
+}
 
-                        "bubble_dirt" => {
-                            bubble_models
-                                .0
-                                .insert(BubbleType::Dirt, gltf_asset.default_scene.clone());
-                        }
+impl Default for Wave {
+    fn default() -> Self {
+        Wave {
+            number: 0,
+            bubbles_remaining: 0,
+            rest_timer: Timer::from_seconds(WAVE_FIRST_DELAY, TimerMode::Once),
+            resting: true,
+        }
+    }
+}
 
-                        "bubble_freeze" => {
-                            bubble_models
-                                .0
-                                .insert(BubbleType::Freeze, gltf_asset.default_scene.clone());
-                        }
+//tunables that make a run harder the longer it lasts
+#[derive(Resource)]
+struct Difficulty {
+    bubble_speed: f32,
+    bubble_speed_ramp: f32,
+    bubble_max_speed: f32,
+}
 
-                        "bubble_regular" => {
-                            bubble_models
-                                .0
-                                .insert(BubbleType::Regular, gltf_asset.default_scene.clone());
-                        }
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty {
+            bubble_speed: BUBBLE_MOVEMENT_SPEED,
+            bubble_speed_ramp: BUBBLE_MOVEMENT_SPEED_RAMP,
+            bubble_max_speed: BUBBLE_MOVEMENT_MAX_SPEED,
+        }
+    }
+}
 
-                        "gauge" => {
-                            let empty_gltf = gltf_meshes
-                                .get(&gltf_asset.named_meshes["Gauge_empty"])
-                                .unwrap();
-                            let empty_prim = &empty_gltf.primitives[0];
-                            let gauge_empty_mesh = empty_prim.mesh.clone();
-                            let gauge_empty_material = empty_prim.material.clone().unwrap();
+impl Difficulty {
+    //speed for bubbles spawned now; bubbles already out keep the speed they were spawned with
+    fn bubble_speed(&self, survival_time: f32) -> f32 {
+        (self.bubble_speed + self.bubble_speed_ramp * survival_time).min(self.bubble_max_speed)
+    }
+}
 
-                            let zeiger_mesh = gltf_meshes.get(&gltf_asset.meshes[1]).unwrap();
-                            let zeiger_prim = &zeiger_mesh.primitives[0];
-                            let zeiger_mesh = zeiger_prim.mesh.clone();
-                            let zeiger_material = zeiger_prim.material.clone().unwrap();
-                            let gauge_id = commands
-                                .spawn((
-                                    Transform::from_xyz(0.0, 8.0, 2.8)
-                                        .with_rotation(guage_quat())
-                                        .with_scale(Vec3::ONE * 0.5),
-                                    Mesh3d(gauge_empty_mesh),
-                                    MeshMaterial3d(gauge_empty_material),
-                                ))
-                                .id();
-                            let zeiger_id = commands
-                                .spawn((
-                                    Transform::from_xyz(0.0, 8.0, 2.8)
-                                        .with_rotation(guage_quat())
-                                        .with_scale(Vec3::ONE * 0.5),
-                                    Mesh3d(zeiger_mesh),
-                                    MeshMaterial3d(zeiger_material),
-                                    Zeiger,
-                                ))
-                                .id();
-                            commands.entity(*player_entity).add_child(gauge_id);
-                            commands.entity(*player_entity).add_child(zeiger_id);
-                        }
+//the camera looks at the player at an angle, so bubbles spawning behind it stay out of view for
+//longer; part of the spawns are moved into the arc in front of the camera
+//it also remembers the recent spawn angles (with the survival time they were used at), so
+//bubbles spawned close together in time don't start on top of each other
+#[derive(Resource)]
+struct SpawnArcBias {
+    fraction: f32,
+    arc_width: f32,
+    recent_angles: Vec<(f32, f32)>,
+}
 
-                        _ => warn!("asset name was mepty"),
-                    };
+impl Default for SpawnArcBias {
+    fn default() -> Self {
+        SpawnArcBias {
+            fraction: SPAWN_ARC_BIAS_FRACTION,
+            arc_width: SPAWN_ARC_WIDTH,
+            recent_angles: Vec::new(),
+        }
+    }
+}
 
-                    info!("asset {} spawned", gltf_handle.0);
-                    processed_assets.insert(asset_name);
-                } else {
-                    warn!("asset {} was none", gltf_handle.0);
-                }
+impl SpawnArcBias {
+    //angle (radians, on the x-z-plane) on the spawn circle around the player that keeps
+    //SPAWN_MIN_ANGLE_SEPARATION to the recent spawns if a few tries allow it
+    fn roll_angle(&mut self, rng: &mut impl Rng, survival_time: f32) -> f32 {
+        self.recent_angles
+            .retain(|(_, time)| survival_time - time < SPAWN_ANGLE_MEMORY);
+        let mut angle = self.roll_any_angle(rng);
+        for _ in 0..SPAWN_ANGLE_MAX_RESAMPLES {
+            let too_close = self.recent_angles.iter().any(|(recent_angle, _)| {
+                let difference = (angle - recent_angle + PI).rem_euclid(2.0 * PI) - PI;
+                difference.abs() < SPAWN_MIN_ANGLE_SEPARATION
+            });
+            if !too_close {
+                break;
             }
+            angle = self.roll_any_angle(rng);
         }
+        self.remember(angle, survival_time);
+        angle
+    }
 
-        for gltf_handle in processed_assets {
-            assets_loading.0.remove(&gltf_handle);
-            info!(
-                "asset {} processed and removed from loading set",
-                gltf_handle
-            );
+    //for spawners that pick their own angles, so the random spawns keep away from them
+    fn remember(&mut self, angle: f32, survival_time: f32) {
+        self.recent_angles.push((angle, survival_time));
+    }
+
+    fn roll_any_angle(&self, rng: &mut impl Rng) -> f32 {
+        //the camera only ever moves, it never turns, so its setup rotation is all we need
+        let forward = camera_local_transform(CAMERA_DISTANCE)
+            .forward()
+            .as_vec3()
+            .xz()
+            .normalize_or(Vec2::NEG_Y);
+        if rng.gen::<f32>() < self.fraction {
+            forward.y.atan2(forward.x) + (rng.gen::<f32>() - 0.5) * self.arc_width
+        } else {
+            rng.gen::<f32>() * 2.0 * PI
         }
     }
 }
 
-fn play_game_over_sound(
-    asset_server: Res<AssetServer>,
-    mut game_over_event_reader: EventReader<GameOverEvent>,
-    mut commands: Commands,
-    audio_players: Query<Entity, With<AudioPlayer>>,
-) {
+//scripted barrage late in a run; it replaces the normal spawns while it lasts, see finale_spawner
+#[derive(Resource)]
+struct Finale {
+    elapsed: f32,
+    shot_timer: Timer,
+    shots: u32,
+}
+
+impl Default for Finale {
+    fn default() -> Self {
+        Finale {
+            elapsed: 0.0,
+            shot_timer: Timer::from_seconds(FINALE_SPIRAL_INTERVAL, TimerMode::Repeating),
+            shots: 0,
+        }
+    }
+}
+
+impl Finale {
+    fn is_active(&self, survival_time: f32) -> bool {
+        survival_time >= FINALE_START_TIME && self.elapsed < FINALE_DURATION
+    }
+}
+
+//whether the player is currently paying the off-plateau penalty, set by reduce_oxygen_level
+#[derive(Resource, Default, PartialEq)]
+struct OffPlateau(bool);
+
+//red screen border that pulses while the player is off the plateau
+#[derive(Component)]
+struct OffPlateauBorder;
+
+#[derive(Component)]
+struct OffPlateauAlarm;
+
+//the beats of the first-run tutorial, in order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TutorialStep {
+    CollectOxygen,
+    AvoidBlood,
+    StayNearCenter,
+}
+
+impl TutorialStep {
+    fn prompt(self) -> &'static str {
+        match self {
+            TutorialStep::CollectOxygen => "Collect yellow bubbles for oxygen",
+            TutorialStep::AvoidBlood => "Avoid red!",
+            TutorialStep::StayNearCenter => "Stay near the center, the water out there drains you",
+        }
+    }
+
+    fn next(self) -> Option<TutorialStep> {
+        match self {
+            TutorialStep::CollectOxygen => Some(TutorialStep::AvoidBlood),
+            TutorialStep::AvoidBlood => Some(TutorialStep::StayNearCenter),
+            TutorialStep::StayNearCenter => None,
+        }
+    }
+}
+
+//scripted start of the very first run; while a step is active only the tutorial spawns bubbles
+//and the step moves on after enough yellow bubbles were collected, see advance_tutorial
+#[derive(Resource, Default)]
+struct Tutorial {
+    step: Option<TutorialStep>,
+    collected: u32,
+    spawn_timer: Timer,
+    //replays do not know about the tutorial, so a run that had one is not saved
+    played: bool,
+}
+
+impl Tutorial {
+    //only normal runs that are played live get the tutorial, and only until it was finished once;
+    //skip is for replays, the benchmark and training runs
+    fn for_run(settings: &Settings, game_mode: GameMode, skip: bool) -> Self {
+        if settings.tutorial_completed || game_mode != GameMode::Normal || skip {
+            return Tutorial::default();
+        }
+        Tutorial {
+            step: Some(TutorialStep::CollectOxygen),
+            collected: 0,
+            spawn_timer: Timer::from_seconds(TUTORIAL_SPAWN_INTERVAL, TimerMode::Repeating),
+            played: true,
+        }
+    }
+}
+
+//shows the prompt of the current tutorial step
+#[derive(Component)]
+struct TutorialText;
+
+//how much more likely harmful bubbles are; the adaptive part is between -ADAPTIVE_MAX_BIAS and
+//ADAPTIVE_MAX_BIAS, positive while the player has lots of oxygen and negative when they are about
+//to run out, and the difficulty preset adds its own on top
+#[derive(Resource, Default)]
+struct SpawnBias(f32);
+
+//whether the spawns adapt to the oxygen left in this run; taken from the setting when the run
+//starts, so F10 only changes the next run and a replay header always matches the whole run
+#[derive(Resource, Default)]
+struct AdaptiveDifficulty(bool);
+
+//the starting oxygen chosen for this run before the preset changes it, kept for the same reason
+#[derive(Resource)]
+struct StartOxygen(f32);
+
+impl Default for StartOxygen {
+    fn default() -> Self {
+        StartOxygen(PLAYER_OXYGEN_START_SUPPLY)
+    }
+}
+
+//a bubble that was rolled but not created yet
+struct PendingSpawn {
+    bubble_type: BubbleType,
+    location: Vec3,
+    velocity: Vec2,
+    player_translation: Vec3, //where the player was when the bubble was queued
+    waited: f32,              //seconds in the queue, the bubble is moved ahead by this much
+}
+
+//the spawners queue their bubbles here and drain_spawn_queue creates at most SPAWN_QUEUE_PER_TICK
+//of them per tick, so a ring burst or a finale wall does not create all its models, lights and
+//shadows in one frame; counting ticks instead of frames keeps replays in sync
+#[derive(Resource, Default)]
+struct SpawnQueue(std::collections::VecDeque<PendingSpawn>);
+
+impl SpawnQueue {
+    fn push(
+        &mut self,
+        bubble_type: BubbleType,
+        location: Vec3,
+        velocity: Vec2,
+        player_translation: Vec3,
+    ) {
+        self.0.push_back(PendingSpawn {
+            bubble_type,
+            location,
+            velocity,
+            player_translation,
+            waited: 0.0,
+        });
+    }
+}
+
+//periodic ring of bubbles spawned all around the player at once
+#[derive(Resource)]
+struct RingBurst {
+    interval: Timer,
+    count: u32,
+}
+
+impl Default for RingBurst {
+    fn default() -> Self {
+        RingBurst {
+            interval: Timer::from_seconds(RING_BURST_INTERVAL, TimerMode::Repeating),
+            count: RING_BURST_COUNT,
+        }
+    }
+}
+
+#[derive(Event)]
+struct WaveStartedEvent {
+    number: u32,
+}
+
+#[derive(Event)]
+struct WaveClearedEvent {
+    number: u32,
+}
+
+//a type that briefly dominates the spawns, with gaps of normal spawning in between
+//the timer runs for the theme while there is one and for the gap otherwise
+#[derive(Resource)]
+struct SpawnTheme {
+    current: Option<BubbleType>,
+    timer: Timer,
+}
+
+impl Default for SpawnTheme {
+    fn default() -> Self {
+        SpawnTheme {
+            current: None,
+            timer: Timer::from_seconds(SPAWN_THEME_MAX_GAP, TimerMode::Once),
+        }
+    }
+}
+
+#[derive(Event)]
+struct SpawnThemeStartedEvent {
+    bubble_type: BubbleType,
+}
+
+#[derive(Component)]
+struct WaveBanner {
+    timer: Timer,
+}
+
+//faint ghost at the screen edge in the direction of a bubble that spawned off screen
+#[derive(Component)]
+struct SpawnPreview {
+    lifetime: Timer,
+}
+
+//ui text that follows a point in the world, rises and fades out
+#[derive(Component)]
+struct FloatingText {
+    world_position: Vec3,
+    lifetime: Timer,
+}
+
+//a floating text with the oxygen a hit gave or took, see spawn_floating_number
+#[derive(Component)]
+struct FloatingNumber;
+
+#[derive(Resource, Default)]
+struct IsGameOver(bool);
+
+//live numbers for external tools like overlays, refreshed every frame by collect_stats; with the
+//stats_file feature they are also written to STATS_FILE_PATH a few times per second
+//the modes are their names, so the file does not change when the enums are reordered
+#[derive(Resource, Serialize, Default)]
+pub struct GameStats {
+    pub oxygen: f32,
+    pub bonus_reserve: f32,
+    pub survival_time: f32,
+    pub score: u32,
+    pub active_bubbles: usize,
+    pub bubble_speed: f32,
+    pub game_mode: &'static str,
+    pub difficulty: &'static str,
+    pub game_over: bool,
+}
+
+//start a new run in the same mode once the game is over
+#[derive(Event)]
+struct RestartEvent {
+    regenerate_level: bool,
+}
+
+//throw away the current plant layout and grow a new one from <seed>
+#[derive(Event)]
+struct RegenerateLevelEvent {
+    seed: u64,
+}
+
+//kept after loading so the plants can be respawned without loading the gltf again
+#[derive(Resource)]
+struct AlgaeScene(Handle<Scene>);
+
+#[derive(Component)]
+struct Player;
+
+//tunable player values; the collision radius is independent of the (scaled) model
+#[derive(Resource)]
+struct PlayerStats {
+    radius: f32,
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        PlayerStats {
+            radius: PLAYER_RADIUS,
+        }
+    }
+}
+
+//keys that are not about moving the player
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+struct GeneralBindings {
+    quit: KeyCode,
+}
+
+impl Default for GeneralBindings {
+    fn default() -> Self {
+        GeneralBindings {
+            quit: KeyCode::Escape,
+        }
+    }
+}
+
+//shown after the quit key was pressed during a run; pressing it again quits
+#[derive(Component)]
+struct QuitPrompt;
+
+//keys used to move the player
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+struct MovementBindings {
+    up: KeyCode,
+    down: KeyCode,
+    left: KeyCode,
+    right: KeyCode,
+    dash: KeyCode,
+    panic_clear: KeyCode,
+}
+
+impl Default for MovementBindings {
+    fn default() -> Self {
+        MovementBindings {
+            up: KeyCode::KeyE,
+            down: KeyCode::KeyD,
+            left: KeyCode::KeyS,
+            right: KeyCode::KeyF,
+            dash: KeyCode::Space,
+            panic_clear: KeyCode::KeyA,
+        }
+    }
+}
+
+#[derive(Component)]
+struct ControlsHint;
+
+//one row per bubble type with its color and what it does, see update_bubble_legend
+#[derive(Component)]
+struct BubbleLegend;
+
+//debug builds only: F6 shows the player hitbox
+#[derive(Resource, Default)]
+struct ShowHitbox(bool);
+
+//debug builds only: Shift+T draws bright trajectory lines for every bubble
+#[derive(Resource, Default)]
+struct ShowTrajectories(bool);
+
+//started with --benchmark: a fixed load of bubbles without oxygen drain (god mode) and a log of
+//the frame times, so performance changes can be compared between commits
+#[derive(Resource)]
+struct Benchmark {
+    frame_times: Vec<f32>,
+    warmup: Timer,
+    timer: Timer,
+    rng: StdRng,
+}
+
+impl Default for Benchmark {
+    fn default() -> Self {
+        Benchmark {
+            frame_times: Vec::new(),
+            warmup: Timer::from_seconds(BENCHMARK_WARMUP, TimerMode::Once),
+            timer: Timer::from_seconds(BENCHMARK_DURATION, TimerMode::Once),
+            rng: StdRng::seed_from_u64(BENCHMARK_SEED),
+        }
+    }
+}
+
+//testing aid, debug builds only (F12 or the console) and on for --benchmark: while on, nothing costs oxygen
+//collecting bubbles and their other effects still work
+#[derive(Resource, Default)]
+struct GodMode(bool);
+
+//set for the rest of the run as soon as god mode is on for a single tick, so turning it off right
+//before dying does not make the time count, see record_best_survival_time
+#[derive(Resource, Default)]
+struct GodModeUsed(bool);
+
+//picked in the main menu: only this bubble type spawns and nothing costs oxygen (see start_training),
+//so a single hazard can be practiced; ring bursts, the finale and density_fill are off
+#[derive(Resource, Default)]
+struct TrainingType(Option<BubbleType>);
+
+impl TrainingType {
+    //off, then every bubble type in turn, then off again
+    fn next(&self) -> Self {
+        TrainingType(match self.0 {
+            None => BubbleType::ALL.first().copied(),
+            Some(bubble_type) => BubbleType::ALL
+                .iter()
+                .position(|other| *other == bubble_type)
+                .and_then(|index| BubbleType::ALL.get(index + 1))
+                .copied(),
+        })
+    }
+}
+
+//debug builds only: F5 shows details about the bubble closest to the player
+#[derive(Component)]
+struct BubbleInspectorText;
+
+//debug builds only: C draws the circle new bubbles spawn on, this shows its radius next to it
+#[derive(Component)]
+struct SpawnCircleLabel;
+
+//the visible character model; only this turns with movement so the camera and gauge stay put
+#[derive(Component)]
+struct PlayerModel;
+
+#[derive(Component)]
+struct Zeiger;
+
+//the oxygen the gauge and the player model show; it eases towards the real OxygenLevel, so hits
+//and pickups don't make them jump. nothing in the gameplay reads it
+#[derive(Resource)]
+struct DisplayedOxygen(f32);
+
+impl Default for DisplayedOxygen {
+    fn default() -> Self {
+        DisplayedOxygen(PLAYER_OXYGEN_START_SUPPLY)
+    }
+}
+
+//spot light above the player that points where the player is heading
+#[derive(Component)]
+struct Flashlight;
+
+//everything the player can change, saved to SETTINGS_FILE_PATH whenever it changes
+//missing fields fall back to their defaults, so older settings files keep working
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+struct Settings {
+    fullscreen: bool,
+    color_palette: ColorPalette,
+    rumble: bool,              //F7: gamepad rumble on harmful hits
+    controls_hint: bool,       //F1: the controls hint in the bottom right corner
+    bubble_legend: bool,       //L: the bubble colors and what they do, on the left
+    sfx_volume: f32,           //volume of the sound effects, 1.0 is full volume
+    spatial_audio: bool,       //hit sounds pan towards where the bubble was hit
+    adaptive_difficulty: bool, //F10: nudges the spawns based on the oxygen left
+    reduced_motion: bool,      //F9: turns off motion heavy effects without touching the gameplay
+    trajectory_assist: bool,   //T: faint lines showing where each bubble is heading
+    spawn_preview: bool,       //ghosts at the screen edge for bubbles spawning off screen
+    tutorial_completed: bool,
+    camera_distance: f32, //mouse wheel: how far the camera is from the player
+    new_layout_each_run: bool, //F2: grow a new plant layout whenever a run is restarted
+    underwater_distortion: bool, //F8: the screen wobble, can be turned off for performance or motion sickness
+    vsync: VsyncMode,            //V: how frames are handed to the screen
+    bubble_style: BubbleStyle,   //O: models with lights or plain orbs
+    //bubbles off screen move less often, see move_bubbles; it changes the gameplay, so runs with
+    //it on don't set best times; only set in the settings file
+    offscreen_throttling: bool,
+    //the short stop of the gameplay on heavy hits, see HitStop; only set in the settings file
+    hit_stop: bool,
+    //keeps a number of bubbles around that grows with the run, see fill_bubble_density; only set
+    //in the settings file
+    density_fill: bool,
+    //no new bubbles appear while the player is frozen, so the freeze is a real pause; only set in
+    //the settings file
+    freeze_pauses_spawns: bool,
+    //frames per second the game is held to, 0 for no cap; only set in the settings file
+    max_fps: u32,
+    arena: ArenaShape,
+    movement_bindings: MovementBindings,
+    general_bindings: GeneralBindings,
+    //score needed for each level after the first, in increasing order
+    level_thresholds: Vec<u32>,
+    playfield: PlayfieldMode,     //chosen in the main menu
+    difficulty: DifficultyPreset, //chosen in the main menu
+    //oxygen a normal run starts with, chosen in the main menu; what does not fit into the tank
+    //goes into the bonus reserve, see DifficultyPreset::start_oxygen
+    start_oxygen: f32,
+    //best survival time for each kind of run, so zen runs, open playfield runs or runs with another
+    //starting oxygen are not compared with normal ones, see Settings::best_time_key
+    best_times: HashMap<(GameMode, PlayfieldMode, DifficultyPreset, u32), f32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            fullscreen: false,
+            color_palette: ColorPalette::default(),
+            rumble: true,
+            controls_hint: true,
+            bubble_legend: true,
+            sfx_volume: 1.0,
+            spatial_audio: true,
+            adaptive_difficulty: true,
+            reduced_motion: false,
+            trajectory_assist: false,
+            spawn_preview: true,
+            tutorial_completed: false,
+            camera_distance: CAMERA_DISTANCE,
+            new_layout_each_run: false,
+            underwater_distortion: true,
+            vsync: VsyncMode::default(),
+            bubble_style: BubbleStyle::default(),
+            offscreen_throttling: false,
+            hit_stop: true,
+            density_fill: false,
+            freeze_pauses_spawns: true,
+            max_fps: 0,
+            arena: ArenaShape::default(),
+            movement_bindings: MovementBindings::default(),
+            general_bindings: GeneralBindings::default(),
+            level_thresholds: vec![50, 150, 300, 600, 1000, 1500],
+            playfield: PlayfieldMode::default(),
+            difficulty: DifficultyPreset::default(),
+            start_oxygen: PLAYER_OXYGEN_START_SUPPLY,
+            best_times: HashMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    //score range of the given level, None once the last threshold is passed
+    fn level_range(&self, level: u32) -> Option<(u32, u32)> {
+        let index = level as usize - 1;
+        let end = *self.level_thresholds.get(index)?;
+        let start = index
+            .checked_sub(1)
+            .map_or(0, |previous| self.level_thresholds[previous]);
+        Some((start, end))
+    }
+
+    //every motion heavy effect asks one of these instead of checking reduced_motion on its own
+    fn distortion_enabled(&self) -> bool {
+        self.underwater_distortion && !self.reduced_motion
+    }
+
+    //a hand edited settings file must not put the camera inside the player
+    fn camera_distance(&self) -> f32 {
+        self.camera_distance
+            .clamp(CAMERA_MIN_DISTANCE, CAMERA_MAX_DISTANCE)
+    }
+
+    //a hand edited settings file must not start a run with an empty tank
+    fn start_oxygen(&self) -> f32 {
+        self.start_oxygen.clamp(START_OXYGEN_MIN, START_OXYGEN_MAX)
+    }
+
+    //the starting oxygen is chosen in steps of whole units, so it is kept as one in the key
+    fn best_time_key(
+        game_mode: GameMode,
+        playfield: PlayfieldMode,
+        difficulty_preset: DifficultyPreset,
+        start_oxygen: f32,
+    ) -> (GameMode, PlayfieldMode, DifficultyPreset, u32) {
+        (
+            game_mode,
+            playfield,
+            difficulty_preset,
+            start_oxygen.round() as u32,
+        )
+    }
+
+    //None until a run of this kind was finished
+    fn best_survival_time(
+        &self,
+        game_mode: GameMode,
+        playfield: PlayfieldMode,
+        difficulty_preset: DifficultyPreset,
+        start_oxygen: f32,
+    ) -> Option<f32> {
+        self.best_times
+            .get(&Settings::best_time_key(
+                game_mode,
+                playfield,
+                difficulty_preset,
+                start_oxygen,
+            ))
+            .copied()
+    }
+
+    fn camera_motion_enabled(&self) -> bool {
+        !self.reduced_motion
+    }
+
+    fn hit_stop_enabled(&self) -> bool {
+        self.hit_stop && !self.reduced_motion
+    }
+
+    fn screen_pulse_enabled(&self) -> bool {
+        !self.reduced_motion
+    }
+
+    fn particle_speed(&self, speed: f32) -> f32 {
+        if self.reduced_motion {
+            speed.min(REDUCED_MOTION_MAX_PARTICLE_SPEED)
+        } else {
+            speed
+        }
+    }
+
+    //a missing or broken settings file is not fatal, we just start with the defaults
+    fn load() -> Self {
+        let contents = match std::fs::read_to_string(SETTINGS_FILE_PATH) {
+            Ok(contents) => contents,
+            Err(error) => {
+                info!("no settings loaded from {}: {}", SETTINGS_FILE_PATH, error);
+                return Settings::default();
+            }
+        };
+        ron::from_str::<Settings>(&contents).unwrap_or_else(|error| {
+            warn!(
+                "could not parse {}, using default settings: {}",
+                SETTINGS_FILE_PATH, error
+            );
+            Settings::default()
+        })
+    }
+
+    //writes to a temporary file first so a crash mid-write can't leave a half written file behind
+    fn save(&self) {
+        let contents = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(error) => {
+                warn!("could not serialize settings: {}", error);
+                return;
+            }
+        };
+        let temp_path = format!("{}.tmp", SETTINGS_FILE_PATH);
+        let result = std::fs::write(&temp_path, contents)
+            .and_then(|_| std::fs::rename(&temp_path, SETTINGS_FILE_PATH));
+        if let Err(error) = result {
+            warn!(
+                "could not save settings to {}: {}",
+                SETTINGS_FILE_PATH, error
+            );
+        }
+    }
+}
+
+fn window_mode(fullscreen: bool) -> WindowMode {
+    if fullscreen {
+        WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+    } else {
+        WindowMode::Windowed
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_replay_file(
+    seed: u64,
+    game_mode: GameMode,
+    playfield: PlayfieldMode,
+    difficulty_preset: DifficultyPreset,
+    start_oxygen: f32,
+    adaptive_difficulty: bool,
+    density_fill: bool,
+    freeze_pauses_spawns: bool,
+    arena_shape: &ArenaShape,
+    frames: &[ReplayFrame],
+) {
+    //the arena is written as ron on a single line
+    let arena = match ron::to_string(arena_shape) {
+        Ok(arena) => arena,
+        Err(error) => {
+            warn!("could not save replay: {}", error);
+            return;
+        }
+    };
+    let mut replay = format!(
+        "bubble_hell_replay {}\nseed {}\nmode {}\nplayfield {}\ndifficulty {}\nstart_oxygen {}\nadaptive {}\ndensity_fill {}\nfreeze_pauses_spawns {}\narena {}\n",
+        REPLAY_FORMAT_VERSION,
+        seed,
+        game_mode.name(),
+        playfield.name(),
+        difficulty_preset.name(),
+        start_oxygen,
+        adaptive_difficulty,
+        density_fill,
+        freeze_pauses_spawns,
+        arena
+    );
+    for frame in frames {
+        replay.push_str(&format!(
+            "{} {} {} {} {}\n",
+            frame.movement.x,
+            frame.movement.y,
+            frame.delta_secs,
+            frame.dash as u8,
+            frame.panic_clear as u8
+        ));
+    }
+
+    match std::fs::write(REPLAY_FILE_PATH, replay) {
+        Ok(()) => info!(
+            "replay with {} frames saved to {}",
+            frames.len(),
+            REPLAY_FILE_PATH
+        ),
+        Err(error) => warn!("could not save replay: {}", error),
+    }
+}
+
+//returns the seed and the player for a replay file, or why it could not be loaded
+fn load_replay_file(path: &str) -> Result<(u64, ReplayPlayer), String> {
+    let replay = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let mut lines = replay.lines();
+
+    let version = lines
+        .next()
+        .and_then(|line| line.strip_prefix("bubble_hell_replay "))
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or("not a replay file")?;
+    if version != REPLAY_FORMAT_VERSION {
+        return Err(format!(
+            "replay format version {} is not supported (expected {})",
+            version, REPLAY_FORMAT_VERSION
+        ));
+    }
+
+    let seed = lines
+        .next()
+        .and_then(|line| line.strip_prefix("seed "))
+        .and_then(|seed| seed.parse::<u64>().ok())
+        .ok_or("missing seed")?;
+    let game_mode = match lines.next().and_then(|line| line.strip_prefix("mode ")) {
+        Some("normal") => GameMode::Normal,
+        Some("zen") => GameMode::Zen,
+        Some("hardcore") => GameMode::Hardcore,
+        _ => return Err("missing or unknown game mode".into()),
+    };
+    let playfield = match lines
+        .next()
+        .and_then(|line| line.strip_prefix("playfield "))
+    {
+        Some("confined") => PlayfieldMode::Confined,
+        Some("open") => PlayfieldMode::Open,
+        _ => return Err("missing or unknown playfield".into()),
+    };
+    let difficulty_preset = match lines
+        .next()
+        .and_then(|line| line.strip_prefix("difficulty "))
+    {
+        Some("easy") => DifficultyPreset::Easy,
+        Some("normal") => DifficultyPreset::Normal,
+        Some("hard") => DifficultyPreset::Hard,
+        _ => return Err("missing or unknown difficulty".into()),
+    };
+    let start_oxygen = lines
+        .next()
+        .and_then(|line| line.strip_prefix("start_oxygen "))
+        .and_then(|start_oxygen| start_oxygen.parse::<f32>().ok())
+        .ok_or("missing start oxygen")?;
+    let adaptive_difficulty = lines
+        .next()
+        .and_then(|line| line.strip_prefix("adaptive "))
+        .and_then(|adaptive| adaptive.parse::<bool>().ok())
+        .ok_or("missing adaptive difficulty")?;
+    let density_fill = lines
+        .next()
+        .and_then(|line| line.strip_prefix("density_fill "))
+        .and_then(|density_fill| density_fill.parse::<bool>().ok())
+        .ok_or("missing density fill")?;
+    let freeze_pauses_spawns = lines
+        .next()
+        .and_then(|line| line.strip_prefix("freeze_pauses_spawns "))
+        .and_then(|freeze_pauses_spawns| freeze_pauses_spawns.parse::<bool>().ok())
+        .ok_or("missing freeze pauses spawns")?;
+    let arena = lines
+        .next()
+        .and_then(|line| line.strip_prefix("arena "))
+        .ok_or("missing arena")?;
+    let arena_shape = ron::from_str::<ArenaShape>(arena)
+        .map_err(|error| format!("bad arena '{}': {}", arena, error))?;
+
+    let mut frames = Vec::new();
+    for line in lines {
+        let values: Vec<f32> = line
+            .split_whitespace()
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|error| format!("bad frame '{}': {}", line, error))?;
+        if values.len() != 5 {
+            return Err(format!("bad frame '{}'", line));
+        }
+        frames.push(ReplayFrame {
+            movement: Vec2::new(values[0], values[1]),
+            delta_secs: values[2],
+            dash: values[3] != 0.0,
+            panic_clear: values[4] != 0.0,
+        });
+    }
+
+    Ok((
+        seed,
+        ReplayPlayer {
+            game_mode,
+            playfield,
+            difficulty_preset,
+            start_oxygen,
+            adaptive_difficulty,
+            density_fill,
+            freeze_pauses_spawns,
+            arena_shape,
+            frames,
+            next_frame: 0,
+        },
+    ))
+}
+
+fn guage_quat() -> Quat {
+    let quat = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 0.0);
+    quat
+}
+
+//the camera follows the player on its own instead of being parented to it
+//position is the smoothed follow position, so additive effects like shake can be applied on top
+//of it each frame without feeding back into the follow
+#[derive(Component)]
+struct CameraFollow {
+    position: Vec3,
+    look_ahead: Vec3,
+}
+
+//camera transform relative to the player, looking down at the player from behind
+//the up vector is -z (the top of the screen) so it is never parallel to the view direction
+//zooming only changes the distance, the angle is always the same
+fn camera_local_transform(distance: f32) -> Transform {
+    let offset = Vec3::new(
+        0.0,
+        CAMERA_PITCH.sin() * distance,
+        CAMERA_PITCH.cos() * distance,
+    );
+    let transform = Transform::from_translation(offset).looking_at(Vec3::ZERO, Vec3::NEG_Z);
+    debug_assert!(
+        transform.rotation.is_finite() && transform.rotation.is_normalized(),
+        "camera rotation must be a finite unit quaternion"
+    );
+    transform
+}
+
+#[derive(Component)]
+struct Velocity(Vec2);
+
+//dark disc on the sea floor below a bubble, so it is easier to tell where the bubble is compared
+//to the player; a child of the bubble, kept on the floor by update_bubble_shadows
+#[derive(Component)]
+struct BubbleShadow;
+
+//one piece of a BubbleSilhouette, a child of the bubble tinted like its orb
+#[derive(Component)]
+struct SilhouettePart;
+
+//where in its up and down bobbing a bubble is, in radians; differs per bubble so they don't bob in sync
+#[derive(Component)]
+struct BobPhase(f32);
+
+//movement an off screen bubble has not been given yet, see move_bubbles
+#[derive(Component, Default)]
+struct SkippedMovement(Vec2);
+
+#[derive(Component)]
+struct Bubble {
+    bubble_type: BubbleType,
+    value: f32, //how many bubbles this one is worth; grows when regular bubbles merge
+    base_radius: f32, //radius at a value of 1, set from BubbleType::radius at spawn
+}
+
+impl Bubble {
+    //merged bubbles grow with their value so the area roughly adds up
+    fn radius(&self) -> f32 {
+        self.base_radius * self.value.sqrt()
+    }
+}
+
+//safe area on the x-z-plane; leaving it drains oxygen faster
+//defaults to a circle the size of the plateau model
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum ArenaShape {
+    Circle { radius: f32 },
+    Square { half_extent: f32 },
+    Polygon { points: Vec<Vec2> },
+}
+
+impl Default for ArenaShape {
+    fn default() -> Self {
+        ArenaShape::Circle {
+            radius: PLATEAU_RADIUS,
+        }
+    }
+}
+
+impl ArenaShape {
+    fn point_inside_arena(&self, position: Vec2) -> bool {
+        match self {
+            ArenaShape::Circle { radius } => position.length_squared() <= powf(*radius, 2.0),
+            ArenaShape::Square { half_extent } => {
+                position.x.abs() <= *half_extent && position.y.abs() <= *half_extent
+            }
+            ArenaShape::Polygon { points } => {
+                //even-odd rule: count how many edges a ray towards +x crosses
+                let mut inside = false;
+                for (index, point) in points.iter().enumerate() {
+                    let previous = points[(index + points.len() - 1) % points.len()];
+                    if (point.y > position.y) != (previous.y > position.y) {
+                        let crossing_x = point.x
+                            + (position.y - point.y) / (previous.y - point.y)
+                                * (previous.x - point.x);
+                        if position.x < crossing_x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    //radius of a circle around the origin that contains the whole arena
+    fn bounding_radius(&self) -> f32 {
+        match self {
+            ArenaShape::Circle { radius } => *radius,
+            ArenaShape::Square { half_extent } => half_extent * 2.0_f32.sqrt(),
+            ArenaShape::Polygon { points } => points
+                .iter()
+                .map(|point| point.length())
+                .fold(0.0, f32::max),
+        }
+    }
+
+    //random position inside the arena, trying to keep some distance to the given position
+    fn random_point(&self, rng: &mut impl Rng, avoid: Vec2, min_distance: f32) -> Vec2 {
+        let mut point = None;
+        for _ in 0..16 {
+            // sqrt keeps the positions evenly spread over the area
+            let candidate = Rot2::degrees(rng.gen::<f32>() * 360.0)
+                * Vec2::X
+                * rng.gen::<f32>().sqrt()
+                * self.bounding_radius();
+            if !self.point_inside_arena(candidate) {
+                continue;
+            }
+            point = Some(candidate);
+            if candidate.distance(avoid) > min_distance {
+                break;
+            }
+        }
+        point.unwrap_or_else(|| self.interior_point())
+    }
+
+    //a point that is always inside the arena, for when random_point finds none
+    fn interior_point(&self) -> Vec2 {
+        let ArenaShape::Polygon { points } = self else {
+            return Vec2::ZERO;
+        };
+        let centroid = points.iter().sum::<Vec2>() / points.len() as f32;
+        if self.point_inside_arena(centroid) {
+            return centroid;
+        }
+        //a concave polygon can have its centroid outside, so cut it with a horizontal line
+        //through the widest gap between vertex heights, no vertex lies on that line
+        let mut heights: Vec<f32> = points.iter().map(|point| point.y).collect();
+        heights.sort_by(f32::total_cmp);
+        let y = heights
+            .windows(2)
+            .max_by(|a, b| (a[1] - a[0]).total_cmp(&(b[1] - b[0])))
+            .map_or(centroid.y, |pair| (pair[0] + pair[1]) / 2.0);
+        let mut crossings: Vec<f32> = Vec::new();
+        for (index, point) in points.iter().enumerate() {
+            let previous = points[(index + points.len() - 1) % points.len()];
+            if (point.y > y) != (previous.y > y) {
+                crossings.push(
+                    point.x + (y - point.y) / (previous.y - point.y) * (previous.x - point.x),
+                );
+            }
+        }
+        crossings.sort_by(f32::total_cmp);
+        //the stretch between the first two crossings is inside by the even-odd rule
+        match crossings[..] {
+            [first, second, ..] => Vec2::new((first + second) / 2.0, y),
+            _ => centroid,
+        }
+    }
+}
+
+//current size of the safe zone relative to ArenaShape; grow bubbles make it bigger for a while
+#[derive(Resource)]
+struct PlateauSize {
+    scale: f32,
+}
+
+impl Default for PlateauSize {
+    fn default() -> Self {
+        PlateauSize { scale: 1.0 }
+    }
+}
+
+//while set the plateau drains oxygen and the water around it is safe; follows EffectKind::Invert,
+//so it turns back on its own when the effect runs out
+#[derive(Resource, Default, PartialEq)]
+struct InvertArena(bool);
+
+//uniform grid over the x-z-plane, rebuilt every tick, so collision checks only look at nearby bubbles
+#[derive(Resource, Default)]
+struct BubbleGrid {
+    cells: HashMap<IVec2, Vec<Entity>>,
+}
+
+impl BubbleGrid {
+    fn cell_of(position: Vec3) -> IVec2 {
+        (position.xz() / BUBBLE_GRID_CELL_SIZE).floor().as_ivec2()
+    }
+
+    fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    fn insert(&mut self, entity: Entity, position: Vec3) {
+        self.cells
+            .entry(Self::cell_of(position))
+            .or_default()
+            .push(entity);
+    }
+
+    //all bubbles in cells touched by a circle around the position; callers still do the exact test
+    fn nearby(&self, position: Vec3, radius: f32) -> impl Iterator<Item = Entity> + '_ {
+        let min_cell = Self::cell_of(position - Vec3::splat(radius));
+        let max_cell = Self::cell_of(position + Vec3::splat(radius));
+        (min_cell.x..=max_cell.x)
+            .flat_map(move |x| (min_cell.y..=max_cell.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+impl BubbleType {
+    const ALL: [BubbleType; 15] = [
+        BubbleType::Regular,
+        BubbleType::Blood,
+        BubbleType::Dirt,
+        BubbleType::Freeze,
+        BubbleType::Mine,
+        BubbleType::Bomb,
+        BubbleType::Leech,
+        BubbleType::Grow,
+        BubbleType::Rush,
+        BubbleType::Invert,
+        BubbleType::Warp,
+        BubbleType::Purge,
+        BubbleType::Repel,
+        BubbleType::Thief,
+        BubbleType::Timed,
+    ];
+
+    fn from_name(name: &str) -> Option<BubbleType> {
+        BubbleType::ALL
+            .into_iter()
+            .find(|bubble_type| bubble_type.name() == name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            BubbleType::Regular => "regular",
+            BubbleType::Blood => "blood",
+            BubbleType::Dirt => "dirt",
+            BubbleType::Freeze => "freeze",
+            BubbleType::Mine => "mine",
+            BubbleType::Bomb => "bomb",
+            BubbleType::Leech => "leech",
+            BubbleType::Grow => "grow",
+            BubbleType::Rush => "rush",
+            BubbleType::Invert => "invert",
+            BubbleType::Warp => "warp",
+            BubbleType::Purge => "purge",
+            BubbleType::Repel => "repel",
+            BubbleType::Thief => "thief",
+            BubbleType::Timed => "timed",
+        }
+    }
+
+    //hit sounds are played faster or slower per type, so sharing a sound still tells them apart
+    fn hit_sound_speed(self) -> f32 {
+        match self {
+            BubbleType::Regular => 1.0,
+            BubbleType::Freeze => 1.4,
+            BubbleType::Dirt => 0.85,
+            BubbleType::Blood => 0.7,
+            BubbleType::Bomb => 0.65,
+            BubbleType::Mine => 0.55,
+            BubbleType::Leech => 0.75,
+            BubbleType::Grow => 1.2,
+            BubbleType::Rush => 1.6,
+            BubbleType::Invert => 0.9,
+            BubbleType::Warp => 1.4,
+            BubbleType::Purge => 0.8,
+            BubbleType::Repel => 1.1,
+            BubbleType::Thief => 0.95,
+            BubbleType::Timed => 1.2,
+        }
+    }
+
+    //size of a fresh bubble of this type, used for its scale and its collisions
+    fn radius(self) -> f32 {
+        match self {
+            BubbleType::Dirt | BubbleType::Mine => BUBBLE_RADIUS_BIG,
+            BubbleType::Freeze | BubbleType::Rush | BubbleType::Warp => BUBBLE_RADIUS_SMALL,
+            _ => BUBBLE_RADIUS,
+        }
+    }
+
+    fn is_harmful(self) -> bool {
+        matches!(
+            self,
+            BubbleType::Blood
+                | BubbleType::Dirt
+                | BubbleType::Mine
+                | BubbleType::Bomb
+                | BubbleType::Leech
+                | BubbleType::Thief
+        )
+    }
+}
+
+//drawn as an orb instead of a model, see BubbleStyle
+#[derive(Component)]
+struct BubbleOrb;
+
+//bubble that is still growing in after being spawned
+#[derive(Component)]
+struct SpawnFade {
+    timer: Timer,
+}
+
+//streaks left behind the player during a rush
+#[derive(Resource)]
+struct RushStreaks {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    timer: Timer,
+}
+
+#[derive(Component)]
+struct RushStreak {
+    lifetime: Timer,
+}
+
+//emits small bubbles rising off the player, faster when oxygen drains faster
+#[derive(Resource)]
+struct OxygenTrail {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    pending_particles: f32,
+}
+
+#[derive(Component)]
+struct OxygenTrailParticle {
+    lifetime: Timer,
+}
+
+#[derive(Component)]
+struct Environment;
+
+//rotation the algae were spawned with; the current sway is applied on top of it
+#[derive(Component)]
+struct AlgaeBaseRotation(Quat);
+
+//water current that slowly pushes the player and the bubbles around
+#[derive(Resource)]
+struct Current {
+    direction: Vec2,
+    strength: f32,
+}
+
+impl Default for Current {
+    fn default() -> Self {
+        Current {
+            direction: Vec2::X,
+            strength: CURRENT_STRENGTH,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct CurrentChangeTimer(Timer);
+
+impl Default for CurrentChangeTimer {
+    fn default() -> Self {
+        CurrentChangeTimer(Timer::from_seconds(
+            CURRENT_CHANGE_INTERVAL,
+            TimerMode::Repeating,
+        ))
+    }
+}
+
+//debug panel with live numbers for balancing the bubble spawns, toggled with F3
+#[derive(Component)]
+struct SpawnTuningPanel;
+
+#[derive(Component)]
+struct OxygenLevel(f32);
+
+#[derive(Resource)]
+struct BubbleSpawnTimer(Timer);
+
+impl Default for BubbleSpawnTimer {
+    fn default() -> Self {
+        BubbleSpawnTimer(Timer::from_seconds(
+            BUBBLE_SPAWN_INTERVAL,
+            TimerMode::Repeating,
+        ))
+    }
+}
+
+#[derive(Resource)]
+struct AssetsLoadingGltf(HashMap<String, Handle<Gltf>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+//the derive above is needed so we can use the enum as a key in the HashMap
+//Debug is for logging
+enum BubbleType {
+    Regular, //Oxygen
+    Blood,   //Death
+    Dirt,
+    Freeze,
+    Mine,   //parks on the plateau and explodes when touched
+    Bomb,   //pops every bubble around it when touched
+    Leech,  //clings to the player and drains oxygen until it falls off or is shaken off
+    Grow,   //makes the safe zone bigger for a while
+    Rush,   //speeds the player up for a while
+    Invert, //turns the plateau around for a while, the center drains and the outside is safe
+    Warp,   //moves the player to a random spot on the plateau
+    Purge,  //fills the tank completely but throws away the bonus reserve
+    Repel,  //pushes every bubble away from the player
+    Thief,  //chases regular bubbles and pops them before the player gets there
+    Timed, //goes off after a while, hurting the player only if they are close; collect it to defuse it
+}
+
+//relative chance of each bubble type being picked by the spawner
+const BUBBLE_SPAWN_WEIGHTS: [(BubbleType, u32); 15] = [
+    (BubbleType::Regular, 4),
+    (BubbleType::Blood, 4),
+    (BubbleType::Dirt, 4),
+    (BubbleType::Freeze, 4),
+    (BubbleType::Mine, 1),
+    (BubbleType::Bomb, 1),
+    (BubbleType::Leech, 2),
+    (BubbleType::Grow, 1),
+    (BubbleType::Rush, 1),
+    (BubbleType::Invert, 1),
+    (BubbleType::Warp, 1),
+    (BubbleType::Purge, 1),
+    (BubbleType::Repel, 1),
+    (BubbleType::Thief, 1),
+    (BubbleType::Timed, 1),
+];
+
+//harmful_bias scales the harmful weights up and the regular weight down, or the other way
+//around when it is negative, see SpawnBias; the theme type (see SpawnTheme) is a lot more likely
+fn roll_bubble_type(
+    rng: &mut impl Rng,
+    game_mode: GameMode,
+    harmful_bias: f32,
+    theme: Option<BubbleType>,
+) -> BubbleType {
+    let allowed_weights = BUBBLE_SPAWN_WEIGHTS
+        .into_iter()
+        .filter(|(bubble_type, _)| {
+            game_mode != GameMode::Zen
+                || matches!(bubble_type, BubbleType::Regular | BubbleType::Freeze)
+        })
+        .map(|(bubble_type, weight)| {
+            let scale = if bubble_type.is_harmful() {
+                1.0 + harmful_bias
+            } else if bubble_type == BubbleType::Regular {
+                1.0 - harmful_bias
+            } else {
+                1.0
+            };
+            let theme_scale = if theme == Some(bubble_type) {
+                SPAWN_THEME_WEIGHT_MULTIPLIER
+            } else {
+                1.0
+            };
+            (bubble_type, weight as f32 * scale * theme_scale)
+        });
+    let total_weight: f32 = allowed_weights.clone().map(|(_, weight)| weight).sum();
+    let mut roll = rng.gen::<f32>() * total_weight;
+    for (bubble_type, weight) in allowed_weights {
+        if roll < weight {
+            return bubble_type;
+        }
+        roll -= weight;
+    }
+    BubbleType::Regular
+}
+
+//vsync adds input lag on some machines, no vsync wastes power rendering frames nobody sees
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum VsyncMode {
+    #[default]
+    Fifo, //classic vsync, waits for the screen; supported everywhere
+    Immediate, //no waiting, may tear; uses mailbox where immediate is missing
+}
+
+impl VsyncMode {
+    fn next(self) -> Self {
+        match self {
+            VsyncMode::Fifo => VsyncMode::Immediate,
+            VsyncMode::Immediate => VsyncMode::Fifo,
+        }
+    }
+
+    //AutoNoVsync tries immediate, then mailbox, then fifo, so it never asks for a missing mode
+    fn present_mode(self) -> PresentMode {
+        match self {
+            VsyncMode::Fifo => PresentMode::Fifo,
+            VsyncMode::Immediate => PresentMode::AutoNoVsync,
+        }
+    }
+}
+
+//the orbs are unlit spheres in the bubble color without a point light; easier to read and much
+//cheaper to draw than the models, collisions are the same either way
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum BubbleStyle {
+    #[default]
+    Models,
+    Orbs,
+}
+
+//color palettes for players who have trouble telling the default bubble colors apart
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum ColorPalette {
+    #[default]
+    Default,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl ColorPalette {
+    const ALL: [ColorPalette; 4] = [
+        ColorPalette::Default,
+        ColorPalette::Deuteranopia,
+        ColorPalette::Protanopia,
+        ColorPalette::Tritanopia,
+    ];
+
+    fn next(self) -> Self {
+        match self {
+            ColorPalette::Default => ColorPalette::Deuteranopia,
+            ColorPalette::Deuteranopia => ColorPalette::Protanopia,
+            ColorPalette::Protanopia => ColorPalette::Tritanopia,
+            ColorPalette::Tritanopia => ColorPalette::Default,
+        }
+    }
+}
+
+//every place that colors something by bubble type should go through here
+//the color-blind palettes are based on the Okabe-Ito colors, with Paul Tol's muted colors where
+//those run out; types that share a model are only told apart by these, so no color (and no gray)
+//repeats within a palette
+fn bubble_color(
+    bubble_type: BubbleType,
+    palette: ColorPalette,
+    bubble_models: &BubbleModels,
+) -> Color {
+    match palette {
+        ColorPalette::Default => bubble_models
+            .light_colors
+            .get(&bubble_type)
+            .copied()
+            .unwrap_or(WHITE.into()),
+        ColorPalette::Deuteranopia => match bubble_type {
+            BubbleType::Blood => Color::srgb_u8(213, 94, 0),
+            BubbleType::Dirt => Color::srgb_u8(0, 114, 178),
+            BubbleType::Freeze => WHITE.into(),
+            BubbleType::Regular => Color::srgb_u8(240, 228, 66),
+            BubbleType::Mine => Color::srgb_u8(204, 121, 167),
+            BubbleType::Bomb => Color::srgb_u8(86, 180, 233),
+            BubbleType::Leech => Color::srgb_u8(0, 158, 115),
+            BubbleType::Grow => Color::srgb_u8(230, 159, 0),
+            BubbleType::Rush => Color::srgb_u8(120, 94, 240),
+            BubbleType::Invert => Color::srgb_u8(136, 34, 85),
+            BubbleType::Warp => Color::srgb_u8(68, 170, 153),
+            BubbleType::Purge => Color::srgb_u8(153, 0, 17),
+            BubbleType::Repel => Color::srgb_u8(204, 102, 119),
+            BubbleType::Thief => Color::srgb_u8(51, 34, 136),
+            BubbleType::Timed => Color::srgb_u8(0, 0, 238),
+        },
+        ColorPalette::Protanopia => match bubble_type {
+            BubbleType::Blood => Color::srgb_u8(230, 159, 0),
+            BubbleType::Dirt => Color::srgb_u8(0, 114, 178),
+            BubbleType::Freeze => WHITE.into(),
+            BubbleType::Regular => Color::srgb_u8(240, 228, 66),
+            BubbleType::Mine => Color::srgb_u8(204, 121, 167),
+            BubbleType::Bomb => Color::srgb_u8(86, 180, 233),
+            BubbleType::Leech => Color::srgb_u8(0, 158, 115),
+            BubbleType::Grow => Color::srgb_u8(213, 94, 0),
+            BubbleType::Rush => Color::srgb_u8(120, 94, 240),
+            BubbleType::Invert => Color::srgb_u8(136, 34, 85),
+            BubbleType::Warp => Color::srgb_u8(68, 170, 153),
+            BubbleType::Purge => Color::srgb_u8(153, 0, 17),
+            BubbleType::Repel => Color::srgb_u8(204, 102, 119),
+            BubbleType::Thief => Color::srgb_u8(51, 34, 136),
+            BubbleType::Timed => Color::srgb_u8(0, 0, 238),
+        },
+        ColorPalette::Tritanopia => match bubble_type {
+            BubbleType::Blood => Color::srgb_u8(204, 0, 0),
+            BubbleType::Dirt => Color::srgb_u8(0, 158, 115),
+            BubbleType::Freeze => WHITE.into(),
+            BubbleType::Regular => Color::srgb_u8(230, 159, 0),
+            BubbleType::Mine => Color::srgb_u8(204, 121, 167),
+            BubbleType::Bomb => Color::srgb_u8(86, 180, 233),
+            BubbleType::Leech => Color::srgb_u8(0, 114, 178),
+            BubbleType::Grow => Color::srgb_u8(170, 68, 153),
+            BubbleType::Rush => Color::srgb_u8(120, 94, 240),
+            BubbleType::Invert => Color::srgb_u8(136, 34, 85),
+            BubbleType::Warp => Color::srgb_u8(68, 170, 153),
+            BubbleType::Purge => Color::srgb_u8(221, 204, 119),
+            BubbleType::Repel => Color::srgb_u8(204, 102, 119),
+            BubbleType::Thief => Color::srgb_u8(51, 34, 136),
+            BubbleType::Timed => Color::srgb_u8(238, 119, 51),
+        },
+    }
+}
+
+//bubbles are removed once this runs out, no matter where they are
+#[derive(Component)]
+struct Lifetime {
+    timer: Timer,
+}
+
+//fuse of a timed bubble, see detonate_timed_bubbles
+#[derive(Component)]
+struct Countdown {
+    timer: Timer,
+}
+
+//everything from the bubble manifest; the scenes are filled in as the models finish loading
+#[derive(Resource, Default)]
+struct BubbleModels {
+    scenes: HashMap<BubbleType, Option<Handle<Scene>>>,
+    light_colors: HashMap<BubbleType, Color>,
+    //stand-ins for models that failed or did not load in time, see use_placeholder_bubble_models
+    placeholders: HashMap<BubbleType, (Handle<Mesh>, Handle<StandardMaterial>)>,
+    //flat disc under every bubble and its tint for every type in every palette, see BubbleShadow
+    shadow_mesh: Handle<Mesh>,
+    shadow_materials: HashMap<(BubbleType, ColorPalette), Handle<StandardMaterial>>,
+    //unit sphere for orbs and placeholders, and the orb material for every type in every palette
+    orb_mesh: Handle<Mesh>,
+    orb_materials: HashMap<(BubbleType, ColorPalette), Handle<StandardMaterial>>,
+    //meshes and their offsets from the bubble for the silhouette of every type
+    silhouettes: HashMap<BubbleType, Vec<(Handle<Mesh>, Transform)>>,
+}
+
+impl BubbleModels {
+    //whether bubbles of this type can be spawned, with their model or a placeholder
+    fn is_ready(&self, bubble_type: BubbleType) -> bool {
+        matches!(self.scenes.get(&bubble_type), Some(Some(_)))
+            || self.placeholders.contains_key(&bubble_type)
+    }
+
+    //whether every type can be spawned, missing models get placeholders after a timeout
+    fn all_ready(&self) -> bool {
+        BubbleType::ALL
+            .into_iter()
+            .all(|bubble_type| self.is_ready(bubble_type))
+    }
+
+    //adds the model of the type to a bubble entity; the loaded scene wins over the placeholder
+    fn insert_model(&self, entity: &mut EntityCommands, bubble_type: BubbleType) {
+        if let Some(Some(scene)) = self.scenes.get(&bubble_type) {
+            entity.insert(SceneRoot(scene.clone()));
+        } else if let Some((mesh, material)) = self.placeholders.get(&bubble_type) {
+            entity.insert((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone())));
+        }
+    }
+
+    //adds the silhouette of the type to a bubble entity, in both bubble styles
+    fn insert_silhouette(
+        &self,
+        entity: &mut EntityCommands,
+        bubble_type: BubbleType,
+        palette: ColorPalette,
+    ) {
+        let (Some(parts), Some(material)) = (
+            self.silhouettes.get(&bubble_type),
+            self.orb_materials.get(&(bubble_type, palette)),
+        ) else {
+            return;
+        };
+        for (mesh, transform) in parts {
+            entity.with_child((
+                SilhouettePart,
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(material.clone()),
+                *transform,
+            ));
+        }
+    }
+}
+
+//shape added around the model, so harmful types that share a model are still told apart without
+//their color; the parts are sized for a bubble with a radius of 1
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum BubbleSilhouette {
+    #[default]
+    Round,
+    Spikes, //cones sticking out around the middle
+    Fuse,   //a stick leaning out of the top
+    Studs,  //a few knobs around the middle
+    Ring,   //a thin flat ring around the middle
+}
+
+impl BubbleSilhouette {
+    fn parts(self, meshes: &mut Assets<Mesh>) -> Vec<(Handle<Mesh>, Transform)> {
+        //the parts sit on the horizontal plane, so they stand out when looking down at them
+        let around = |count: usize, distance: f32| {
+            (0..count).map(move |index| {
+                let direction = Vec2::from_angle(index as f32 / count as f32 * 2.0 * PI);
+                Vec3::new(direction.x, 0.0, direction.y) * distance
+            })
+        };
+        match self {
+            BubbleSilhouette::Round => Vec::new(),
+            BubbleSilhouette::Spikes => {
+                let spike = meshes.add(Cone {
+                    radius: 0.2,
+                    height: 0.6,
+                });
+                //cones point along +y
+                around(6, 1.1)
+                    .map(|offset| {
+                        let rotation = Quat::from_rotation_arc(Vec3::Y, offset.normalize());
+                        (
+                            spike.clone(),
+                            Transform::from_translation(offset).with_rotation(rotation),
+                        )
+                    })
+                    .collect()
+            }
+            BubbleSilhouette::Fuse => {
+                let tilt = Quat::from_rotation_z(-PI / 3.0);
+                let fuse = Transform::from_translation(Vec3::Y * 0.9 + tilt * Vec3::Y * 0.45)
+                    .with_rotation(tilt);
+                vec![(meshes.add(Cylinder::new(0.08, 0.9)), fuse)]
+            }
+            BubbleSilhouette::Studs => {
+                let stud = meshes.add(Sphere::new(0.25));
+                around(4, 1.0)
+                    .map(|offset| (stud.clone(), Transform::from_translation(offset)))
+                    .collect()
+            }
+            BubbleSilhouette::Ring => {
+                vec![(meshes.add(Torus::new(1.2, 1.35)), Transform::IDENTITY)]
+            }
+        }
+    }
+}
+
+//one line of assets/bubbles.ron
+#[derive(Deserialize)]
+struct BubbleManifestEntry {
+    bubble_type: String,
+    model: String,
+    color: (u8, u8, u8),
+    //replaces the default effect of the type, see BubbleEffects
+    #[serde(default)]
+    effect: Option<BubbleEffect>,
+    #[serde(default)]
+    silhouette: BubbleSilhouette,
+}
+
+//what hitting a bubble does to the player, see handle_bubble_hit
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+struct BubbleEffect {
+    oxygen: f32,             //added to the oxygen level, negative for harmful bubbles
+    scales_with_value: bool, //merged bubbles give the oxygen of every bubble they are made of
+    timed_effect: Option<EffectKind>,
+    duration: f32, //seconds the timed effect lasts
+    refill: bool,  //fills the tank to the max and empties the bonus reserve in exchange
+    //added to the oxygen level when the bubble goes off close to the player, see detonate_timed_bubbles
+    blast_oxygen: f32,
+}
+
+//the effect of every bubble type; the manifest can override single types
+#[derive(Resource)]
+struct BubbleEffects(HashMap<BubbleType, BubbleEffect>);
+
+impl Default for BubbleEffects {
+    fn default() -> Self {
+        let oxygen = |oxygen| BubbleEffect {
+            oxygen,
+            ..default()
+        };
+        BubbleEffects(HashMap::from([
+            (
+                BubbleType::Regular,
+                BubbleEffect {
+                    oxygen: BUBBLE_EFFECT_OXYGEN_INCREASE,
+                    scales_with_value: true,
+                    ..default()
+                },
+            ),
+            (
+                BubbleType::Dirt,
+                oxygen(-BUBBLE_EFFECT_OXYGEN_DECREASE_SMALL),
+            ),
+            (
+                BubbleType::Blood,
+                oxygen(-BUBBLE_EFFECT_OXYGEN_DECREASE_BIG),
+            ),
+            (
+                BubbleType::Mine,
+                oxygen(-BUBBLE_EFFECT_OXYGEN_DECREASE_MINE),
+            ),
+            (
+                BubbleType::Thief,
+                oxygen(-BUBBLE_EFFECT_OXYGEN_DECREASE_SMALL * 0.5),
+            ),
+            (
+                BubbleType::Bomb,
+                oxygen(-BUBBLE_EFFECT_OXYGEN_DECREASE_BOMB),
+            ),
+            //collecting a timed bubble defuses it, the danger is in letting it go off nearby
+            (
+                BubbleType::Timed,
+                BubbleEffect {
+                    oxygen: BUBBLE_EFFECT_OXYGEN_INCREASE * 0.5,
+                    blast_oxygen: -TIMED_BLAST_OXYGEN_DECREASE,
+                    ..default()
+                },
+            ),
+            (
+                BubbleType::Freeze,
+                BubbleEffect {
+                    oxygen: BUBBLE_EFFECT_OXYGEN_INCREASE * 0.5,
+                    timed_effect: Some(EffectKind::Freeze),
+                    duration: BUBBLE_EFFECT_FREEZE_DURATION,
+                    ..default()
+                },
+            ),
+            (
+                BubbleType::Grow,
+                BubbleEffect {
+                    timed_effect: Some(EffectKind::Grow),
+                    duration: PLATEAU_GROW_DURATION,
+                    ..default()
+                },
+            ),
+            (
+                BubbleType::Rush,
+                BubbleEffect {
+                    timed_effect: Some(EffectKind::Rush),
+                    duration: RUSH_DURATION,
+                    ..default()
+                },
+            ),
+            (
+                BubbleType::Invert,
+                BubbleEffect {
+                    timed_effect: Some(EffectKind::Invert),
+                    duration: INVERT_DURATION,
+                    ..default()
+                },
+            ),
+            (
+                BubbleType::Purge,
+                BubbleEffect {
+                    refill: true,
+                    ..default()
+                },
+            ),
+            //leeches drain over time instead, see handle_leeches
+            (BubbleType::Leech, BubbleEffect::default()),
+        ]))
+    }
+}
+
+impl BubbleEffects {
+    fn from_manifest(bubble_manifest: &[(BubbleType, BubbleManifestEntry)]) -> Self {
+        let mut bubble_effects = BubbleEffects::default();
+        for (bubble_type, entry) in bubble_manifest {
+            if let Some(effect) = entry.effect {
+                bubble_effects.0.insert(*bubble_type, effect);
+            }
+        }
+        bubble_effects
+    }
+
+    //all the oxygen a hit costs in the end, including what an attached leech drains
+    fn oxygen_loss(&self, bubble_type: BubbleType) -> f32 {
+        if bubble_type == BubbleType::Leech {
+            return LEECH_DRAIN_PER_SECOND * LEECH_DURATION;
+        }
+        self.0
+            .get(&bubble_type)
+            .map_or(0.0, |effect| (-effect.oxygen).max(0.0))
+    }
+}
+
+//a missing manifest or an unknown type name is logged; the affected bubbles just never spawn
+//the manifest is read from the same folder the AssetServer loads from, not the working directory
+fn load_bubble_manifest() -> Vec<(BubbleType, BubbleManifestEntry)> {
+    let manifest_path = FileAssetReader::new(AssetPlugin::default().file_path)
+        .root_path()
+        .join(BUBBLE_MANIFEST_PATH);
+    let entries = std::fs::read_to_string(&manifest_path)
+        .map_err(|error| error.to_string())
+        .and_then(|contents| {
+            ron::from_str::<Vec<BubbleManifestEntry>>(&contents).map_err(|error| error.to_string())
+        });
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(error) => {
+            error!("could not load {}: {}", manifest_path.display(), error);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| match BubbleType::from_name(&entry.bubble_type) {
+            Some(bubble_type) => Some((bubble_type, entry)),
+            None => {
+                error!(
+                    "unknown bubble type {} in {}",
+                    entry.bubble_type, BUBBLE_MANIFEST_PATH
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+//how many bubbles of each type the player hit during the current run
+#[derive(Resource, Default)]
+struct CollectionStats(HashMap<BubbleType, u32>);
+
+#[derive(Component)]
+struct GameOverStatsText;
+
+#[derive(Component)]
+struct GameOverScreen;
+
+//root node of the play again and quit buttons on the game over screen
+#[derive(Component)]
+struct GameOverButtons;
+
+#[derive(Component, Clone, Copy)]
+enum GameOverButton {
+    PlayAgain,
+    Quit,
+}
+
+const BUTTON_COLOR: Color = Color::srgba(0.1, 0.2, 0.4, 0.8);
+const BUTTON_HOVERED_COLOR: Color = Color::srgba(0.2, 0.4, 0.7, 0.9);
+const BUTTON_PRESSED_COLOR: Color = Color::srgba(0.4, 0.6, 0.9, 1.0);
+
+//oxygen collected while the tank was already full
+#[derive(Resource, Default)]
+struct BonusReserve(f32);
+
+#[derive(Component)]
+struct BonusReserveBar;
+
+//fills up while the dash cools down, grayed out when there is not enough oxygen for a dash
+#[derive(Component)]
+struct DashIndicator;
+
+//every gameplay random roll goes through this so a run can be reproduced from its seed
+#[derive(Resource)]
+struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    fn from_seed(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+//movement input of a single fixed update tick
+#[derive(Clone, Copy)]
+struct ReplayFrame {
+    movement: Vec2,
+    delta_secs: f32,
+    dash: bool,
+    panic_clear: bool,
+}
+
+//only present when started with --record-replay
+#[derive(Resource, Default)]
+struct ReplayRecorder {
+    frames: Vec<ReplayFrame>,
+}
+
+//only present when started with --replay <file>; feeds the recorded input into player_effects
+#[derive(Resource)]
+struct ReplayPlayer {
+    game_mode: GameMode,
+    playfield: PlayfieldMode,
+    difficulty_preset: DifficultyPreset,
+    start_oxygen: f32,
+    adaptive_difficulty: bool,
+    density_fill: bool,
+    freeze_pauses_spawns: bool,
+    arena_shape: ArenaShape,
+    frames: Vec<ReplayFrame>,
+    next_frame: usize,
+}
+
+//one gameplay event of a run, see EventLog
+enum LoggedEvent {
+    BubbleHit { bubble_type: BubbleType, value: f32 },
+    LevelUp(u32),
+    OffPlateau(bool), //entered (true) or left (false) the zone that drains extra oxygen
+    NearMiss,
+    Death(String), //the cause, see EventLog::death_cause
+}
+
+impl LoggedEvent {
+    fn describe(&self) -> String {
+        match self {
+            LoggedEvent::BubbleHit { bubble_type, value } => {
+                format!("hit {} {}", bubble_type.name(), value)
+            }
+            LoggedEvent::LevelUp(level) => format!("level_up {}", level),
+            LoggedEvent::OffPlateau(true) => "off_plateau enter".to_string(),
+            LoggedEvent::OffPlateau(false) => "off_plateau leave".to_string(),
+            LoggedEvent::NearMiss => "near_miss".to_string(),
+            LoggedEvent::Death(cause) => format!("death {}", cause),
+        }
+    }
+}
+
+//only present when started with --event-log; what happened during the run and when (in seconds
+//survived), for balancing; unlike the ReplayRecorder this records what the input led to
+#[derive(Resource, Default)]
+struct EventLog {
+    entries: Vec<(f32, LoggedEvent)>,
+    near_misses_seen: u32,
+}
+
+impl EventLog {
+    fn push(&mut self, survival_time: f32, event: LoggedEvent) {
+        self.entries.push((survival_time, event));
+    }
+
+    //the harmful bubble that hit last if it was just now, otherwise where the oxygen ran out
+    fn death_cause(&self, survival_time: f32) -> String {
+        for (time, event) in self.entries.iter().rev() {
+            if survival_time - time > EVENT_LOG_DEATH_CAUSE_WINDOW {
+                break;
+            }
+            if let LoggedEvent::BubbleHit { bubble_type, .. } = event {
+                if bubble_type.is_harmful() {
+                    return format!("{}_bubble", bubble_type.name());
+                }
+            }
+        }
+        let off_plateau = self
+            .entries
+            .iter()
+            .rev()
+            .find_map(|(_, event)| match event {
+                LoggedEvent::OffPlateau(entered) => Some(*entered),
+                _ => None,
+            });
+        if off_plateau == Some(true) {
+            "drained_off_plateau".to_string()
+        } else {
+            "out_of_oxygen".to_string()
+        }
+    }
+}
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum GameState {
+    #[default]
+    MainMenu,
+    Playing,
+}
+
+//one menu choice for how forgiving a run is: the oxygen it starts with, how fast the oxygen drains
+//and how many harmful bubbles spawn; it stacks with the game mode and the adaptive difficulty
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum DifficultyPreset {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl DifficultyPreset {
+    fn name(self) -> &'static str {
+        match self {
+            DifficultyPreset::Easy => "easy",
+            DifficultyPreset::Normal => "normal",
+            DifficultyPreset::Hard => "hard",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            DifficultyPreset::Easy => DifficultyPreset::Normal,
+            DifficultyPreset::Normal => DifficultyPreset::Hard,
+            DifficultyPreset::Hard => DifficultyPreset::Easy,
+        }
+    }
+
+    //all the oxygen a run starts with, for the starting oxygen chosen in the main menu
+    fn start_supply(self, start_oxygen: f32) -> f32 {
+        match self {
+            DifficultyPreset::Easy => start_oxygen + EASY_START_RESERVE,
+            DifficultyPreset::Normal => start_oxygen,
+            DifficultyPreset::Hard => start_oxygen * HARD_START_OXYGEN_FRACTION,
+        }
+    }
+
+    fn start_oxygen(self, start_oxygen: f32) -> f32 {
+        self.start_supply(start_oxygen).min(PLAYER_OXYGEN_MAX)
+    }
+
+    //the tank can't hold more than PLAYER_OXYGEN_MAX, so the rest is banked
+    fn start_reserve(self, start_oxygen: f32) -> f32 {
+        (self.start_supply(start_oxygen) - PLAYER_OXYGEN_MAX).max(0.0)
+    }
+
+    fn drain_multiplier(self) -> f32 {
+        match self {
+            DifficultyPreset::Easy => EASY_DRAIN_MULTIPLIER,
+            DifficultyPreset::Normal => 1.0,
+            DifficultyPreset::Hard => HARD_DRAIN_MULTIPLIER,
+        }
+    }
+
+    //added to the spawn bias, see roll_bubble_type
+    fn harmful_bias(self) -> f32 {
+        match self {
+            DifficultyPreset::Easy => -DIFFICULTY_PRESET_BIAS,
+            DifficultyPreset::Normal => 0.0,
+            DifficultyPreset::Hard => DIFFICULTY_PRESET_BIAS,
+        }
+    }
+
+    //bubbles kept around at the start of a run with density_fill, see fill_bubble_density
+    fn target_density(self) -> usize {
+        match self {
+            DifficultyPreset::Easy => DENSITY_TARGET_EASY,
+            DifficultyPreset::Normal => DENSITY_TARGET_NORMAL,
+            DifficultyPreset::Hard => DENSITY_TARGET_HARD,
+        }
+    }
+}
+
+//the open playfield has no off-plateau penalty at all and spawns bubbles further out, so the whole
+//sea floor can be roamed; the plateau center still refills the tank
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum PlayfieldMode {
+    #[default]
+    Confined,
+    Open,
+}
+
+impl PlayfieldMode {
+    fn name(self) -> &'static str {
+        match self {
+            PlayfieldMode::Confined => "confined",
+            PlayfieldMode::Open => "open",
+        }
+    }
+
+    fn spawn_radius(self) -> f32 {
+        match self {
+            PlayfieldMode::Confined => BUBBLE_SPAWN_RADIUS,
+            PlayfieldMode::Open => BUBBLE_SPAWN_RADIUS_OPEN,
+        }
+    }
+}
+
+//zen mode only spawns harmless bubbles and has no off-plateau penalty
+//hardcore drains faster and takes away all the help: no adaptive spawns, no refilling center and
+//no spawn previews
+//best times are kept per mode, so hardcore runs only rank against each other
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum GameMode {
+    #[default]
+    Normal,
+    Zen,
+    Hardcore,
+}
+
+impl GameMode {
+    fn name(self) -> &'static str {
+        match self {
+            GameMode::Normal => "normal",
+            GameMode::Zen => "zen",
+            GameMode::Hardcore => "hardcore",
+        }
+    }
+
+    //order of the modes in the main menu
+    fn next(self) -> Self {
+        match self {
+            GameMode::Normal => GameMode::Zen,
+            GameMode::Zen => GameMode::Hardcore,
+            GameMode::Hardcore => GameMode::Normal,
+        }
+    }
+}
+
+//seconds the player stayed alive during the current run
+#[derive(Resource, Default)]
+struct SurvivalTime(f32);
+
+//gameplay time for the timers of a run; it only moves while a run is being played, so pausing or
+//slowing down the game happens here instead of in every system that ticks a timer
+//every system of the fixed chain reads its time from here, never from Time
+#[derive(Resource)]
+struct GameClock {
+    delta: std::time::Duration,
+    elapsed: std::time::Duration,
+    time_scale: f32, //1.0 runs at the normal speed, 0.0 stands still
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock {
+            delta: std::time::Duration::ZERO,
+            elapsed: std::time::Duration::ZERO,
+            time_scale: 1.0,
+        }
+    }
+}
+
+//the gameplay stands still for a moment after a heavy hit, so the hit is felt; tick_game_clock
+//keeps the clock at zero until the timer is done
+#[derive(Resource)]
+struct HitStop {
+    timer: Timer,
+}
+
+impl Default for HitStop {
+    fn default() -> Self {
+        let mut timer = Timer::from_seconds(HIT_STOP_DURATION, TimerMode::Once);
+        timer.tick(timer.duration());
+        HitStop { timer }
+    }
+}
+
+impl GameClock {
+    fn delta(&self) -> std::time::Duration {
+        self.delta
+    }
+
+    fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    fn elapsed_secs(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+}
+
+#[derive(Component)]
+struct MainMenu;
+
+//the options change to their next value when activated, see MenuNavigationPlugin
+#[derive(Component, Clone, Copy)]
+enum MainMenuButton {
+    Mode,
+    Playfield,
+    Difficulty,
+    StartOxygen,
+    Training,
+    DiveIn,
+}
+
+#[derive(Component)]
+struct GameModeLabel;
+
+#[derive(Component)]
+struct Background;
+
+#[derive(Component)]
+struct Plateau;
+
+fn main() {
+    let mut app = App::new();
+    let args: Vec<String> = std::env::args().collect();
+    let mut seed = rand::random::<u64>();
+    #[cfg(debug_assertions)]
+    app.add_plugins(ConsolePlugin);
+    //hidden flag for profiling, see run_benchmark; the level is the same on every run as well
+    if args.iter().any(|arg| arg == "--benchmark") {
+        seed = BENCHMARK_SEED;
+        app.insert_resource(Benchmark::default())
+            .insert_resource(GodMode(true));
+    }
+    if args.iter().any(|arg| arg == "--record-replay") {
+        app.insert_resource(ReplayRecorder::default());
+    }
+    if args.iter().any(|arg| arg == "--event-log") {
+        app.insert_resource(EventLog::default());
+    }
+    if let Some(replay_path) = args
+        .iter()
+        .position(|arg| arg == "--replay")
+        .and_then(|index| args.get(index + 1))
+    {
+        match load_replay_file(replay_path) {
+            Ok((replay_seed, replay_player)) => {
+                seed = replay_seed;
+                app.insert_resource(replay_player);
+            }
+            Err(error) => eprintln!("could not load replay {}: {}", replay_path, error),
+        }
+    }
+
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            title: WINDOW_TITLE.into(),
+            ..default()
+        }),
+        ..default()
+    }))
+    .add_plugins(LogDiagnosticsPlugin::default())
+    .add_plugins(UnderwaterDistortionPlugin)
+    .add_plugins(MenuNavigationPlugin)
+    .insert_resource(GameRng::from_seed(seed))
+    .init_resource::<BubbleGrid>()
+    .init_resource::<Score>()
+    .init_resource::<OxygenBonus>()
+    .init_resource::<Level>()
+    .init_resource::<NearMisses>()
+    .init_resource::<LeechShake>()
+    .init_resource::<MovementInput>()
+    .init_resource::<PlayerVelocity>()
+    .init_resource::<Dash>()
+    .init_resource::<PanicClear>()
+    .init_resource::<Wave>()
+    .init_resource::<RingBurst>()
+    .init_resource::<SpawnQueue>()
+    .init_resource::<SpawnBias>()
+    .init_resource::<AdaptiveDifficulty>()
+    .init_resource::<StartOxygen>()
+    .init_resource::<SpawnArcBias>()
+    .init_resource::<Finale>()
+    .init_resource::<SpawnTheme>()
+    .init_resource::<Tutorial>()
+    .init_resource::<OffPlateau>()
+    .init_resource::<InvertArena>()
+    .init_resource::<BubbleEffects>()
+    .init_resource::<Difficulty>()
+    .init_resource::<PlateauSize>()
+    .init_resource::<ActiveEffects>()
+    .init_resource::<PlayerStats>()
+    .init_resource::<ShowHitbox>()
+    .init_resource::<GodMode>()
+    .init_resource::<GodModeUsed>()
+    .init_resource::<TrainingType>()
+    .init_resource::<ShowTrajectories>()
+    .insert_resource(WindowTitleTimer(Timer::from_seconds(
+        WINDOW_TITLE_UPDATE_INTERVAL,
+        TimerMode::Repeating,
+    )))
+    .init_resource::<BubbleSpawnTimer>()
+    .init_resource::<GameClock>()
+    .init_resource::<HitStop>()
+    .init_resource::<GameStats>()
+    .init_resource::<DisplayedOxygen>()
+    .init_resource::<Current>()
+    .init_resource::<CurrentChangeTimer>()
+    .init_state::<GameState>()
+    .add_systems(Startup, (load_settings, setup).chain())
+    .add_systems(Last, (save_settings_on_exit, limit_frame_rate))
+    .add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
+    .add_systems(OnExit(GameState::MainMenu), despawn_all::<MainMenu>)
+    .add_systems(
+        OnEnter(GameState::Playing),
+        (
+            reset_run,
+            (
+                spawn_game_mode_label,
+                apply_difficulty_preset,
+                start_adaptive_difficulty,
+                start_tutorial,
+                start_training,
+            ),
+        )
+            .chain(),
+    )
+    //the game over screen hangs off the player, despawning it recursively also drops it from
+    //the player's children; restart_run clears the same entities when a run starts over
+    .add_systems(
+        OnExit(GameState::Playing),
+        (
+            despawn_all::<GameModeLabel>,
+            despawn_all::<GameOverScreen>,
+            despawn_all::<GameOverStatsText>,
+            despawn_all::<GameOverButtons>,
+        ),
+    )
+    .add_systems(FixedFirst, tick_game_clock)
+    //everything that changes the game state runs on the fixed timestep in a fixed order,
+    //so a run plays out the same way given the same seed and input (see ReplayRecorder)
+    //none of it runs once the run is over, the world stays as it was in the moment of death
+    .add_systems(FixedUpdate, fixed_gameplay_systems())
+    .add_systems(
+        Update,
+        (
+            emit_oxygen_trail,
+            (
+                emit_rush_streaks.run_if(not(game_over)),
+                update_rush_streaks,
+            )
+                .chain(),
+            show_freeze_effect,
+            aim_flashlight,
+            draw_bubble_trajectories,
+            update_dash_indicator,
+            check_level_up,
+            update_level_progress.after(check_level_up),
+            show_spawn_previews,
+            update_spawn_previews,
+            skip_tutorial,
+            update_off_plateau_warning,
+            update_tutorial_text,
+            record_best_survival_time.before(show_game_over_screen),
+            save_replay,
+            convert_oxygen_to_score.before(show_game_over_screen),
+            spawn_game_over_buttons,
+            game_over_buttons,
+            game_over_input,
+            (
+                leave_training,
+                restart_run
+                    .after(game_over_input)
+                    .after(game_over_buttons)
+                    .after(leave_training),
+            ),
+        )
+            .run_if(in_state(GameState::Playing)),
+    )
+    .add_systems(
+        Update,
+        (log_run_events.after(check_level_up), save_event_log)
+            .chain()
+            .run_if(in_state(GameState::Playing))
+            .run_if(resource_exists::<EventLog>),
+    )
+    .add_systems(
+        Update,
+        (
+            (on_asset_loaded, use_placeholder_bubble_models).chain(),
+            populate_level,
+            (stop_all_loops_on_game_over, play_game_over_sound).chain(),
+            show_game_over_screen,
+            (play_spawn_sounds, clear_old_sounds).chain(),
+            update_music_layers,
+            update_bonus_reserve_bar,
+            sway_algae,
+            draw_arena_boundary,
+            update_floating_texts,
+            (debug_hitbox, debug_bubble_inspector, debug_spawn_circle),
+            show_wave_banner,
+            (zoom_camera, follow_camera).chain(),
+            (
+                smooth_displayed_oxygen,
+                update_oxygen_gauge,
+                update_player_appearance,
+            )
+                .chain(),
+            toggle_spawn_tuning_panel,
+            spawn_tuning_panel,
+            (update_spawn_fade, update_bubble_shadows).chain(),
+            (pulse_mine_lights, pulse_timed_bubbles),
+            update_oxygen_trail,
+        ),
+    )
+    .add_systems(
+        Update,
+        (
+            save_settings_on_change,
+            (cycle_color_palette, apply_color_palette).chain(),
+            (toggle_bubble_legend, update_bubble_legend).chain(),
+            toggle_bubble_style,
+            toggle_fullscreen,
+            (cycle_vsync_mode, apply_vsync_mode).chain(),
+            toggle_rumble,
+            toggle_underwater_distortion,
+            toggle_reduced_motion,
+            toggle_new_layout_each_run,
+            toggle_adaptive_difficulty,
+            toggle_god_mode,
+            toggle_trajectories,
+            update_underwater_distortion,
+            rumble_on_hit,
+            toggle_controls_hint,
+            update_controls_hint,
+            update_window_title,
+            set_window_icon,
+            quit_on_key,
+        ),
+    )
+    .add_systems(
+        Update,
+        (main_menu_buttons, update_main_menu_labels)
+            .chain()
+            .run_if(in_state(GameState::MainMenu)),
+    )
+    .add_systems(
+        Update,
+        (
+            top_up_benchmark_bubbles.run_if(in_state(GameState::Playing)),
+            run_benchmark,
+        )
+            .run_if(resource_exists::<Benchmark>),
+    )
+    .add_systems(
+        Update,
+        (
+            collect_stats,
+            #[cfg(feature = "stats_file")]
+            write_stats_file,
+        )
+            .chain(),
+    )
+    .add_event::<GameOverEvent>()
+    .add_event::<RestartEvent>()
+    .add_event::<LevelUpEvent>()
+    .add_event::<RegenerateLevelEvent>()
+    .add_event::<BubbleHitEvent>()
+    .add_event::<ChainEvent>()
+    .add_event::<WaveStartedEvent>()
+    .add_event::<SpawnThemeStartedEvent>()
+    .add_event::<WaveClearedEvent>()
+    .run();
+}
+
+//the fixed update chain of a run, on its own so tests can run it without the rest of the game
+fn fixed_gameplay_systems() -> SystemConfigs {
+    (
+        (
+            latch_god_mode,
+            change_current,
+            advance_waves,
+            update_spawn_bias,
+            (
+                advance_spawn_theme.run_if(not(tutorial_running)),
+                bubble_spawns
+                    .run_if(not(tutorial_running))
+                    .run_if(not(finale_running).or(training_running)),
+                ring_burst
+                    .run_if(not(tutorial_running))
+                    .run_if(not(training_running)),
+                finale_spawner
+                    .run_if(not(tutorial_running))
+                    .run_if(not(training_running)),
+                tutorial_spawner.run_if(tutorial_running),
+                fill_bubble_density
+                    .run_if(density_fill_enabled)
+                    .run_if(not(tutorial_running))
+                    .run_if(not(finale_running))
+                    .run_if(not(training_running)),
+                drain_spawn_queue,
+            )
+                .chain()
+                .run_if(not(spawns_frozen)),
+        )
+            .chain(),
+        (steer_thieves, move_bubbles).chain(),
+        update_bubble_grid,
+        bubble_interactions,
+        player_effects,
+        use_panic_clear,
+        check_collisions,
+        detonate_bombs,
+        score_chains,
+        handle_bubble_hit,
+        start_hit_stop,
+        advance_tutorial,
+        handle_leeches,
+        (warp_player, repel_bubbles).chain(),
+        tick_effects,
+        update_plateau_size,
+        transfer_bonus_reserve,
+        reduce_oxygen_level,
+        update_survival_time,
+        (detonate_timed_bubbles, expire_bubbles).chain(),
+    )
+        .chain()
+        .run_if(in_state(GameState::Playing))
+        .run_if(game_clock_running)
+        .run_if(not(game_over))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn on_asset_loaded(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    gltf_assets: Res<Assets<Gltf>>,
+    gltf_meshes: Res<Assets<GltfMesh>>,
+    assets_loading: ResMut<AssetsLoadingGltf>,
+    player_entity: Single<Entity, With<Player>>,
+    mut bubble_models: ResMut<BubbleModels>,
+    mut regenerate_level_event_writer: EventWriter<RegenerateLevelEvent>,
+    game_rng: Res<GameRng>,
+) {
+    let assets_loading = assets_loading.into_inner();
+    if !assets_loading.0.is_empty() {
+        let mut processed_assets: HashSet<String> = HashSet::from([]);
+
+        for gltf_handle in assets_loading.0.iter() {
+            if asset_server.is_loaded_with_dependencies(gltf_handle.1.id()) {
+                info!("handling loaded asset: {}", gltf_handle.0);
+
+                let loaded_asset = gltf_assets.get(gltf_handle.1.id());
+
+                if loaded_asset.is_some() {
+                    let gltf_asset = loaded_asset.unwrap();
+
+                    let asset_name = gltf_handle.0.to_string();
+                    match asset_name.as_str() {
+                        "player_character" => {
+                            //create mesh and add as child of player entity
+                            let player_character_id = commands
+                                .spawn((
+                                    PlayerModel,
+                                    SceneRoot(gltf_asset.default_scene.clone().unwrap()),
+                                    Transform::from_scale(Vec3::splat(ASSET_SCALE)),
+                                    InheritedVisibility::VISIBLE,
+                                ))
+                                .id();
+
+                            commands
+                                .entity(*player_entity)
+                                .add_child(player_character_id);
+
+                            //start animation
+                            //screw this; there is not reasonable example of how to get this going
+                            /*
+                            for mut player in &mut animation_players {
+                                let (graph, node_indices) =
+                                    AnimationGraph::from_clips(gltf_asset.animations.clone());
+
+                                player.play(node_indices[0]);
+                            }
+                             */
+                        }
+
+                        "alge" => {
+                            //the plants themselves are spawned by populate_level, so they can be
+                            //regenerated without loading the gltf again
+                            commands.insert_resource(AlgaeScene(
+                                gltf_asset.default_scene.clone().unwrap(),
+                            ));
+                            regenerate_level_event_writer.send(RegenerateLevelEvent {
+                                seed: game_rng.seed,
+                            });
+                        }
+
+                        "sand" => {
+                            commands.spawn((
+                                Background,
+                                SceneRoot(gltf_asset.default_scene.clone().unwrap()),
+                                Transform::from_translation(Vec3::splat(0.0_f32))
+                                    .with_scale(Vec3::splat(ASSET_SCALE)),
+                            ));
+                        }
+
+                        "plateau" => {
+                            commands.spawn((
+                                Plateau,
+                                Transform::from_translation(Vec3::splat(0.0_f32))
+                                    .with_scale(Vec3::splat(ASSET_SCALE)),
+                                SceneRoot(gltf_asset.default_scene.clone().unwrap()),
+                            ));
+                        }
+
+                        name if name.starts_with(BUBBLE_ASSET_PREFIX) => {
+                            //the manifest only lists known types, see load_bubble_manifest
+                            if let Some(bubble_type) =
+                                BubbleType::from_name(&name[BUBBLE_ASSET_PREFIX.len()..])
+                            {
+                                bubble_models
+                                    .scenes
+                                    .insert(bubble_type, gltf_asset.default_scene.clone());
+                            }
+                        }
+
+                        "gauge" => {
+                            let empty_gltf = gltf_meshes
+                                .get(&gltf_asset.named_meshes["Gauge_empty"])
+                                .unwrap();
+                            let empty_prim = &empty_gltf.primitives[0];
+                            let gauge_empty_mesh = empty_prim.mesh.clone();
+                            let gauge_empty_material = empty_prim.material.clone().unwrap();
+
+                            let zeiger_mesh = gltf_meshes.get(&gltf_asset.meshes[1]).unwrap();
+                            let zeiger_prim = &zeiger_mesh.primitives[0];
+                            let zeiger_mesh = zeiger_prim.mesh.clone();
+                            let zeiger_material = zeiger_prim.material.clone().unwrap();
+                            let gauge_id = commands
+                                .spawn((
+                                    Transform::from_xyz(0.0, 8.0, 2.8)
+                                        .with_rotation(guage_quat())
+                                        .with_scale(Vec3::ONE * 0.5),
+                                    Mesh3d(gauge_empty_mesh),
+                                    MeshMaterial3d(gauge_empty_material),
+                                ))
+                                .id();
+                            let zeiger_id = commands
+                                .spawn((
+                                    Transform::from_xyz(0.0, 8.0, 2.8)
+                                        .with_rotation(guage_quat())
+                                        .with_scale(Vec3::ONE * 0.5),
+                                    Mesh3d(zeiger_mesh),
+                                    MeshMaterial3d(zeiger_material),
+                                    Zeiger,
+                                ))
+                                .id();
+                            commands.entity(*player_entity).add_child(gauge_id);
+                            commands.entity(*player_entity).add_child(zeiger_id);
+                        }
+
+                        _ => warn!("asset name was mepty"),
+                    };
+
+                    info!("asset {} spawned", gltf_handle.0);
+                    processed_assets.insert(asset_name);
+                } else {
+                    warn!("asset {} was none", gltf_handle.0);
+                }
+            }
+        }
+
+        for gltf_handle in processed_assets {
+            assets_loading.0.remove(&gltf_handle);
+            info!(
+                "asset {} processed and removed from loading set",
+                gltf_handle
+            );
+        }
+    }
+}
+
+//despawns the current plants and spawns a new layout on the plateau
+//the sand and the plateau are only spawned once in on_asset_loaded and stay for every run
+fn populate_level(
+    mut commands: Commands,
+    mut regenerate_level_event_reader: EventReader<RegenerateLevelEvent>,
+    algae_scene: Option<Res<AlgaeScene>>,
+    environment_query: Query<Entity, With<Environment>>,
+) {
+    let Some(regenerate_level_event) = regenerate_level_event_reader.read().last() else {
+        return;
+    };
+    let Some(algae_scene) = algae_scene else {
+        return;
+    };
+
+    for entity in &environment_query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    //the layout gets its own rng seeded from the run, so it is reproducible without taking any
+    //rolls away from the gameplay
+    let mut layout_rng = StdRng::seed_from_u64(regenerate_level_event.seed);
+    spawn_algae(&mut commands, &algae_scene.0, &mut layout_rng);
+}
+
+fn spawn_algae(commands: &mut Commands, scene_handle: &Handle<Scene>, rng: &mut impl Rng) {
+    let mut number_of_plants_to_spawn =
+        rng.gen_range(PLATEAU_MINIMUM_PLANTS..PLATEAU_MAXIMUM_PLANTS);
+    while number_of_plants_to_spawn > 0 {
+        let random_rotation = rng.gen::<f32>();
+        let random_distances = Vec2::from([rng.gen::<f32>(), rng.gen::<f32>()]);
+        let rotation_vector = Rot2::degrees(random_rotation * 360.0);
+
+        let transform = Transform::from_matrix(Mat4::from_scale_rotation_translation(
+            Vec3::splat(ASSET_SCALE),
+            Quat::from_euler(
+                EulerRot::XYZ,
+                rng.gen::<f32>() * PI / 0.1,
+                rng.gen::<f32>() * PI / 0.3,
+                rng.gen::<f32>() * PI / 0.1,
+            ),
+            Vec3::from([
+                rotation_vector.cos * random_distances.x * PLATEAU_RADIUS,
+                0.0_f32, //do not change y unless intentionally letting it hover
+                rotation_vector.sin * random_distances.y * PLATEAU_RADIUS,
+            ]),
+        ));
+
+        commands.spawn((
+            Environment,
+            AlgaeBaseRotation(transform.rotation),
+            SceneRoot(scene_handle.clone()),
+            transform,
+        ));
+
+        number_of_plants_to_spawn -= 1;
+    }
+}
+
+fn spawn_music(commands: &mut Commands, asset_server: &AssetServer) {
+    spawn_music_layers(commands, asset_server, 0.0);
+
+    commands.spawn(AudioPlayer::new(
+        asset_server.load("Stereotypische unterwasser Atmo.mp3"),
+    ));
+}
+
+//the two music files have different lengths, so instead of looping on their own both play once
+//and update_music_layers spawns them again together, which keeps the loops from drifting apart
+//both start paused and are unpaused together once both are loaded, so both start from the top
+fn spawn_music_layers(commands: &mut Commands, asset_server: &AssetServer, intensity: f32) {
+    let calm = commands
+        .spawn((
+            LoopingSound,
+            AudioPlayer::new(asset_server.load(MUSIC_CALM_FILE)),
+            PlaybackSettings {
+                paused: true,
+                volume: Volume::new(1.0 - intensity),
+                ..PlaybackSettings::ONCE
+            },
+        ))
+        .id();
+    let intense = commands
+        .spawn((
+            LoopingSound,
+            AudioPlayer::new(asset_server.load(MUSIC_INTENSE_FILE)),
+            PlaybackSettings {
+                paused: true,
+                volume: Volume::new(intensity),
+                ..PlaybackSettings::ONCE
+            },
+        ))
+        .id();
+    commands.insert_resource(MusicLayers {
+        calm,
+        intense,
+        intensity,
+        started: false,
+    });
+}
+
+//the intense loop takes over the longer the run lasts and the emptier the tank gets
+#[allow(clippy::too_many_arguments)]
+fn update_music_layers(
+    mut commands: Commands,
+    music_layers: Option<ResMut<MusicLayers>>,
+    sinks: Query<&AudioSink>,
+    audio_players: Query<&AudioPlayer>,
+    asset_server: Res<AssetServer>,
+    oxygen_level: Single<&OxygenLevel, With<Player>>,
+    survival_time: Res<SurvivalTime>,
+    time: Res<Time>,
+    mut intense_failed_warned: Local<bool>,
+) {
+    let Some(mut music_layers) = music_layers else {
+        return;
+    };
+    let Ok(calm_sink) = sinks.get(music_layers.calm) else {
+        return;
+    };
+    let Ok(intense_sink) = sinks.get(music_layers.intense) else {
+        //without the intense loop the calm one plays on its own, at full volume
+        let intense_failed = audio_players
+            .get(music_layers.intense)
+            .is_ok_and(|audio_player| asset_server.load_state(&audio_player.0).is_failed());
+        if !intense_failed {
+            return;
+        }
+        if !*intense_failed_warned {
+            warn!(
+                "{} failed to load, only the calm music plays",
+                MUSIC_INTENSE_FILE
+            );
+            *intense_failed_warned = true;
+        }
+        if !music_layers.started {
+            calm_sink.set_volume(1.0);
+            calm_sink.play();
+            music_layers.started = true;
+        } else if calm_sink.empty() {
+            restart_music_layers(&mut commands, &asset_server, &music_layers);
+        }
+        return;
+    };
+
+    if !music_layers.started {
+        calm_sink.play();
+        intense_sink.play();
+        music_layers.started = true;
+    }
+
+    //the intense track is the shorter one, when either ends both start over on the same boundary
+    if calm_sink.empty() || intense_sink.empty() {
+        restart_music_layers(&mut commands, &asset_server, &music_layers);
+        return;
+    }
+
+    let oxygen_intensity =
+        1.0 - (oxygen_level.0 / (PLAYER_OXYGEN_MAX * MUSIC_LOW_OXYGEN_FRACTION)).clamp(0.0, 1.0);
+    let time_intensity = (survival_time.0 / MUSIC_FULL_INTENSITY_TIME).clamp(0.0, 1.0);
+    let target_intensity = oxygen_intensity.max(time_intensity);
+    let max_step = MUSIC_CROSSFADE_SPEED * time.delta_secs();
+    music_layers.intensity +=
+        (target_intensity - music_layers.intensity).clamp(-max_step, max_step);
+
+    calm_sink.set_volume(1.0 - music_layers.intensity);
+    intense_sink.set_volume(music_layers.intensity);
+}
+
+fn restart_music_layers(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    music_layers: &MusicLayers,
+) {
+    commands.entity(music_layers.calm).despawn();
+    commands.entity(music_layers.intense).despawn();
+    spawn_music_layers(commands, asset_server, music_layers.intensity);
+}
+
+fn play_game_over_sound(
+    asset_server: Res<AssetServer>,
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+    mut commands: Commands,
+) {
+    for _event in game_over_event_reader.read() {
+        info!("Game Over - Thanks for dying :-)");
+        // spawn the game over sound
+        commands.spawn((
+            GameOverSound,
+            AudioPlayer::new(asset_server.load("background rumbling.wav")),
+        ));
+    }
+}
+
+//all audio teardown on death happens here: every loop, the music and whatever else is still
+//playing is despawned so only the game over rumble is left
+//the music stays gone until restart_run spawns it again
+#[allow(clippy::type_complexity)]
+fn stop_all_loops_on_game_over(
+    mut commands: Commands,
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+    audio_players: Query<
+        Entity,
+        (
+            Or<(With<AudioPlayer>, With<AudioPlayer<Pitch>>)>,
+            Without<GameOverSound>,
+        ),
+    >,
+) {
+    if game_over_event_reader.read().count() == 0 {
+        return;
+    }
+
+    for entity in &audio_players {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<MusicLayers>();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_game_over_screen(
+    mut commands: Commands,
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    camera_transform: Single<&Transform, With<Camera3d>>,
+    player_query: Single<(Entity, &Transform), With<Player>>,
+    collection_stats: Res<CollectionStats>,
+    survival_time: Res<SurvivalTime>,
+    settings: Res<Settings>,
+    playfield: Res<PlayfieldMode>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    start_oxygen: Res<StartOxygen>,
+    //grouped, a system takes at most 16 parameters
+    (score, oxygen_bonus): (Res<Score>, Res<OxygenBonus>),
+    near_misses: Res<NearMisses>,
+) {
+    let mut is_game_over = false;
+    for _event in game_over_event_reader.read() {
+        is_game_over = true;
+    }
+
+    if !is_game_over {
+        return;
+    }
+
+    // create quad handlePrimitiveTopology::Qua, asset_usa
+    let screen_mesh_handle = meshes.add(Plane3d::default());
+
+    // create texture handle
+    let texture_handle = materials.add(StandardMaterial {
+        base_color: Color::WHITE,
+        base_color_texture: Some(asset_server.load("Game Over2.png")),
+        perceptual_roughness: 1.0,
+        ..default()
+    });
+
+    // calculate camera-attached transform & rotation
+    // the screen is a child of the player, so place it relative to the player
+    let camera_transform = camera_transform.into_inner();
+    let (player_entity, player_transform) = player_query.into_inner();
+    let screen_location = camera_transform.translation - player_transform.translation
+        + camera_transform.forward() * GAME_OVER_SCREEN_DISTANCE;
+
+    let game_over_screen_id = commands
+        .spawn((
+            GameOverScreen,
+            Mesh3d(screen_mesh_handle.clone()),
+            MeshMaterial3d(texture_handle.clone()),
+            Transform::from_translation(screen_location).with_rotation(Quat::from_euler(
+                EulerRot::XYZ,
+                0.4,
+                0.0,
+                0.0,
+            )),
+        ))
+        .id();
+
+    commands
+        .entity(player_entity)
+        .add_child(game_over_screen_id);
+
+    // list how long the player survived and how many bubbles of each type were hit during the run
+    let best_time = settings
+        .best_survival_time(*game_mode, *playfield, *difficulty_preset, start_oxygen.0)
+        .unwrap_or(survival_time.0);
+    let mut stats_text = format!(
+        "Survived {:.1}s (best in {} mode, {} playfield, {} difficulty, {:.0} oxygen: {:.1}s)\nScore: {}",
+        survival_time.0,
+        game_mode.name(),
+        playfield.name(),
+        difficulty_preset.name(),
+        start_oxygen.0,
+        best_time,
+        score.0
+    );
+    //most runs end with an empty reserve, a +0 line would only be noise
+    if oxygen_bonus.0 > 0 {
+        stats_text.push_str(&format!("\nOxygen bonus: +{}", oxygen_bonus.0));
+    }
+    stats_text.push_str(&format!(
+        "\nNear misses: {}\n\nBubbles collected:",
+        near_misses.0
+    ));
+    //only the types that were eaten, all of them would not fit on a small window
+    let mut any_eaten = false;
+    for bubble_type in BubbleType::ALL {
+        let count = collection_stats.0.get(&bubble_type).copied().unwrap_or(0);
+        if count == 0 {
+            continue;
+        }
+        any_eaten = true;
+        stats_text.push_str(&format!(
+            "\nYou ate {} {} bubbles",
+            count,
+            bubble_type.name()
+        ));
+    }
+    if !any_eaten {
+        stats_text.push_str("\nNo bubbles eaten");
+    }
+    //the game state is still Playing, so quit_on_key asks for the quit key a second time
+    stats_text.push_str(&format!(
+        "\n\nPress R to play again, G to play again on a new layout or {} twice to quit",
+        key_name(settings.general_bindings.quit)
+    ));
+
+    commands.spawn((
+        GameOverStatsText,
+        Text::new(stats_text),
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(24.0),
+            left: Val::Px(24.0),
+            ..default()
+        },
+    ));
+}
+
+fn spawn_game_over_buttons(
+    mut commands: Commands,
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+) {
+    if game_over_event_reader.read().count() == 0 {
+        return;
+    }
+
+    commands
+        .spawn((
+            GameOverButtons,
+            Node {
+                position_type: PositionType::Absolute,
+                //centered, the stats are in the bottom left and the controls hint in the bottom right
+                bottom: Val::Px(24.0),
+                width: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                column_gap: Val::Px(16.0),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for (index, (button, label)) in [
+                (GameOverButton::PlayAgain, "Play Again"),
+                (GameOverButton::Quit, "Quit"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                parent
+                    .spawn((
+                        button,
+                        MenuItem { index },
+                        Button,
+                        Node {
+                            padding: UiRect::axes(Val::Px(24.0), Val::Px(12.0)),
+                            ..default()
+                        },
+                        BackgroundColor(BUTTON_COLOR),
+                        BorderRadius::all(Val::Px(8.0)),
+                    ))
+                    .with_child((
+                        Text::new(label),
+                        TextFont {
+                            font_size: 24.0,
+                            ..default()
+                        },
+                    ));
+            }
+        });
+}
+
+//clicked or confirmed with the keyboard or a gamepad, see MenuNavigationPlugin
+fn game_over_buttons(
+    mut menu_activated_event_reader: EventReader<MenuActivated>,
+    button_query: Query<&GameOverButton>,
+    mut restart_event_writer: EventWriter<RestartEvent>,
+    mut app_exit_event_writer: EventWriter<AppExit>,
+) {
+    for MenuActivated(entity) in menu_activated_event_reader.read() {
+        let Ok(button) = button_query.get(*entity) else {
+            continue;
+        };
+        match button {
+            GameOverButton::PlayAgain => {
+                restart_event_writer.send(RestartEvent {
+                    regenerate_level: false,
+                });
+            }
+            GameOverButton::Quit => {
+                app_exit_event_writer.send(AppExit::Success);
+            }
+        }
+    }
+}
+
+//keyboard alternatives to the game over buttons; quitting is left to quit_on_key, which uses the
+//bound quit key and asks first
+fn game_over_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    is_game_over: Res<IsGameOver>,
+    mut restart_event_writer: EventWriter<RestartEvent>,
+) {
+    if !is_game_over.0 {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::KeyR) {
+        restart_event_writer.send(RestartEvent {
+            regenerate_level: false,
+        });
+    } else if keyboard_input.just_pressed(KeyCode::KeyG) {
+        restart_event_writer.send(RestartEvent {
+            regenerate_level: true,
+        });
+    }
+}
+
+//puts everything a run changes back to how setup left it and starts over in the same mode
+//loaded assets, the sand, the plateau and (unless asked to regenerate) the plants are kept
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn restart_run(
+    mut commands: Commands,
+    mut restart_event_reader: EventReader<RestartEvent>,
+    mut regenerate_level_event_writer: EventWriter<RegenerateLevelEvent>,
+    asset_server: Res<AssetServer>,
+    player_query: Single<(&mut Transform, &mut OxygenLevel), With<Player>>,
+    camera_query: Single<(&mut Transform, &mut CameraFollow), Without<Player>>,
+    run_entities: Query<
+        Entity,
+        Or<(
+            With<Bubble>,
+            With<Leeching>,
+            With<FloatingText>,
+            With<SpawnPreview>,
+            With<WaveBanner>,
+            With<OxygenTrailParticle>,
+            With<RushStreak>,
+            With<GameOverScreen>,
+            With<GameOverStatsText>,
+            With<GameOverButtons>,
+        )>,
+    >,
+    audio_players: Query<Entity, Or<(With<AudioPlayer>, With<AudioPlayer<Pitch>>)>>,
+    replay_recorder: Option<ResMut<ReplayRecorder>>,
+    event_log: Option<ResMut<EventLog>>,
+    settings: Res<Settings>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    training_type: Res<TrainingType>,
+) {
+    let Some(restart_event) = restart_event_reader.read().last() else {
+        return;
+    };
+    info!("restarting the run");
+
+    for entity in run_entities.iter().chain(audio_players.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_music(&mut commands, &asset_server);
+
+    let (mut player_transform, mut oxygen_level) = player_query.into_inner();
+    *player_transform = Transform::default();
+    oxygen_level.0 = difficulty_preset.start_oxygen(settings.start_oxygen());
+
+    let (mut camera_transform, mut camera_follow) = camera_query.into_inner();
+    *camera_transform = camera_local_transform(settings.camera_distance());
+    *camera_follow = CameraFollow {
+        position: camera_transform.translation,
+        look_ahead: Vec3::ZERO,
+    };
+
+    insert_run_resources(
+        &mut commands,
+        &settings,
+        *game_mode,
+        *difficulty_preset,
+        training_type.0.is_some(),
+    );
+
+    //a replay only covers the run it was recorded in, so later runs are played live with a new
+    //seed; a recording starts over so the replay file always matches the latest run
+    commands.remove_resource::<ReplayPlayer>();
+    let seed = rand::random::<u64>();
+    commands.insert_resource(GameRng::from_seed(seed));
+    if let Some(mut replay_recorder) = replay_recorder {
+        replay_recorder.frames.clear();
+    }
+    if let Some(mut event_log) = event_log {
+        *event_log = EventLog::default();
+    }
+
+    if restart_event.regenerate_level || settings.new_layout_each_run {
+        regenerate_level_event_writer.send(RegenerateLevelEvent { seed });
+    }
+}
+
+//every resource that only lives for one run, as it is at the start of a run; used by restart_run
+//and when a run is started from the main menu, so both start from the same state
+fn insert_run_resources(
+    commands: &mut Commands,
+    settings: &Settings,
+    game_mode: GameMode,
+    difficulty_preset: DifficultyPreset,
+    training: bool,
+) {
+    let start_oxygen = settings.start_oxygen();
+    commands.insert_resource(IsGameOver::default());
+    commands.insert_resource(Score::default());
+    commands.insert_resource(OxygenBonus::default());
+    commands.insert_resource(Level::default());
+    commands.insert_resource(NearMisses::default());
+    commands.insert_resource(CollectionStats::default());
+    commands.insert_resource(StartOxygen(start_oxygen));
+    commands.insert_resource(BonusReserve(difficulty_preset.start_reserve(start_oxygen)));
+    //the gauge starts full right away instead of filling up
+    commands.insert_resource(DisplayedOxygen(
+        difficulty_preset.start_oxygen(start_oxygen),
+    ));
+    commands.insert_resource(SurvivalTime::default());
+    commands.insert_resource(LeechShake::default());
+    commands.insert_resource(MovementInput::default());
+    commands.insert_resource(PlayerVelocity::default());
+    commands.insert_resource(Dash::default());
+    commands.insert_resource(PanicClear::default());
+    commands.insert_resource(Wave::default());
+    commands.insert_resource(RingBurst::default());
+    commands.insert_resource(SpawnQueue::default());
+    commands.insert_resource(Finale::default());
+    commands.insert_resource(SpawnTheme::default());
+    commands.insert_resource(OffPlateau::default());
+    //a tutorial that was not finished yet starts over with the run
+    commands.insert_resource(Tutorial::for_run(settings, game_mode, training));
+    commands.insert_resource(SpawnBias::default());
+    commands.insert_resource(AdaptiveDifficulty(settings.adaptive_difficulty));
+    commands.insert_resource(SpawnArcBias::default());
+    commands.insert_resource(ActiveEffects::default());
+    commands.insert_resource(PlateauSize::default());
+    commands.insert_resource(InvertArena::default());
+    commands.insert_resource(BubbleSpawnTimer::default());
+    commands.insert_resource(Current::default());
+    commands.insert_resource(CurrentChangeTimer::default());
+    //a restart during a hit stop must not start the next run frozen
+    commands.insert_resource(HitStop::default());
+    commands.insert_resource(GameClock::default());
+    commands.insert_resource(Difficulty::default());
+    //the hitbox can be resized while debugging, see debug_hitbox
+    commands.insert_resource(PlayerStats::default());
+    commands.insert_resource(GodModeUsed::default());
+}
+
+//a run started from the main menu starts from the same state as a restarted one; the systems
+//after it in OnEnter(GameState::Playing) apply the mode, the difficulty and a replay
+fn reset_run(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    training_type: Res<TrainingType>,
+) {
+    insert_run_resources(
+        &mut commands,
+        &settings,
+        *game_mode,
+        *difficulty_preset,
+        training_type.0.is_some(),
+    );
+}
+
+fn spawn_main_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    training_type: Res<TrainingType>,
+    playfield: Res<PlayfieldMode>,
+    settings: Res<Settings>,
+) {
+    commands
+        .spawn((
+            MainMenu,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(16.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.1, 0.7)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ImageNode::new(asset_server.load("Title3.png")),
+                Node {
+                    width: Val::Percent(50.0),
+                    ..default()
+                },
+            ));
+            for (index, button) in [
+                MainMenuButton::Mode,
+                MainMenuButton::Playfield,
+                MainMenuButton::Difficulty,
+                MainMenuButton::StartOxygen,
+                MainMenuButton::Training,
+                MainMenuButton::DiveIn,
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let mut button_commands = parent.spawn((
+                    button,
+                    MenuItem { index },
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(24.0), Val::Px(8.0)),
+                        ..default()
+                    },
+                    BackgroundColor(BUTTON_COLOR),
+                    BorderRadius::all(Val::Px(8.0)),
+                ));
+                button_commands.with_child((
+                    Text::new(main_menu_label(
+                        button,
+                        *game_mode,
+                        *playfield,
+                        *difficulty_preset,
+                        settings.start_oxygen(),
+                        &training_type,
+                    )),
+                    TextFont {
+                        font_size: 22.0,
+                        ..default()
+                    },
+                ));
+                if let MainMenuButton::DiveIn = button {
+                    button_commands.insert(MenuDefault);
+                }
+            }
+            parent.spawn((
+                Text::new(
+                    "Arrow keys or d-pad to choose - Enter or A to change an option or to dive in",
+                ),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn main_menu_label(
+    button: MainMenuButton,
+    game_mode: GameMode,
+    playfield: PlayfieldMode,
+    difficulty_preset: DifficultyPreset,
+    start_oxygen: f32,
+    training_type: &TrainingType,
+) -> String {
+    match button {
+        MainMenuButton::Mode => main_menu_mode_text(game_mode),
+        MainMenuButton::Playfield => main_menu_playfield_text(playfield),
+        MainMenuButton::Difficulty => main_menu_difficulty_text(difficulty_preset, start_oxygen),
+        MainMenuButton::StartOxygen => format!("Starting oxygen: {:.0}", start_oxygen),
+        MainMenuButton::Training => main_menu_training_text(training_type),
+        MainMenuButton::DiveIn => "Dive in".into(),
+    }
+}
+
+fn main_menu_mode_text(game_mode: GameMode) -> String {
+    match game_mode {
+        GameMode::Normal => "Mode: normal".into(),
+        GameMode::Zen => "Mode: zen (no harmful bubbles)".into(),
+        GameMode::Hardcore => "Mode: hardcore (faster drain, no help)".into(),
+    }
+}
+
+fn main_menu_playfield_text(playfield: PlayfieldMode) -> String {
+    match playfield {
+        PlayfieldMode::Confined => "Playfield: confined to the plateau".into(),
+        PlayfieldMode::Open => "Playfield: open (no off-plateau penalty)".into(),
+    }
+}
+
+fn main_menu_training_text(training_type: &TrainingType) -> String {
+    match training_type.0 {
+        None => "Training: off".into(),
+        Some(bubble_type) => format!(
+            "Training: only {} bubbles, no oxygen loss",
+            bubble_type.name()
+        ),
+    }
+}
+
+fn main_menu_difficulty_text(difficulty_preset: DifficultyPreset, start_oxygen: f32) -> String {
+    format!(
+        "Difficulty: {} ({:.0} oxygen to start, {:.0}% drain)",
+        difficulty_preset.name(),
+        difficulty_preset.start_supply(start_oxygen),
+        difficulty_preset.drain_multiplier() * 100.0
+    )
+}
+
+//clicked or confirmed with the keyboard or a gamepad, see MenuNavigationPlugin
+#[allow(clippy::too_many_arguments)]
+fn main_menu_buttons(
+    mut menu_activated_event_reader: EventReader<MenuActivated>,
+    button_query: Query<&MainMenuButton>,
+    mut game_mode: ResMut<GameMode>,
+    mut difficulty_preset: ResMut<DifficultyPreset>,
+    mut playfield: ResMut<PlayfieldMode>,
+    mut settings: ResMut<Settings>,
+    mut training_type: ResMut<TrainingType>,
+    mut next_state: ResMut<NextState<GameState>>,
+    replay_player: Option<Res<ReplayPlayer>>,
+) {
+    for MenuActivated(entity) in menu_activated_event_reader.read() {
+        let Ok(button) = button_query.get(*entity) else {
+            continue;
+        };
+        //the last choices are kept for the next start of the game
+        match button {
+            MainMenuButton::Mode => *game_mode = game_mode.next(),
+            MainMenuButton::Playfield => {
+                *playfield = match *playfield {
+                    PlayfieldMode::Confined => PlayfieldMode::Open,
+                    PlayfieldMode::Open => PlayfieldMode::Confined,
+                };
+                settings.playfield = *playfield;
+            }
+            MainMenuButton::Difficulty => {
+                *difficulty_preset = difficulty_preset.next();
+                settings.difficulty = *difficulty_preset;
+            }
+            MainMenuButton::StartOxygen => {
+                let start_oxygen = settings.start_oxygen() + START_OXYGEN_STEP;
+                settings.start_oxygen = if start_oxygen > START_OXYGEN_MAX {
+                    START_OXYGEN_MIN
+                } else {
+                    start_oxygen
+                };
+            }
+            MainMenuButton::Training => *training_type = training_type.next(),
+            MainMenuButton::DiveIn => {
+                //a replay has to run in the mode it was recorded in
+                if let Some(replay_player) = &replay_player {
+                    *game_mode = replay_player.game_mode;
+                    *playfield = replay_player.playfield;
+                    *difficulty_preset = replay_player.difficulty_preset;
+                    training_type.0 = None;
+                }
+                info!(
+                    "starting a run in {} mode on {} difficulty",
+                    game_mode.name(),
+                    difficulty_preset.name()
+                );
+                next_state.set(GameState::Playing);
+            }
+        }
+    }
+}
+
+fn update_main_menu_labels(
+    button_query: Query<(&MainMenuButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+    game_mode: Res<GameMode>,
+    playfield: Res<PlayfieldMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    settings: Res<Settings>,
+    training_type: Res<TrainingType>,
+) {
+    for (button, children) in &button_query {
+        let label = main_menu_label(
+            *button,
+            *game_mode,
+            *playfield,
+            *difficulty_preset,
+            settings.start_oxygen(),
+            &training_type,
+        );
+        for child in children {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                if text.0 != label {
+                    text.0.clone_from(&label);
+                }
+            }
+        }
+    }
+}
+
+//state cleanup for anything tagged with a marker, used with OnExit so a screen that is left
+//takes all of its entities (and their children) with it
+fn despawn_all<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+//the player is spawned in setup before the preset is chosen, so its tank is filled here
+//a replay starts with the oxygen it was recorded with
+fn apply_difficulty_preset(
+    mut oxygen_level: Single<&mut OxygenLevel, With<Player>>,
+    mut bonus_reserve: ResMut<BonusReserve>,
+    mut displayed_oxygen: ResMut<DisplayedOxygen>,
+    mut start_oxygen: ResMut<StartOxygen>,
+    difficulty_preset: Res<DifficultyPreset>,
+    settings: Res<Settings>,
+    replay_player: Option<Res<ReplayPlayer>>,
+) {
+    start_oxygen.0 = replay_player.map_or(settings.start_oxygen(), |replay_player| {
+        replay_player.start_oxygen
+    });
+    oxygen_level.0 = difficulty_preset.start_oxygen(start_oxygen.0);
+    bonus_reserve.0 = difficulty_preset.start_reserve(start_oxygen.0);
+    displayed_oxygen.0 = oxygen_level.0;
+}
+
+//a replay runs with the setting it was recorded with
+fn start_adaptive_difficulty(
+    mut adaptive_difficulty: ResMut<AdaptiveDifficulty>,
+    settings: Res<Settings>,
+    replay_player: Option<Res<ReplayPlayer>>,
+) {
+    adaptive_difficulty.0 = replay_player.map_or(settings.adaptive_difficulty, |replay_player| {
+        replay_player.adaptive_difficulty
+    });
+}
+
+fn spawn_game_mode_label(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    training_type: Res<TrainingType>,
+) {
+    let mut label = format!(
+        "{} mode, {} difficulty",
+        game_mode.name(),
+        difficulty_preset.name()
+    );
+    if let Some(bubble_type) = training_type.0 {
+        label.push_str(&format!(
+            ", training {} bubbles ({:?} to leave)",
+            bubble_type.name(),
+            TRAINING_LEAVE_KEY
+        ));
+    }
+    commands.spawn((
+        GameModeLabel,
+        Text::new(label),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(16.0),
+            right: Val::Px(16.0),
+            ..default()
+        },
+    ));
+}
+
+//shows the oxygen and survival time in the title while playing
+fn update_window_title(
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut title_timer: ResMut<WindowTitleTimer>,
+    oxygen_level: Single<&OxygenLevel, With<Player>>,
+    survival_time: Res<SurvivalTime>,
+    game_state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    if !title_timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let title = match game_state.get() {
+        GameState::Playing => format!(
+            "{} \u{2014} O2: {:.1} \u{2014} {:.0}s",
+            WINDOW_TITLE,
+            oxygen_level.0.max(0.0),
+            survival_time.0
+        ),
+        GameState::MainMenu => WINDOW_TITLE.to_string(),
+    };
+    if window.title != title {
+        window.title = title;
+    }
+}
+
+//bevy has no window icon setting, so the icon goes straight to the winit window once loaded
+fn set_window_icon(
+    mut commands: Commands,
+    window_icon: Option<Res<WindowIcon>>,
+    images: Res<Assets<Image>>,
+    window_entity: Single<Entity, With<PrimaryWindow>>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    let Some(window_icon) = window_icon else {
+        return;
+    };
+    let Some(image) = images.get(&window_icon.0) else {
+        return;
+    };
+    let Some(winit_window) = winit_windows.get_window(*window_entity) else {
+        return;
+    };
+
+    let icon = image
+        .clone()
+        .try_into_dynamic()
+        .map_err(|error| error.to_string())
+        .and_then(|dynamic_image| {
+            let rgba_image = dynamic_image.to_rgba8();
+            let (width, height) = rgba_image.dimensions();
+            Icon::from_rgba(rgba_image.into_raw(), width, height).map_err(|error| error.to_string())
+        });
+    match icon {
+        Ok(icon) => winit_window.set_window_icon(Some(icon)),
+        Err(error) => warn!(
+            "could not use {} as window icon: {}",
+            WINDOW_ICON_PATH, error
+        ),
+    }
+    //only try once
+    commands.remove_resource::<WindowIcon>();
+}
+
+//runs before every fixed tick; outside of a run and during a hit stop the gameplay timers stand still
+fn tick_game_clock(
+    mut game_clock: ResMut<GameClock>,
+    mut hit_stop: ResMut<HitStop>,
+    game_state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    let stopped = !hit_stop.timer.tick(time.delta()).finished();
+    let delta = if *game_state.get() == GameState::Playing && !stopped {
+        time.delta().mul_f32(game_clock.time_scale)
+    } else {
+        std::time::Duration::ZERO
+    };
+    game_clock.delta = delta;
+    game_clock.elapsed += delta;
+}
+
+//the whole fixed chain waits for the clock, so a stopped tick changes nothing at all; that keeps
+//replays in sync even when the hit stop is turned off while watching one
+fn game_clock_running(game_clock: Res<GameClock>) -> bool {
+    !game_clock.delta().is_zero()
+}
+
+//blood and bomb hits are the heavy ones
+fn start_hit_stop(
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    mut hit_stop: ResMut<HitStop>,
+    settings: Res<Settings>,
+) {
+    let heavy_hit = bubble_hit_event_reader
+        .read()
+        .any(|event| matches!(event.bubble_type, BubbleType::Blood | BubbleType::Bomb));
+    if heavy_hit && settings.hit_stop_enabled() {
+        hit_stop.timer.reset();
+    }
+}
+
+fn update_survival_time(
+    mut survival_time: ResMut<SurvivalTime>,
+    is_game_over: Res<IsGameOver>,
+    game_clock: Res<GameClock>,
+) {
+    if !is_game_over.0 {
+        survival_time.0 += game_clock.delta_secs();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_replay(
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+    replay_recorder: Option<Res<ReplayRecorder>>,
+    game_rng: Res<GameRng>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    settings: Res<Settings>,
+    playfield: Res<PlayfieldMode>,
+    tutorial: Res<Tutorial>,
+    training_type: Res<TrainingType>,
+    arena_shape: Res<ArenaShape>,
+    adaptive_difficulty: Res<AdaptiveDifficulty>,
+    start_oxygen: Res<StartOxygen>,
+) {
+    for _event in game_over_event_reader.read() {
+        if tutorial.played {
+            info!("no replay is saved for a run with the tutorial");
+            continue;
+        }
+        if training_type.0.is_some() {
+            info!("no replay is saved for a training run");
+            continue;
+        }
+        if let Some(replay_recorder) = &replay_recorder {
+            save_replay_file(
+                game_rng.seed,
+                *game_mode,
+                *playfield,
+                *difficulty_preset,
+                start_oxygen.0,
+                adaptive_difficulty.0,
+                settings.density_fill,
+                settings.freeze_pauses_spawns,
+                &arena_shape,
+                &replay_recorder.frames,
+            );
+        }
+    }
+}
+
+fn log_run_events(
+    mut event_log: ResMut<EventLog>,
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    mut level_up_event_reader: EventReader<LevelUpEvent>,
+    game_over_event_reader: EventReader<GameOverEvent>,
+    off_plateau: Res<OffPlateau>,
+    near_misses: Res<NearMisses>,
+    survival_time: Res<SurvivalTime>,
+) {
+    let time = survival_time.0;
+    for event in bubble_hit_event_reader.read() {
+        event_log.push(
+            time,
+            LoggedEvent::BubbleHit {
+                bubble_type: event.bubble_type,
+                value: event.value,
+            },
+        );
+    }
+    for event in level_up_event_reader.read() {
+        event_log.push(time, LoggedEvent::LevelUp(event.level));
+    }
+    while event_log.near_misses_seen < near_misses.0 {
+        event_log.near_misses_seen += 1;
+        event_log.push(time, LoggedEvent::NearMiss);
+    }
+    //the warning is cleared at the moment of death, that is not the player leaving the zone
+    if off_plateau.is_changed() && game_over_event_reader.is_empty() {
+        event_log.push(time, LoggedEvent::OffPlateau(off_plateau.0));
+    }
+}
+
+fn save_event_log(
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+    mut event_log: ResMut<EventLog>,
+    survival_time: Res<SurvivalTime>,
+    game_rng: Res<GameRng>,
+    game_mode: Res<GameMode>,
+) {
     for _event in game_over_event_reader.read() {
-        info!("Game Over - Thanks for dying :-)");
-        //despawn all running AudioPlayers
-        for entity in audio_players.iter() {
+        let cause = event_log.death_cause(survival_time.0);
+        event_log.push(survival_time.0, LoggedEvent::Death(cause));
+
+        let mut contents = format!(
+            "bubble_hell_event_log\nseed {}\nmode {}\n",
+            game_rng.seed,
+            game_mode.name()
+        );
+        for (time, event) in &event_log.entries {
+            contents.push_str(&format!("{:.2} {}\n", time, event.describe()));
+        }
+        match std::fs::write(EVENT_LOG_FILE_PATH, contents) {
+            Ok(()) => info!(
+                "event log with {} entries saved to {}",
+                event_log.entries.len(),
+                EVENT_LOG_FILE_PATH
+            ),
+            Err(error) => warn!("could not save event log: {}", error),
+        }
+    }
+}
+
+fn oxygen_bonus_points(bonus_reserve: f32) -> u32 {
+    (bonus_reserve.max(0.0) * OXYGEN_BONUS_POINTS_PER_UNIT).round() as u32
+}
+
+//oxygen that is left in the reserve when the run ends is not wasted, it is added to the score
+//the tank is always empty by then, a run only ends when it runs out
+fn convert_oxygen_to_score(
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+    bonus_reserve: Res<BonusReserve>,
+    mut score: ResMut<Score>,
+    mut oxygen_bonus: ResMut<OxygenBonus>,
+) {
+    for _event in game_over_event_reader.read() {
+        oxygen_bonus.0 = oxygen_bonus_points(bonus_reserve.0);
+        score.0 += oxygen_bonus.0;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_best_survival_time(
+    mut game_over_event_reader: EventReader<GameOverEvent>,
+    survival_time: Res<SurvivalTime>,
+    mut settings: ResMut<Settings>,
+    playfield: Res<PlayfieldMode>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    start_oxygen: Res<StartOxygen>,
+    god_mode_used: Res<GodModeUsed>,
+    replay_player: Option<Res<ReplayPlayer>>,
+) {
+    for _event in game_over_event_reader.read() {
+        //a replay file can be someone else's run
+        if replay_player.is_some() {
+            continue;
+        }
+        //nothing costs oxygen with god mode on (that includes training runs), so the time says nothing
+        if god_mode_used.0 {
+            continue;
+        }
+        //bubbles off screen interact with stale positions, see move_bubbles
+        if settings.offscreen_throttling {
+            continue;
+        }
+        //only borrowed mutably for a new best, so save_settings_on_change doesn't write the file
+        //after every run
+        let best_time = settings
+            .best_survival_time(*game_mode, *playfield, *difficulty_preset, start_oxygen.0)
+            .unwrap_or(0.0);
+        if survival_time.0 <= best_time {
+            continue;
+        }
+        settings.best_times.insert(
+            Settings::best_time_key(*game_mode, *playfield, *difficulty_preset, start_oxygen.0),
+            survival_time.0,
+        );
+        info!(
+            "new best time in {} mode on the {} playfield on {} difficulty with {:.0} oxygen: {:.1}s",
+            game_mode.name(),
+            playfield.name(),
+            difficulty_preset.name(),
+            start_oxygen.0,
+            survival_time.0
+        );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<Settings>,
+) {
+    // create a player entity and the camera
+    // we need to do this in setup because the player_movement requires the an entity with
+    // a player component Tag and a Transform
+    commands
+        .spawn((
+            Player,
+            OxygenLevel(PLAYER_OXYGEN_START_SUPPLY),
+            Transform::default(),
+            InheritedVisibility::VISIBLE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Flashlight,
+                SpotLight {
+                    color: GREY.into(),
+                    intensity: 500_000.0,
+                    range: 5.0,
+                    radius: 10.0,
+                    inner_angle: FLASHLIGHT_INNER_ANGLE,
+                    outer_angle: FLASHLIGHT_OUTER_ANGLE,
+                    shadows_enabled: true,
+                    ..Default::default()
+                },
+                flashlight_transform(Vec3::Z),
+            ));
+
+            parent.spawn((
+                SpotLight {
+                    color: WHITE.into(),
+                    intensity: 100_000.0,
+                    range: GAME_OVER_SCREEN_DISTANCE * 2.0,
+                    radius: 10.0,
+                    inner_angle: GAME_OVER_LIGHT_INNER_ANGLE,
+                    outer_angle: GAME_OVER_LIGHT_OUTER_ANGLE,
+                    ..Default::default()
+                },
+                camera_local_transform(CAMERA_DISTANCE),
+            ));
+        });
+
+    let camera_transform = camera_local_transform(settings.camera_distance());
+    commands.spawn((
+        Camera3d::default(),
+        //the camera looks down at the player, so its left and right are the screen's left and right
+        SpatialListener::new(AUDIO_LISTENER_EAR_GAP),
+        CameraFollow {
+            position: camera_transform.translation,
+            look_ahead: Vec3::ZERO,
+        },
+        camera_transform,
+    ));
+
+    // create light
+    commands.insert_resource(AmbientLight {
+        color: ROYAL_BLUE.into(),
+        brightness: 100.0,
+    });
+
+    /*
+    //FOR DEBUGGING
+    commands.insert_resource(AmbientLight {
+        color: WHITE.into(),
+        brightness: 10_000.0,
+    });
+     */
+
+    // create flag resources
+    commands.insert_resource(IsGameOver(false));
+
+    commands.insert_resource(CollectionStats::default());
+
+    commands.insert_resource(BonusReserve::default());
+
+    commands.insert_resource(OxygenTrail {
+        mesh: meshes.add(Sphere::new(OXYGEN_TRAIL_PARTICLE_RADIUS)),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgba(0.8, 0.9, 1.0, 0.4),
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        }),
+        pending_particles: 0.0,
+    });
+
+    commands.insert_resource(RushStreaks {
+        mesh: meshes.add(Cuboid::new(0.05, 0.05, RUSH_STREAK_LENGTH)),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgba(0.6, 0.5, 1.0, 0.5),
+            emissive: LinearRgba::rgb(0.4, 0.3, 1.0),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+        timer: Timer::from_seconds(RUSH_STREAK_INTERVAL, TimerMode::Repeating),
+    });
+
+    commands.insert_resource(GameMode::default());
+    commands.insert_resource(settings.difficulty);
+    commands.insert_resource(settings.playfield);
+    commands.insert_resource(SurvivalTime::default());
+    commands.spawn((
+        ControlsHint,
+        Text::default(),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(16.0),
+            right: Val::Px(16.0),
+            ..default()
+        },
+    ));
+
+    commands.spawn((
+        BubbleLegend,
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(30.0),
+            left: Val::Px(16.0),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+    ));
+
+    commands.spawn((
+        OffPlateauBorder,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            border: UiRect::all(Val::Px(OFF_PLATEAU_BORDER_WIDTH)),
+            ..default()
+        },
+        BorderColor(RED.with_alpha(OFF_PLATEAU_BORDER_MAX_ALPHA).into()),
+        Visibility::Hidden,
+    ));
+
+    commands.spawn((
+        TutorialText,
+        Text::default(),
+        TextFont {
+            font_size: 28.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+        Visibility::Hidden,
+    ));
+
+    commands.spawn((
+        QuitPrompt,
+        Text::new("Quit? Press the quit key again to quit, any other key to keep playing"),
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(45.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+        Visibility::Hidden,
+    ));
+
+    commands.spawn((
+        BubbleInspectorText,
+        Text::default(),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            right: Val::Px(16.0),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+
+    commands.spawn((
+        SpawnCircleLabel,
+        Text::default(),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(YELLOW.into()),
+        Node {
+            position_type: PositionType::Absolute,
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+
+    commands.spawn((
+        SpawnTuningPanel,
+        Text::default(),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            left: Val::Px(16.0),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+
+    // bonus reserve bar in the top left corner
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(16.0),
+                left: Val::Px(16.0),
+                width: Val::Px(160.0),
+                height: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BonusReserveBar,
+                Node {
+                    width: Val::Percent(0.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(LIGHT_SKY_BLUE.into()),
+            ));
+        });
+
+    // dash cooldown below the bonus reserve bar
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(32.0),
+                left: Val::Px(16.0),
+                width: Val::Px(160.0),
+                height: Val::Px(6.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                DashIndicator,
+                Node {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(WHITE.into()),
+            ));
+        });
+
+    // progress towards the next level below the dash cooldown
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(44.0),
+            left: Val::Px(16.0),
+            width: Val::Px(160.0),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(4.0),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                LevelText,
+                Text::default(),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Percent(100.0),
+                        height: Val::Px(6.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+                ))
+                .with_children(|parent| {
+                    let mut flash = Timer::from_seconds(LEVEL_UP_FLASH_DURATION, TimerMode::Once);
+                    flash.tick(flash.duration());
+                    parent.spawn((
+                        LevelBar { flash },
+                        Node {
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        BackgroundColor(GOLD.into()),
+                    ));
+                });
+        });
+
+    commands.insert_resource(FreezeHalo {
+        mesh: meshes.add(Torus::new(PLAYER_RADIUS * 1.2, PLAYER_RADIUS * 1.5)),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgba(0.7, 0.9, 1.0, 0.6),
+            emissive: LinearRgba::rgb(0.3, 0.6, 1.0),
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        }),
+    });
+
+    // freeze indicator at the bottom center, only visible while frozen
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(32.0),
+            width: Val::Percent(100.0),
+            height: Val::Px(FREEZE_INDICATOR_SIZE),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                FreezeIndicator,
+                Node {
+                    width: Val::Px(FREEZE_INDICATOR_SIZE),
+                    height: Val::Px(FREEZE_INDICATOR_SIZE),
+                    border: UiRect::all(Val::Px(4.0)),
+                    ..default()
+                },
+                BorderColor(LIGHT_CYAN.into()),
+                BorderRadius::MAX,
+                Visibility::Hidden,
+            ));
+        });
+
+    info!("init loading assets...");
+
+    //load gltF files; the bubble models come from the manifest
+    let bubble_manifest = load_bubble_manifest();
+    let mut assets_loading = HashMap::from([
+        ("player_character".into(), asset_server.load("Player.glb")),
+        ("alge".into(), asset_server.load("Alge.glb")),
+        ("sand".into(), asset_server.load("Sand.glb")),
+        ("plateau".into(), asset_server.load("Plateau.glb")),
+        ("gauge".into(), asset_server.load("Gauge.glb")),
+    ]);
+    for (bubble_type, entry) in &bubble_manifest {
+        assets_loading.insert(
+            format!("{}{}", BUBBLE_ASSET_PREFIX, bubble_type.name()),
+            asset_server.load(&entry.model),
+        );
+    }
+    commands.insert_resource(AssetsLoadingGltf(assets_loading));
+
+    commands.insert_resource(BubbleEffects::from_manifest(&bubble_manifest));
+
+    //store material mapping for the bubbles
+    let light_colors: HashMap<BubbleType, Color> = bubble_manifest
+        .iter()
+        .map(|(bubble_type, entry)| {
+            let (red, green, blue) = entry.color;
+            (*bubble_type, Color::srgb_u8(red, green, blue))
+        })
+        .collect();
+    let mut bubble_models = BubbleModels {
+        scenes: HashMap::new(),
+        placeholders: HashMap::new(),
+        light_colors,
+        //the bubbles are scaled to their radius, so unit meshes are as wide as the bubble
+        shadow_mesh: meshes.add(Circle::new(1.0)),
+        shadow_materials: HashMap::new(),
+        orb_mesh: meshes.add(Sphere::new(1.0)),
+        orb_materials: HashMap::new(),
+        silhouettes: bubble_manifest
+            .iter()
+            .map(|(bubble_type, entry)| (*bubble_type, entry.silhouette.parts(&mut meshes)))
+            .collect(),
+    };
+    for bubble_type in BubbleType::ALL {
+        for palette in ColorPalette::ALL {
+            let material = materials.add(StandardMaterial {
+                base_color: bubble_color(bubble_type, palette, &bubble_models)
+                    .with_alpha(BUBBLE_ORB_ALPHA),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            });
+            bubble_models
+                .orb_materials
+                .insert((bubble_type, palette), material);
+            //a dark tint of the bubble color, unlit so it looks the same everywhere on the floor
+            let shadow_color = bubble_color(bubble_type, palette, &bubble_models).to_linear() * 0.3;
+            let material = materials.add(StandardMaterial {
+                base_color: Color::from(shadow_color).with_alpha(BUBBLE_SHADOW_ALPHA),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            });
+            bubble_models
+                .shadow_materials
+                .insert((bubble_type, palette), material);
+        }
+    }
+    commands.insert_resource(bubble_models);
+
+    info!("player character should load now...");
+
+    //play music
+    spawn_music(&mut commands, &asset_server);
+
+    commands.insert_resource(WindowIcon(asset_server.load(WINDOW_ICON_PATH)));
+
+    commands.insert_resource(BubbleHitAudioSource(
+        asset_server.load("collect bubble.flac"),
+    ));
+}
+
+//plateau_scale is PlateauSize::scale
+fn is_off_plateau(player_translation: Vec3, arena_shape: &ArenaShape, plateau_scale: f32) -> bool {
+    //if the player is ever attached anywhere this needs changing
+    let player_coordinates_2d = Vec2::from_array([player_translation.x, player_translation.z]);
+
+    //info!("player translation 2d: {:?}", player_coordinates_2d);
+
+    !arena_shape.point_inside_arena(player_coordinates_2d / plateau_scale)
+}
+
+//whether the player pays the off-plateau penalty at the given position; that is off the
+//plateau normally and on it while the arena is inverted (never in zen mode or on the open playfield)
+fn in_drain_zone(
+    player_translation: Vec3,
+    game_mode: GameMode,
+    playfield: PlayfieldMode,
+    arena_shape: &ArenaShape,
+    plateau_scale: f32,
+    inverted: bool,
+) -> bool {
+    game_mode != GameMode::Zen
+        && playfield == PlayfieldMode::Confined
+        && is_off_plateau(player_translation, arena_shape, plateau_scale) != inverted
+}
+
+//oxygen lost per second at the given position
+//being in the drain zone effecgively doubles the oxygen loss
+fn oxygen_drain_rate(
+    player_translation: Vec3,
+    game_mode: GameMode,
+    playfield: PlayfieldMode,
+    difficulty_preset: DifficultyPreset,
+    arena_shape: &ArenaShape,
+    plateau_scale: f32,
+    inverted: bool,
+) -> f32 {
+    let mut drain_rate = PLAYER_OXYGEN_DECREASE_PER_SECOND;
+    if in_drain_zone(
+        player_translation,
+        game_mode,
+        playfield,
+        arena_shape,
+        plateau_scale,
+        inverted,
+    ) {
+        drain_rate += PLAYER_OXYGEN_DECREASE_PER_SECOND;
+    }
+    if game_mode == GameMode::Hardcore {
+        drain_rate *= HARDCORE_DRAIN_MULTIPLIER;
+    }
+    drain_rate * difficulty_preset.drain_multiplier()
+}
+
+fn clear_old_sounds(
+    mut commands: Commands,
+    bubble_hit_sounds: Query<(&AudioSink, Entity), With<BubbleHitSound>>,
+) {
+    for (sound, entity) in bubble_hit_sounds.iter() {
+        // the audio sink being "empty" means there are not sound effects in it's internal queue for it to play
+        // this happens when a sound is only played once - like the hit effects
+        if sound.empty() {
             commands.entity(entity).despawn();
+            //info!("despawning sound sink");
+        }
+    }
+}
+
+//a quiet blip from where a bubble appeared, so bubbles spawning off screen don't arrive unannounced
+//at most one harmful and one harmless blip per frame, on top of the limit for all of them
+fn play_spawn_sounds(
+    mut commands: Commands,
+    new_bubbles: Query<(&Transform, &Bubble), Added<Bubble>>,
+    spawn_sounds: Query<(), With<SpawnSound>>,
+    bubble_hit_audio_source: Res<BubbleHitAudioSource>,
+    settings: Res<Settings>,
+) {
+    let mut playing = spawn_sounds.iter().count();
+    let mut played_harmful = false;
+    let mut played_harmless = false;
+    for (transform, bubble) in &new_bubbles {
+        if playing >= SPAWN_SOUND_MAX_CONCURRENT {
+            return;
+        }
+        let harmful = bubble.bubble_type.is_harmful();
+        let played = if harmful {
+            &mut played_harmful
+        } else {
+            &mut played_harmless
+        };
+        if *played {
+            continue;
+        }
+        *played = true;
+        playing += 1;
+
+        commands.spawn((
+            SpawnSound,
+            //cleaned up by clear_old_sounds like the hit sounds
+            BubbleHitSound,
+            AudioPlayer::new(bubble_hit_audio_source.0.clone()),
+            PlaybackSettings {
+                mode: PlaybackMode::Once,
+                volume: Volume::new(settings.sfx_volume * SPAWN_SOUND_VOLUME),
+                speed: if harmful {
+                    SPAWN_SOUND_SPEED_HARMFUL
+                } else {
+                    SPAWN_SOUND_SPEED_HARMLESS
+                },
+                spatial: settings.spatial_audio,
+                ..default()
+            },
+            //only used for panning when spatial audio is on
+            Transform::from_translation(transform.translation),
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn reduce_oxygen_level(
+    mut oxygen_level: Single<&mut OxygenLevel>,
+    game_clock: Res<GameClock>,
+    mut game_over_event_writer: EventWriter<GameOverEvent>,
+    mut is_game_over: ResMut<IsGameOver>,
+    player_transform: Single<&Transform, With<Player>>,
+    game_mode: Res<GameMode>,
+    arena_shape: Res<ArenaShape>,
+    plateau_size: Res<PlateauSize>,
+    god_mode: Res<GodMode>,
+    mut off_plateau: ResMut<OffPlateau>,
+    invert_arena: Res<InvertArena>,
+    playfield: Res<PlayfieldMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+) {
+    if is_game_over.0 {
+        off_plateau.set_if_neq(OffPlateau(false));
+        return;
+    }
+
+    off_plateau.set_if_neq(OffPlateau(in_drain_zone(
+        player_transform.translation,
+        *game_mode,
+        *playfield,
+        &arena_shape,
+        plateau_size.scale,
+        invert_arena.0,
+    )));
+
+    if oxygen_level.0 <= 0.0_f32 {
+        game_over_event_writer.send(GameOverEvent {});
+        is_game_over.0 = true;
+        //nothing updates this after the game over, so it must not stay set
+        off_plateau.set_if_neq(OffPlateau(false));
+        return;
+    } else if !god_mode.0 {
+        oxygen_level.0 -= game_clock.delta_secs()
+            * oxygen_drain_rate(
+                player_transform.translation,
+                *game_mode,
+                *playfield,
+                *difficulty_preset,
+                &arena_shape,
+                plateau_size.scale,
+                invert_arena.0,
+            );
+    }
+
+    //the center of the plateau is a safe spot, but only up to a half full tank
+    //(and not while the arena is inverted, then the center is the dangerous part, or in hardcore)
+    if *game_mode != GameMode::Hardcore
+        && !invert_arena.0
+        && player_transform.translation.xz().length() < PLATEAU_REGEN_RADIUS
+        && oxygen_level.0 < PLATEAU_REGEN_CAP
+    {
+        oxygen_level.0 = (oxygen_level.0 + PLATEAU_REGEN_PER_SECOND * game_clock.delta_secs())
+            .min(PLATEAU_REGEN_CAP);
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn player_effects(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    player_query: Single<(&mut Transform, &mut OxygenLevel), With<Player>>,
+    player_model_query: Option<Single<&mut Transform, (With<PlayerModel>, Without<Player>)>>,
+    game_clock: Res<GameClock>,
+    is_game_over: Res<IsGameOver>,
+    active_effects: Res<ActiveEffects>,
+    current: Res<Current>,
+    replay_recorder: Option<ResMut<ReplayRecorder>>,
+    replay_player: Option<ResMut<ReplayPlayer>>,
+    mut movement_input: ResMut<MovementInput>,
+    mut player_velocity: ResMut<PlayerVelocity>,
+    mut dash: ResMut<Dash>,
+    mut panic_clear: ResMut<PanicClear>,
+    god_mode: Res<GodMode>,
+) {
+    let mut delta_secs = game_clock.delta_secs();
+    let mut movement: Vec2 = Vec2::new(0.0, 0.0);
+    let mut dash_pressed = false;
+    let mut panic_clear_pressed = false;
+    if let Some(mut replay_player) = replay_player {
+        //play back the recorded input instead of reading the keyboard
+        if let Some(frame) = replay_player.frames.get(replay_player.next_frame).copied() {
+            movement = frame.movement;
+            delta_secs = frame.delta_secs;
+            dash_pressed = frame.dash;
+            panic_clear_pressed = frame.panic_clear;
+            replay_player.next_frame += 1;
+        }
+    } else {
+        //held rather than just pressed, fixed updates can miss a single frame press
+        dash_pressed = keyboard_input.pressed(settings.movement_bindings.dash);
+        panic_clear_pressed = keyboard_input.pressed(settings.movement_bindings.panic_clear);
+        if keyboard_input.pressed(settings.movement_bindings.up) {
+            movement += Vec2::new(0.0, -1.0);
+        }
+        if keyboard_input.pressed(settings.movement_bindings.down) {
+            movement += Vec2::new(0.0, 1.0);
+        }
+        if keyboard_input.pressed(settings.movement_bindings.left) {
+            movement += Vec2::new(-1.0, 0.0);
+        }
+        if keyboard_input.pressed(settings.movement_bindings.right) {
+            movement += Vec2::new(1.0, 0.0);
+        }
+    }
+
+    if let Some(mut replay_recorder) = replay_recorder {
+        replay_recorder.frames.push(ReplayFrame {
+            movement,
+            delta_secs,
+            dash: dash_pressed,
+            panic_clear: panic_clear_pressed,
+        });
+    }
+    panic_clear.held = panic_clear.pressed;
+    panic_clear.pressed = panic_clear_pressed;
+
+    let (mut player_transform, mut oxygen_level) = player_query.into_inner();
+    dash.segment_start = player_transform.translation;
+
+    //block input after game over or when frozen; a dash ends with the freeze, otherwise
+    //check_collisions keeps popping along its path with the wider dash radius
+    if is_game_over.0 || active_effects.is_active(EffectKind::Freeze) {
+        movement_input.0 = Vec2::ZERO;
+        player_velocity.0 = Vec2::ZERO;
+        let remaining = dash.active.remaining();
+        dash.active.tick(remaining);
+        return;
+    }
+    movement_input.0 = movement;
+
+    let delta = std::time::Duration::from_secs_f32(delta_secs);
+    dash.active.tick(delta);
+    dash.cooldown.tick(delta);
+    if dash_pressed && dash.can_dash(oxygen_level.0, god_mode.0) && movement != Vec2::ZERO {
+        oxygen_level.0 -= Dash::oxygen_cost(god_mode.0);
+        dash.direction = movement.normalize();
+        dash.active.reset();
+        dash.cooldown.reset();
+    }
+
+    let speed_multiplier = if active_effects.is_active(EffectKind::Rush) {
+        RUSH_SPEED_MULTIPLIER
+    } else {
+        1.0
+    };
+    if dash.is_active() {
+        //a dash is fast enough already, a rush only adds a little to it
+        movement = delta_secs
+            * DASH_SPEED
+            * speed_multiplier.min(RUSH_MAX_DASH_MULTIPLIER)
+            * dash.direction;
+        player_transform.translation.x += movement.x;
+        player_transform.translation.z += movement.y;
+    } else if Vec2::length_squared(movement) > 0.0 {
+        movement =
+            delta_secs * PLAYER_MOVEMENT_SPEED * speed_multiplier * Vec2::normalize(movement);
+        player_transform.translation.x += movement.x;
+        player_transform.translation.z += movement.y;
+
+        //turn the model towards where we are going
+        if let Some(player_model_query) = player_model_query {
+            let mut player_model_transform = player_model_query.into_inner();
+            let target_rotation =
+                Quat::from_rotation_y(movement.x.atan2(movement.y) + PLAYER_MODEL_YAW_OFFSET);
+            let turn_amount = 1.0 - (-PLAYER_TURN_SPEED * delta_secs).exp();
+            player_model_transform.rotation = player_model_transform
+                .rotation
+                .slerp(target_rotation, turn_amount);
+        }
+    }
+
+    //the current keeps pushing even when the player stands still
+    let drift = current.direction * current.strength * delta_secs;
+    player_transform.translation.x += drift.x;
+    player_transform.translation.z += drift.y;
+
+    //the camera looks ahead with this instead of the distance moved between rendered frames,
+    //which jumps around as the number of fixed updates per frame changes
+    if delta_secs > 0.0 {
+        player_velocity.0 = (player_transform.translation - dash.segment_start).xz() / delta_secs;
+    }
+}
+
+fn smooth_displayed_oxygen(
+    mut displayed_oxygen: ResMut<DisplayedOxygen>,
+    oxygen_level: Single<&OxygenLevel, With<Player>>,
+    time: Res<Time>,
+) {
+    let amount = 1.0 - (-OXYGEN_DISPLAY_SMOOTHING * time.delta_secs()).exp();
+    displayed_oxygen.0 = displayed_oxygen.0.lerp(oxygen_level.0, amount);
+}
+
+//the gauge needle only exists once the player model has loaded
+fn update_oxygen_gauge(
+    zeiger_query: Option<Single<&mut Transform, With<Zeiger>>>,
+    displayed_oxygen: Res<DisplayedOxygen>,
+) {
+    if let Some(zeiger_query) = zeiger_query {
+        let mut zeiger_transform = zeiger_query.into_inner();
+        let mut angle = (displayed_oxygen.0 / PLAYER_OXYGEN_START_SUPPLY).clamp(0.0, 1.0);
+        angle = (angle - 0.5) * 2.0;
+        angle *= -3.0 * PI / 4.0;
+        zeiger_transform.rotation = Quat::from_axis_angle(Vec3::Y, angle);
+    }
+}
+
+//eases the camera towards its offset from the player, looking a bit ahead in the direction of travel
+fn follow_camera(
+    camera_query: Single<(&mut Transform, &mut CameraFollow), With<Camera3d>>,
+    player_transform: Single<&Transform, (With<Player>, Without<Camera3d>)>,
+    player_velocity: Res<PlayerVelocity>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let (mut camera_transform, mut camera_follow) = camera_query.into_inner();
+    let delta_secs = time.delta_secs();
+    if delta_secs <= 0.0 {
+        return;
+    }
+
+    let player_translation = player_transform.translation;
+    let player_velocity = Vec3::new(player_velocity.0.x, 0.0, player_velocity.0.y);
+
+    //only look ahead as far as the player is actually moving; drift from the current counts too
+    //with reduced motion the camera stays centered on the player
+    let speed_fraction = (player_velocity.length() / PLAYER_MOVEMENT_SPEED).min(1.0);
+    let target_look_ahead = if settings.camera_motion_enabled() {
+        player_velocity.normalize_or_zero() * speed_fraction * CAMERA_LOOK_AHEAD_DISTANCE
+    } else {
+        Vec3::ZERO
+    };
+
+    let follow_amount = 1.0 - (-CAMERA_FOLLOW_STIFFNESS * delta_secs).exp();
+    camera_follow.look_ahead = camera_follow
+        .look_ahead
+        .lerp(target_look_ahead, follow_amount);
+    let target_position = player_translation
+        + camera_follow.look_ahead
+        + camera_local_transform(settings.camera_distance()).translation;
+    camera_follow.position = camera_follow.position.lerp(target_position, follow_amount);
+
+    //the angle stays fixed, only the position follows
+    camera_transform.translation = camera_follow.position;
+}
+
+//scrolling up moves the camera closer, follow_camera eases it to the new distance
+//the spawn circle does not depend on the view, so zooming out shows bubbles earlier
+fn zoom_camera(
+    mut mouse_wheel_event_reader: EventReader<MouseWheel>,
+    mut settings: ResMut<Settings>,
+) {
+    let lines: f32 = mouse_wheel_event_reader
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / MOUSE_WHEEL_PIXELS_PER_LINE,
+        })
+        .sum();
+    if lines == 0.0 {
+        return;
+    }
+
+    let camera_distance = (settings.camera_distance() - lines * CAMERA_ZOOM_STEP)
+        .clamp(CAMERA_MIN_DISTANCE, CAMERA_MAX_DISTANCE);
+    settings.camera_distance = camera_distance;
+}
+
+//local transform of the flashlight for a player facing <facing> (on the x-z-plane)
+fn flashlight_transform(facing: Vec3) -> Transform {
+    Transform::from_xyz(0.0, FLASHLIGHT_HEIGHT, 0.0).looking_at(facing * FLASHLIGHT_REACH, Vec3::Y)
+}
+
+//the player model turns towards the movement direction in player_effects, the flashlight follows it
+fn aim_flashlight(
+    player_model: Option<Single<&Transform, With<PlayerModel>>>,
+    mut flashlight: Single<&mut Transform, (With<Flashlight>, Without<PlayerModel>)>,
+) {
+    let Some(player_model) = player_model else {
+        return;
+    };
+    let facing = player_model.rotation * Quat::from_rotation_y(-PLAYER_MODEL_YAW_OFFSET) * Vec3::Z;
+    **flashlight = flashlight_transform(facing);
+}
+
+//the player model deflates and glows red as the oxygen runs out
+fn update_player_appearance(
+    player_model_query: Option<Single<(Entity, &mut Transform), With<PlayerModel>>>,
+    displayed_oxygen: Res<DisplayedOxygen>,
+    children_query: Query<&Children>,
+    material_query: Query<&MeshMaterial3d<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut applied_deflation: Local<f32>,
+) {
+    //the model is only there once its asset has loaded
+    let Some(player_model_query) = player_model_query else {
+        return;
+    };
+
+    let fullness = (displayed_oxygen.0 / PLAYER_OXYGEN_START_SUPPLY).clamp(0.0, 1.0);
+    let deflation = 1.0 - fullness;
+
+    let (player_model_entity, mut player_model_transform) = player_model_query.into_inner();
+    player_model_transform.scale =
+        Vec3::splat(ASSET_SCALE * PLAYER_DEFLATED_SCALE.lerp(1.0, fullness));
+
+    //only touch the materials when the glow changed noticeably
+    if (deflation - *applied_deflation).abs() < 0.01 {
+        return;
+    }
+    *applied_deflation = deflation;
+
+    let emissive = LinearRgba::from(RED) * deflation * PLAYER_DEFLATED_EMISSIVE;
+    for descendant in children_query.iter_descendants(player_model_entity) {
+        if let Ok(material_handle) = material_query.get(descendant) {
+            if let Some(material) = materials.get_mut(material_handle) {
+                material.emissive = emissive;
+            }
+        }
+    }
+}
+
+//more harmful bubbles while the tank is full, more regular ones when it is nearly empty
+//hardcore never adapts
+fn update_spawn_bias(
+    mut spawn_bias: ResMut<SpawnBias>,
+    oxygen_level: Single<&OxygenLevel, With<Player>>,
+    adaptive_difficulty: Res<AdaptiveDifficulty>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+) {
+    let adaptive_bias = if adaptive_difficulty.0 && *game_mode != GameMode::Hardcore {
+        let fullness = (oxygen_level.0 / PLAYER_OXYGEN_START_SUPPLY).clamp(0.0, 1.0);
+        (fullness * 2.0 - 1.0) * ADAPTIVE_MAX_BIAS
+    } else {
+        0.0
+    };
+    spawn_bias.0 = adaptive_bias + difficulty_preset.harmful_bias();
+}
+
+//alternates between themes and gaps of normal spawning; zen mode has no themes
+fn advance_spawn_theme(
+    mut spawn_theme: ResMut<SpawnTheme>,
+    mut game_rng: ResMut<GameRng>,
+    mut spawn_theme_started_event_writer: EventWriter<SpawnThemeStartedEvent>,
+    is_game_over: Res<IsGameOver>,
+    game_mode: Res<GameMode>,
+    game_clock: Res<GameClock>,
+) {
+    if is_game_over.0 || *game_mode == GameMode::Zen {
+        return;
+    }
+    if !spawn_theme.timer.tick(game_clock.delta()).finished() {
+        return;
+    }
+
+    let rng = &mut game_rng.rng;
+    let duration = if spawn_theme.current.is_some() {
+        spawn_theme.current = None;
+        rng.gen_range(SPAWN_THEME_MIN_GAP..SPAWN_THEME_MAX_GAP)
+    } else {
+        let bubble_type = SPAWN_THEME_TYPES[rng.gen_range(0..SPAWN_THEME_TYPES.len())];
+        spawn_theme.current = Some(bubble_type);
+        spawn_theme_started_event_writer.send(SpawnThemeStartedEvent { bubble_type });
+        SPAWN_THEME_DURATION
+    };
+    spawn_theme.timer = Timer::from_seconds(duration, TimerMode::Once);
+}
+
+//pops every harmful bubble near the player when the panic button is pressed; it refuses while
+//cooling down or when it would leave too little oxygen, and says why above the player
+fn use_panic_clear(
+    mut commands: Commands,
+    mut panic_clear: ResMut<PanicClear>,
+    player_query: Single<(&Transform, &mut OxygenLevel), With<Player>>,
+    bubble_query: Query<(Entity, &Transform, &Bubble), Without<Player>>,
+    game_clock: Res<GameClock>,
+    god_mode: Res<GodMode>,
+) {
+    panic_clear.cooldown.tick(game_clock.delta());
+    if !panic_clear.pressed || panic_clear.held {
+        return;
+    }
+
+    let (player_transform, mut oxygen_level) = player_query.into_inner();
+    let player_translation = player_transform.translation;
+    let oxygen_cost = PanicClear::oxygen_cost(god_mode.0);
+    let refusal = if !panic_clear.cooldown.finished() {
+        Some(format!(
+            "Panic clear in {:.0}s",
+            panic_clear.cooldown.remaining_secs().ceil()
+        ))
+    } else if !god_mode.0 && oxygen_level.0 - oxygen_cost < PANIC_CLEAR_MIN_OXYGEN_LEFT {
+        Some("Not enough oxygen".to_string())
+    } else {
+        None
+    };
+    if let Some(refusal) = refusal {
+        spawn_floating_text(&mut commands, player_translation, refusal, GRAY.into());
+        return;
+    }
+
+    let mut cleared = 0;
+    for (bubble_entity, bubble_transform, bubble) in &bubble_query {
+        if bubble.bubble_type.is_harmful()
+            && bubble_transform
+                .translation
+                .xz()
+                .distance(player_translation.xz())
+                < PANIC_CLEAR_RADIUS
+        {
+            commands.entity(bubble_entity).despawn_recursive();
+            cleared += 1;
+        }
+    }
+    oxygen_level.0 -= oxygen_cost;
+    panic_clear.cooldown.reset();
+    info!("panic clear removed {} bubbles", cleared);
+    spawn_floating_text(
+        &mut commands,
+        player_translation,
+        format!("Cleared {}", cleared),
+        WHITE.into(),
+    );
+}
+
+//rests once a wave has spawned all its bubbles, then starts a bigger and faster one
+fn advance_waves(
+    mut wave: ResMut<Wave>,
+    mut spawn_timer: ResMut<BubbleSpawnTimer>,
+    mut wave_started_event_writer: EventWriter<WaveStartedEvent>,
+    mut wave_cleared_event_writer: EventWriter<WaveClearedEvent>,
+    is_game_over: Res<IsGameOver>,
+    game_clock: Res<GameClock>,
+) {
+    if is_game_over.0 || wave.bubbles_remaining > 0 {
+        return;
+    }
+
+    if !wave.resting {
+        info!("wave {} cleared", wave.number);
+        wave_cleared_event_writer.send(WaveClearedEvent {
+            number: wave.number,
+        });
+        wave.resting = true;
+    }
+
+    if wave.rest_timer.tick(game_clock.delta()).just_finished() {
+        wave.number += 1;
+        wave.bubbles_remaining = WAVE_FIRST_BUDGET + WAVE_BUDGET_INCREASE * (wave.number - 1);
+        wave.rest_timer = Timer::from_seconds(WAVE_REST_DURATION, TimerMode::Once);
+        wave.resting = false;
+
+        let spawn_interval = (BUBBLE_SPAWN_INTERVAL
+            * WAVE_SPAWN_INTERVAL_FACTOR.powi(wave.number as i32 - 1))
+        .max(WAVE_MINIMUM_SPAWN_INTERVAL);
+        spawn_timer
+            .0
+            .set_duration(std::time::Duration::from_secs_f32(spawn_interval));
+        spawn_timer.0.reset();
+
+        info!(
+            "wave {} started: {} bubbles every {:.2}s",
+            wave.number, wave.bubbles_remaining, spawn_interval
+        );
+        wave_started_event_writer.send(WaveStartedEvent {
+            number: wave.number,
+        });
+    }
+}
+
+fn show_wave_banner(
+    mut commands: Commands,
+    mut wave_started_event_reader: EventReader<WaveStartedEvent>,
+    mut wave_cleared_event_reader: EventReader<WaveClearedEvent>,
+    mut spawn_theme_started_event_reader: EventReader<SpawnThemeStartedEvent>,
+    mut banner_query: Query<(Entity, &mut WaveBanner)>,
+    time: Res<Time>,
+) {
+    //themes only get a small banner, the waves are the bigger news
+    let mut banner_text = None;
+    let mut font_size = 32.0;
+    for event in spawn_theme_started_event_reader.read() {
+        banner_text = Some(format!("{} bubbles incoming", event.bubble_type.name()));
+        font_size = 20.0;
+    }
+    for event in wave_cleared_event_reader.read() {
+        banner_text = Some(format!("Wave {} cleared - catch your breath", event.number));
+        font_size = 32.0;
+    }
+    for event in wave_started_event_reader.read() {
+        banner_text = Some(format!("Wave {}", event.number));
+        font_size = 32.0;
+    }
+
+    for (entity, mut banner) in &mut banner_query {
+        if banner_text.is_some() || banner.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    if let Some(banner_text) = banner_text {
+        commands
+            .spawn((
+                WaveBanner {
+                    timer: Timer::from_seconds(WAVE_BANNER_DURATION, TimerMode::Once),
+                },
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(48.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    Text::new(banner_text),
+                    TextFont {
+                        font_size,
+                        ..default()
+                    },
+                ));
+            });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn bubble_spawns(
+    mut spawn_queue: ResMut<SpawnQueue>,
+    game_clock: Res<GameClock>,
+    mut timer: ResMut<BubbleSpawnTimer>,
+    bubble_models: Res<BubbleModels>,
+    player_transform: Single<&Transform, With<Player>>,
+    playfield: Res<PlayfieldMode>,
+    game_mode: Res<GameMode>,
+    mut game_rng: ResMut<GameRng>,
+    arena_shape: Res<ArenaShape>,
+    mut wave: ResMut<Wave>,
+    spawn_bias: Res<SpawnBias>,
+    mut spawn_arc_bias: ResMut<SpawnArcBias>,
+    difficulty: Res<Difficulty>,
+    survival_time: Res<SurvivalTime>,
+    spawn_theme: Res<SpawnTheme>,
+    training_type: Res<TrainingType>,
+) {
+    //nothing spawns during the rest between waves; the game over is covered by the run condition
+    //of the whole fixed update, nothing before this system can end the run
+    if wave.bubbles_remaining == 0 {
+        return;
+    }
+
+    if !timer.0.tick(game_clock.delta()).just_finished() {
+        return;
+    }
+
+    //the type is only rolled once the timer fires and every model it could pick is loaded, so the
+    //number of draws from the GameRng does not depend on how fast the assets load
+    let ready = match training_type.0 {
+        Some(bubble_type) => bubble_models.is_ready(bubble_type),
+        None => bubble_models.all_ready(),
+    };
+    if !ready {
+        warn!("not all bubble models are loaded yet, skipping a spawn");
+        return;
+    }
+
+    let rng = &mut game_rng.rng;
+
+    //randomly decide bubble type, unless a single type is being trained
+    let bubble_type = training_type
+        .0
+        .unwrap_or_else(|| roll_bubble_type(rng, *game_mode, spawn_bias.0, spawn_theme.current));
+
+    wave.bubbles_remaining -= 1;
+    let player_translation = player_transform.into_inner().translation;
+    let rotation_vector = Rot2::radians(spawn_arc_bias.roll_angle(rng, survival_time.0));
+    let (spawn_location, bubble_movement_direction) = bubble_spawn_placement(
+        rng,
+        bubble_type,
+        player_translation,
+        rotation_vector,
+        &arena_shape,
+        playfield.spawn_radius(),
+        difficulty.bubble_speed(survival_time.0),
+    );
+
+    spawn_queue.push(
+        bubble_type,
+        spawn_location,
+        bubble_movement_direction,
+        player_translation,
+    );
+}
+
+//where a new bubble starts and how fast it moves; the direction on the spawn circle is rolled by
+//the caller, see SpawnArcBias
+fn bubble_spawn_placement(
+    rng: &mut StdRng,
+    bubble_type: BubbleType,
+    player_translation: Vec3,
+    rotation_vector: Rot2,
+    arena_shape: &ArenaShape,
+    spawn_radius: f32,
+    bubble_speed: f32,
+) -> (Vec3, Vec2) {
+    if bubble_type == BubbleType::Mine {
+        // mines park at a random spot in the arena that is not right next to the player
+        let mine_location =
+            arena_shape.random_point(rng, player_translation.xz(), MINE_MINIMUM_PLAYER_DISTANCE);
+        (
+            Vec3::new(
+                mine_location.x,
+                player_translation.y + BUBBLE_HOVER_OFFSET,
+                mine_location.y,
+            ),
+            Vec2::ZERO,
+        )
+    } else {
+        // generate random position on edge of circle around player transform
+        let spawn_location = Vec3::from_array([
+            player_translation.x + rotation_vector.cos * spawn_radius,
+            player_translation.y + BUBBLE_HOVER_OFFSET,
+            player_translation.z + rotation_vector.sin * spawn_radius,
+        ]);
+
+        // calculate movement angle directly at player
+        let bubble_movement_direction = Vec2::from([
+            (player_translation.x - spawn_location.x) * bubble_speed,
+            (player_translation.z - spawn_location.z) * bubble_speed,
+        ]);
+        (spawn_location, bubble_movement_direction)
+    }
+}
+
+//with density_fill, tops the bubbles up to a target count for the difficulty preset that grows
+//with the survival time, so how many are around does not only depend on how fast they are created
+//the extra bubbles go through the spawn queue and are taken from the wave budget like every other
+//spawn; a replay always uses the setting it was recorded with
+#[allow(clippy::too_many_arguments)]
+fn fill_bubble_density(
+    mut spawn_queue: ResMut<SpawnQueue>,
+    bubble_query: Query<(), With<Bubble>>,
+    bubble_models: Res<BubbleModels>,
+    player_transform: Single<&Transform, With<Player>>,
+    playfield: Res<PlayfieldMode>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    mut game_rng: ResMut<GameRng>,
+    arena_shape: Res<ArenaShape>,
+    mut wave: ResMut<Wave>,
+    spawn_bias: Res<SpawnBias>,
+    mut spawn_arc_bias: ResMut<SpawnArcBias>,
+    difficulty: Res<Difficulty>,
+    survival_time: Res<SurvivalTime>,
+    spawn_theme: Res<SpawnTheme>,
+) {
+    //the rest between waves stays quiet
+    if wave.bubbles_remaining == 0 {
+        return;
+    }
+
+    let target = (difficulty_preset.target_density() as f32 + DENSITY_TARGET_RAMP * survival_time.0)
+        as usize;
+    let target = target.min(DENSITY_MAX_BUBBLES);
+    //like in bubble_spawns nothing is rolled before every model is loaded, so the number of draws
+    //from the GameRng does not depend on how fast the assets load
+    if !bubble_models.all_ready() {
+        return;
+    }
+
+    let count = bubble_query.iter().count() + spawn_queue.0.len();
+    let rng = &mut game_rng.rng;
+    let player_translation = player_transform.translation;
+    for _ in count..target.min(count + DENSITY_FILL_PER_TICK) {
+        if wave.bubbles_remaining == 0 {
+            return;
+        }
+        let bubble_type = roll_bubble_type(rng, *game_mode, spawn_bias.0, spawn_theme.current);
+        wave.bubbles_remaining -= 1;
+        let rotation_vector = Rot2::radians(spawn_arc_bias.roll_angle(rng, survival_time.0));
+        let (spawn_location, bubble_movement_direction) = bubble_spawn_placement(
+            rng,
+            bubble_type,
+            player_translation,
+            rotation_vector,
+            &arena_shape,
+            playfield.spawn_radius(),
+            difficulty.bubble_speed(survival_time.0),
+        );
+        spawn_queue.push(
+            bubble_type,
+            spawn_location,
+            bubble_movement_direction,
+            player_translation,
+        );
+    }
+}
+
+//creates the queued bubbles, moved ahead by the time they waited so formations keep their shape;
+//dropped bubbles don't count towards the limit
+fn drain_spawn_queue(
+    mut commands: Commands,
+    mut spawn_queue: ResMut<SpawnQueue>,
+    bubble_models: Res<BubbleModels>,
+    player_transform: Single<&Transform, With<Player>>,
+    settings: Res<Settings>,
+    game_clock: Res<GameClock>,
+) {
+    let player_translation = player_transform.translation;
+    let mut spawned = 0;
+    while spawned < SPAWN_QUEUE_PER_TICK {
+        let Some(pending) = spawn_queue.0.pop_front() else {
+            break;
+        };
+        //after a warp or a long dash the bubble would come out of nowhere
+        if pending.player_translation.distance(player_translation) > SPAWN_QUEUE_MAX_PLAYER_MOVE {
+            continue;
+        }
+        let moved = pending.velocity * pending.waited;
+        spawn_bubble(
+            &mut commands,
+            &bubble_models,
+            pending.bubble_type,
+            pending.location + Vec3::new(moved.x, 0.0, moved.y),
+            pending.velocity,
+            &settings,
+        );
+        spawned += 1;
+    }
+    for pending in &mut spawn_queue.0 {
+        pending.waited += game_clock.delta_secs();
+    }
+}
+
+//if some bubble models don't load (missing or broken files), plain spheres in the color of the
+//type stand in for them, so the game stays playable with placeholder art; a model that failed to
+//load is replaced right away, the timeout only catches the ones that never finish loading
+fn use_placeholder_bubble_models(
+    mut bubble_models: ResMut<BubbleModels>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    assets_loading: Res<AssetsLoadingGltf>,
+    time: Res<Time>,
+    mut checked: Local<bool>,
+) {
+    if *checked {
+        return;
+    }
+    let timed_out = time.elapsed_secs() >= BUBBLE_MODEL_LOAD_TIMEOUT;
+    *checked = timed_out;
+
+    let missing: Vec<BubbleType> = BubbleType::ALL
+        .into_iter()
+        .filter(|bubble_type| !bubble_models.is_ready(*bubble_type))
+        .filter(|bubble_type| {
+            timed_out
+                || assets_loading
+                    .0
+                    .get(&format!("{}{}", BUBBLE_ASSET_PREFIX, bubble_type.name()))
+                    .is_some_and(|handle| {
+                        asset_server
+                            .recursive_dependency_load_state(handle)
+                            .is_failed()
+                    })
+        })
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+
+    let mesh = bubble_models.orb_mesh.clone();
+    for bubble_type in missing {
+        let color = bubble_models
+            .light_colors
+            .get(&bubble_type)
+            .copied()
+            .unwrap_or(Color::WHITE);
+        let material = materials.add(StandardMaterial {
+            base_color: color.with_alpha(0.6),
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        });
+        warn!(
+            "no model loaded for {} bubbles, using a placeholder sphere",
+            bubble_type.name()
+        );
+        bubble_models
+            .placeholders
+            .insert(bubble_type, (mesh.clone(), material));
+    }
+}
+
+//spawns a single bubble; the model for its type has to be ready already, see BubbleModels::is_ready
+fn spawn_bubble(
+    commands: &mut Commands,
+    bubble_models: &BubbleModels,
+    bubble_type: BubbleType,
+    spawn_location: Vec3,
+    bubble_movement_direction: Vec2,
+    settings: &Settings,
+) {
+    let fade_in = BUBBLE_SPAWN_FADE_DURATION > 0.0;
+    let radius = bubble_type.radius();
+    let start_scale = if fade_in {
+        radius * BUBBLE_SPAWN_FADE_MIN_SCALE
+    } else {
+        radius
+    };
+    let start_intensity = if fade_in { 0.0 } else { BUBBLE_LIGHT_INTENSITY };
+
+    let mut bubble_entity = commands.spawn((
+        Transform::from_translation(spawn_location).with_scale(Vec3::splat(start_scale)),
+        Velocity(bubble_movement_direction),
+        //taken from the spawn location, so the bobbing uses up no random numbers
+        BobPhase((spawn_location.x * 1.7 + spawn_location.z * 2.3).rem_euclid(2.0 * PI)),
+        SkippedMovement::default(),
+        MeshMaterial3d::<StandardMaterial>::default(),
+        Bubble {
+            bubble_type: bubble_type,
+            value: 1.0,
+            base_radius: radius,
+        },
+        Lifetime {
+            timer: Timer::from_seconds(
+                if bubble_type == BubbleType::Mine {
+                    MINE_LIFETIME
+                } else {
+                    BUBBLE_LIFETIME
+                },
+                TimerMode::Once,
+            ),
+        },
+    ));
+    match settings.bubble_style {
+        BubbleStyle::Models => {
+            bubble_entity.insert(PointLight {
+                color: bubble_color(bubble_type, settings.color_palette, bubble_models),
+                radius,
+                intensity: start_intensity,
+                range: radius * 1.2,
+                ..Default::default()
+            });
+            bubble_models.insert_model(&mut bubble_entity, bubble_type);
+        }
+        BubbleStyle::Orbs => {
+            if let Some(orb_material) = bubble_models
+                .orb_materials
+                .get(&(bubble_type, settings.color_palette))
+            {
+                bubble_entity.insert((
+                    BubbleOrb,
+                    Mesh3d(bubble_models.orb_mesh.clone()),
+                    MeshMaterial3d(orb_material.clone()),
+                ));
+            }
+        }
+    }
+    bubble_models.insert_silhouette(&mut bubble_entity, bubble_type, settings.color_palette);
+    if let Some(shadow_material) = bubble_models
+        .shadow_materials
+        .get(&(bubble_type, settings.color_palette))
+    {
+        bubble_entity.with_child((
+            BubbleShadow,
+            Mesh3d(bubble_models.shadow_mesh.clone()),
+            MeshMaterial3d(shadow_material.clone()),
+            //the circle mesh faces +z, this lays it flat facing up
+            Transform::from_rotation(Quat::from_rotation_x(-FRAC_PI_2)),
+        ));
+    }
+
+    if fade_in {
+        bubble_entity.insert(SpawnFade {
+            timer: Timer::from_seconds(BUBBLE_SPAWN_FADE_DURATION, TimerMode::Once),
+        });
+    }
+    if bubble_type == BubbleType::Timed {
+        bubble_entity.insert(Countdown {
+            timer: Timer::from_seconds(TIMED_FUSE, TimerMode::Once),
+        });
+    }
+}
+
+//every now and then a whole ring of bubbles closes in on the player at once
+//a few neighbouring regular bubbles in the ring are the way out
+#[allow(clippy::too_many_arguments)]
+fn ring_burst(
+    mut spawn_queue: ResMut<SpawnQueue>,
+    game_clock: Res<GameClock>,
+    mut ring_burst: ResMut<RingBurst>,
+    bubble_models: Res<BubbleModels>,
+    player_transform: Single<&Transform, With<Player>>,
+    is_game_over: Res<IsGameOver>,
+    playfield: Res<PlayfieldMode>,
+    game_mode: Res<GameMode>,
+    mut game_rng: ResMut<GameRng>,
+    wave: Res<Wave>,
+    spawn_bias: Res<SpawnBias>,
+    difficulty: Res<Difficulty>,
+    survival_time: Res<SurvivalTime>,
+    mut spawn_arc_bias: ResMut<SpawnArcBias>,
+) {
+    //no bursts during the rest between waves either
+    if is_game_over.0 || wave.bubbles_remaining == 0 {
+        return;
+    }
+
+    if !ring_burst.interval.tick(game_clock.delta()).just_finished() {
+        return;
+    }
+
+    if BubbleType::ALL
+        .iter()
+        .any(|bubble_type| !bubble_models.is_ready(*bubble_type))
+    {
+        return;
+    }
+
+    let rng = &mut game_rng.rng;
+    let player_translation = player_transform.translation;
+    let count = ring_burst.count.max(1);
+    let bubble_speed = difficulty.bubble_speed(survival_time.0);
+    let start_angle = rng.gen::<f32>() * 360.0;
+    let gap_start = rng.gen_range(0..count);
+    for index in 0..count {
+        let in_gap = (index + count - gap_start) % count < RING_BURST_GAP_SIZE;
+        let bubble_type = match roll_bubble_type(rng, *game_mode, spawn_bias.0, None) {
+            _ if in_gap => BubbleType::Regular,
+            //mines stay where they spawn, which does not fit a closing ring
+            BubbleType::Mine => BubbleType::Blood,
+            bubble_type => bubble_type,
+        };
+
+        let rotation_vector = Rot2::degrees(start_angle + index as f32 * 360.0 / count as f32);
+        spawn_arc_bias.remember(rotation_vector.as_radians(), survival_time.0);
+        let spawn_location = Vec3::new(
+            player_translation.x + rotation_vector.cos * playfield.spawn_radius(),
+            player_translation.y + BUBBLE_HOVER_OFFSET,
+            player_translation.z + rotation_vector.sin * playfield.spawn_radius(),
+        );
+        let bubble_movement_direction =
+            (player_translation.xz() - spawn_location.xz()) * bubble_speed;
+        spawn_queue.push(
+            bubble_type,
+            spawn_location,
+            bubble_movement_direction,
+            player_translation,
+        );
+    }
+    info!("ring burst of {} bubbles", count);
+}
+
+//the "hell" in bubble hell: after FINALE_START_TIME the normal spawns pause and a patterned
+//barrage comes in, first a turning spiral and then walls with a gap of regular bubbles
+//everything here is derived from the finale time, so it plays out the same in every run
+#[allow(clippy::too_many_arguments)]
+fn finale_spawner(
+    mut spawn_queue: ResMut<SpawnQueue>,
+    game_clock: Res<GameClock>,
+    mut finale: ResMut<Finale>,
+    bubble_models: Res<BubbleModels>,
+    player_transform: Single<&Transform, With<Player>>,
+    is_game_over: Res<IsGameOver>,
+    playfield: Res<PlayfieldMode>,
+    game_mode: Res<GameMode>,
+    survival_time: Res<SurvivalTime>,
+    difficulty: Res<Difficulty>,
+    bubble_query: Query<(), With<Bubble>>,
+) {
+    if is_game_over.0 || *game_mode == GameMode::Zen || !finale.is_active(survival_time.0) {
+        return;
+    }
+    if finale.elapsed == 0.0 {
+        info!("the finale begins");
+    }
+
+    finale.elapsed += game_clock.delta_secs();
+    let in_spiral = finale.elapsed < FINALE_SPIRAL_DURATION;
+    let interval = if in_spiral {
+        FINALE_SPIRAL_INTERVAL
+    } else {
+        FINALE_WALL_INTERVAL
+    };
+    if finale.shot_timer.duration().as_secs_f32() != interval {
+        finale
+            .shot_timer
+            .set_duration(std::time::Duration::from_secs_f32(interval));
+        finale.shot_timer.reset();
+    }
+    if !finale.shot_timer.tick(game_clock.delta()).just_finished() {
+        return;
+    }
+    if bubble_query.iter().count() + spawn_queue.0.len() >= FINALE_MAX_BUBBLES
+        || [BubbleType::Regular, BubbleType::Blood, BubbleType::Dirt]
+            .iter()
+            .any(|bubble_type| !bubble_models.is_ready(*bubble_type))
+    {
+        return;
+    }
+
+    let player_translation = player_transform.translation;
+    let bubble_speed = difficulty.bubble_speed(survival_time.0);
+    let mut shots = finale.shots;
+    let mut next_type = || {
+        shots += 1;
+        match shots % FINALE_REGULAR_EVERY {
+            0 => BubbleType::Regular,
+            1 => BubbleType::Blood,
+            _ => BubbleType::Dirt,
+        }
+    };
+
+    if in_spiral {
+        //a few arms of bubbles entering from a point on the spawn circle that keeps turning
+        let base_angle = finale.elapsed * FINALE_SPIRAL_TURN_SPEED;
+        for arm in 0..FINALE_SPIRAL_ARMS {
+            let angle = base_angle + arm as f32 * 2.0 * PI / FINALE_SPIRAL_ARMS as f32;
+            let offset = Vec2::from_angle(angle) * playfield.spawn_radius();
+            spawn_queue.push(
+                next_type(),
+                player_translation + Vec3::new(offset.x, BUBBLE_HOVER_OFFSET, offset.y),
+                -offset * bubble_speed,
+                player_translation,
+            );
+        }
+    } else {
+        //a straight wall sweeping across the player, the regular bubbles in it are the way through
+        let wall_number = finale.shots;
+        let direction = Vec2::from_angle(wall_number as f32 * FINALE_WALL_TURN);
+        let along = direction.perp();
+        let spawn_radius = playfield.spawn_radius();
+        let velocity = -direction * spawn_radius * bubble_speed;
+        for index in 0..FINALE_WALL_LENGTH {
+            let side = index as f32 - (FINALE_WALL_LENGTH - 1) as f32 * 0.5;
+            let offset = direction * spawn_radius + along * side * FINALE_WALL_SPACING;
+            let gap = (index + wall_number) % FINALE_WALL_LENGTH < 2;
+            let bubble_type = if gap {
+                BubbleType::Regular
+            } else if index % 2 == 0 {
+                BubbleType::Blood
+            } else {
+                BubbleType::Dirt
+            };
+            spawn_queue.push(
+                bubble_type,
+                player_translation + Vec3::new(offset.x, BUBBLE_HOVER_OFFSET, offset.y),
+                velocity,
+                player_translation,
+            );
+        }
+        shots += 1;
+    }
+    finale.shots = shots;
+}
+
+//the border and the alarm come on as soon as the player leaves the plateau and stop right
+//away on the way back; with reduced motion the border stays lit instead of pulsing
+#[allow(clippy::too_many_arguments)]
+fn update_off_plateau_warning(
+    mut commands: Commands,
+    off_plateau: Res<OffPlateau>,
+    mut pitches: ResMut<Assets<Pitch>>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    border_query: Single<(&mut BorderColor, &mut Visibility), With<OffPlateauBorder>>,
+    alarm_query: Query<Entity, With<OffPlateauAlarm>>,
+    alarm_sinks: Query<&AudioSink, With<OffPlateauAlarm>>,
+    is_game_over: Res<IsGameOver>,
+) {
+    let (mut border_color, mut visibility) = border_query.into_inner();
+    if off_plateau.is_changed() {
+        //no new loops once the run is over, see stop_all_loops_on_game_over
+        if off_plateau.0 && !is_game_over.0 {
+            *visibility = Visibility::Inherited;
+            commands.spawn((
+                OffPlateauAlarm,
+                LoopingSound,
+                AudioPlayer(pitches.add(Pitch::new(
+                    OFF_PLATEAU_ALARM_FREQUENCY,
+                    std::time::Duration::from_secs(1),
+                ))),
+                PlaybackSettings::LOOP
+                    .with_volume(Volume::new(OFF_PLATEAU_ALARM_VOLUME * settings.sfx_volume)),
+            ));
+        } else {
+            *visibility = Visibility::Hidden;
+            for entity in &alarm_query {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+    if !off_plateau.0 {
+        return;
+    }
+
+    //the tone beeps along with the border, it does that with reduced motion as well
+    let pulse = (time.elapsed_secs() * OFF_PLATEAU_PULSE_FREQUENCY * 2.0 * PI).sin() * 0.5 + 0.5;
+    for sink in &alarm_sinks {
+        sink.set_volume(OFF_PLATEAU_ALARM_VOLUME * settings.sfx_volume * pulse);
+    }
+
+    let alpha = if settings.screen_pulse_enabled() {
+        OFF_PLATEAU_BORDER_MIN_ALPHA
+            + (OFF_PLATEAU_BORDER_MAX_ALPHA - OFF_PLATEAU_BORDER_MIN_ALPHA) * pulse
+    } else {
+        OFF_PLATEAU_BORDER_MAX_ALPHA
+    };
+    border_color.0 = RED.with_alpha(alpha).into();
+}
+
+//the spawners do not run at all while frozen, so their timers stay where they were
+//a replay always uses the setting it was recorded with
+fn spawns_frozen(
+    active_effects: Res<ActiveEffects>,
+    settings: Res<Settings>,
+    replay_player: Option<Res<ReplayPlayer>>,
+) -> bool {
+    let freeze_pauses_spawns = replay_player
+        .map_or(settings.freeze_pauses_spawns, |replay_player| {
+            replay_player.freeze_pauses_spawns
+        });
+    freeze_pauses_spawns && active_effects.is_active(EffectKind::Freeze)
+}
+
+//a replay always uses the setting it was recorded with
+fn density_fill_enabled(settings: Res<Settings>, replay_player: Option<Res<ReplayPlayer>>) -> bool {
+    replay_player.map_or(settings.density_fill, |replay_player| {
+        replay_player.density_fill
+    })
+}
+
+//the single gate for everything that changes the run; systems that can run in the same fixed
+//update as the death still check IsGameOver themselves
+fn game_over(is_game_over: Res<IsGameOver>) -> bool {
+    is_game_over.0
+}
+
+//the finale replaces the normal spawns while it lasts
+fn finale_running(finale: Res<Finale>, survival_time: Res<SurvivalTime>) -> bool {
+    finale.is_active(survival_time.0)
+}
+
+fn tutorial_running(tutorial: Res<Tutorial>) -> bool {
+    tutorial.step.is_some()
+}
+
+fn start_tutorial(
+    mut tutorial: ResMut<Tutorial>,
+    settings: Res<Settings>,
+    game_mode: Res<GameMode>,
+    training_type: Res<TrainingType>,
+    replay_player: Option<Res<ReplayPlayer>>,
+    benchmark: Option<Res<Benchmark>>,
+) {
+    *tutorial = Tutorial::for_run(
+        &settings,
+        *game_mode,
+        replay_player.is_some() || benchmark.is_some() || training_type.0.is_some(),
+    );
+    if tutorial.step.is_some() {
+        info!("starting the tutorial");
+    }
+}
+
+//a training run is for practicing, so it uses god mode the way --benchmark does
+fn start_training(training_type: Res<TrainingType>, mut god_mode: ResMut<GodMode>) {
+    if let Some(bubble_type) = training_type.0 {
+        god_mode.0 = true;
+        info!("training with {} bubbles", bubble_type.name());
+    }
+}
+
+fn training_running(training_type: Res<TrainingType>) -> bool {
+    training_type.0.is_some()
+}
+
+//a training run never ends on its own, so it is left with a key; the run is cleaned up by
+//restart_run and the menu starts with training and god mode off again
+fn leave_training(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut training_type: ResMut<TrainingType>,
+    mut god_mode: ResMut<GodMode>,
+    mut restart_event_writer: EventWriter<RestartEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if training_type.0.is_none() || !keyboard_input.just_pressed(TRAINING_LEAVE_KEY) {
+        return;
+    }
+
+    info!("leaving the training run");
+    training_type.0 = None;
+    god_mode.0 = false;
+    restart_event_writer.send(RestartEvent {
+        regenerate_level: false,
+    });
+    next_state.set(GameState::MainMenu);
+}
+
+//slow trickle of bubbles from the spawn circle; yellow ones only at first, blood bubbles are
+//mixed in from the second step on
+#[allow(clippy::too_many_arguments)]
+fn tutorial_spawner(
+    mut commands: Commands,
+    game_clock: Res<GameClock>,
+    mut tutorial: ResMut<Tutorial>,
+    bubble_models: Res<BubbleModels>,
+    player_transform: Single<&Transform, With<Player>>,
+    is_game_over: Res<IsGameOver>,
+    settings: Res<Settings>,
+    mut game_rng: ResMut<GameRng>,
+    difficulty: Res<Difficulty>,
+) {
+    let Some(step) = tutorial.step else {
+        return;
+    };
+    if is_game_over.0
+        || !tutorial
+            .spawn_timer
+            .tick(game_clock.delta())
+            .just_finished()
+    {
+        return;
+    }
+
+    let rng = &mut game_rng.rng;
+    let bubble_type =
+        if step != TutorialStep::CollectOxygen && rng.gen::<f32>() < TUTORIAL_BLOOD_CHANCE {
+            BubbleType::Blood
+        } else {
+            BubbleType::Regular
+        };
+    if !bubble_models.is_ready(bubble_type) {
+        return;
+    }
+
+    let player_translation = player_transform.translation;
+    let offset = Vec2::from_angle(rng.gen_range(0.0..2.0 * PI)) * BUBBLE_SPAWN_RADIUS;
+    spawn_bubble(
+        &mut commands,
+        &bubble_models,
+        bubble_type,
+        player_translation + Vec3::new(offset.x, BUBBLE_HOVER_OFFSET, offset.y),
+        -offset * difficulty.bubble_speed(0.0),
+        &settings,
+    );
+}
+
+//moves the tutorial along as yellow bubbles are collected and marks it as done after the last step
+fn advance_tutorial(
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    mut tutorial: ResMut<Tutorial>,
+    mut settings: ResMut<Settings>,
+) {
+    for event in bubble_hit_event_reader.read() {
+        let Some(step) = tutorial.step else {
+            return;
+        };
+        if event.bubble_type != BubbleType::Regular {
+            continue;
+        }
+        tutorial.collected += 1;
+        if tutorial.collected < TUTORIAL_BUBBLES_PER_STEP {
+            continue;
+        }
+        tutorial.collected = 0;
+        tutorial.step = step.next();
+        if tutorial.step.is_none() {
+            info!("tutorial completed");
+            settings.tutorial_completed = true;
+        }
+    }
+}
+
+fn skip_tutorial(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut tutorial: ResMut<Tutorial>,
+    mut settings: ResMut<Settings>,
+) {
+    if tutorial.step.is_some() && keyboard_input.just_pressed(TUTORIAL_SKIP_KEY) {
+        info!("tutorial skipped");
+        tutorial.step = None;
+        settings.tutorial_completed = true;
+    }
+}
+
+fn update_tutorial_text(
+    tutorial: Res<Tutorial>,
+    tutorial_text: Single<(&mut Text, &mut Visibility), With<TutorialText>>,
+) {
+    if !tutorial.is_changed() {
+        return;
+    }
+
+    let (mut text, mut visibility) = tutorial_text.into_inner();
+    match tutorial.step {
+        Some(step) => {
+            text.0 = format!(
+                "{}\n{}/{}   (Tab to skip)",
+                step.prompt(),
+                tutorial.collected,
+                TUTORIAL_BUBBLES_PER_STEP
+            );
+            *visibility = Visibility::Inherited;
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
+
+//keeps BENCHMARK_BUBBLE_COUNT bubbles around the player once the models are loaded
+fn top_up_benchmark_bubbles(
+    mut commands: Commands,
+    mut benchmark: ResMut<Benchmark>,
+    bubble_models: Res<BubbleModels>,
+    bubble_query: Query<(), With<Bubble>>,
+    settings: Res<Settings>,
+) {
+    if BubbleType::ALL
+        .iter()
+        .any(|bubble_type| !bubble_models.is_ready(*bubble_type))
+    {
+        return;
+    }
+
+    let rng = &mut benchmark.rng;
+    for _ in bubble_query.iter().count()..BENCHMARK_BUBBLE_COUNT {
+        let bubble_type = roll_bubble_type(rng, GameMode::Normal, 0.0, None);
+        let position = Vec2::from_angle(rng.gen_range(0.0..2.0 * PI))
+            * BUBBLE_SPAWN_RADIUS
+            * rng.gen::<f32>().sqrt();
+        let velocity = Vec2::from_angle(rng.gen_range(0.0..2.0 * PI));
+        spawn_bubble(
+            &mut commands,
+            &bubble_models,
+            bubble_type,
+            Vec3::new(position.x, BUBBLE_HOVER_OFFSET, position.y),
+            velocity,
+            &settings,
+        );
+    }
+}
+
+//skips the menu and prints the frame time percentiles as one line to stdout when done, measured
+//from when the bubbles of top_up_benchmark_bubbles are there; the frames of the warmup, which
+//include the one that creates all the bubbles, are not counted, e.g.
+//benchmark frames=2400 p50_ms=7.912 p90_ms=9.104 p99_ms=12.530 max_ms=20.771 offscreen_throttling=false
+#[allow(clippy::too_many_arguments)]
+fn run_benchmark(
+    mut benchmark: ResMut<Benchmark>,
+    game_state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    bubble_models: Res<BubbleModels>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut app_exit_event_writer: EventWriter<AppExit>,
+) {
+    if *game_state.get() != GameState::Playing {
+        next_state.set(GameState::Playing);
+        return;
+    }
+    if BubbleType::ALL
+        .iter()
+        .any(|bubble_type| !bubble_models.is_ready(*bubble_type))
+    {
+        return;
+    }
+
+    if !benchmark.warmup.finished() {
+        benchmark.warmup.tick(time.delta());
+        return;
+    }
+    benchmark.frame_times.push(time.delta_secs() * 1000.0);
+    if !benchmark.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let frame_times = &mut benchmark.frame_times;
+    frame_times.sort_by(f32::total_cmp);
+    let percentile = |fraction: f32| {
+        let index = ((frame_times.len() - 1) as f32 * fraction).round() as usize;
+        frame_times[index]
+    };
+    println!(
+        "benchmark frames={} p50_ms={:.3} p90_ms={:.3} p99_ms={:.3} max_ms={:.3} offscreen_throttling={}",
+        frame_times.len(),
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        percentile(1.0),
+        settings.offscreen_throttling
+    );
+    app_exit_event_writer.send(AppExit::Success);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collect_stats(
+    mut game_stats: ResMut<GameStats>,
+    oxygen_level: Single<&OxygenLevel, With<Player>>,
+    bonus_reserve: Res<BonusReserve>,
+    survival_time: Res<SurvivalTime>,
+    score: Res<Score>,
+    bubble_query: Query<(), With<Bubble>>,
+    difficulty: Res<Difficulty>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    is_game_over: Res<IsGameOver>,
+) {
+    *game_stats = GameStats {
+        oxygen: oxygen_level.0,
+        bonus_reserve: bonus_reserve.0,
+        survival_time: survival_time.0,
+        score: score.0,
+        active_bubbles: bubble_query.iter().count(),
+        bubble_speed: difficulty.bubble_speed(survival_time.0),
+        game_mode: game_mode.name(),
+        difficulty: difficulty_preset.name(),
+        game_over: is_game_over.0,
+    };
+}
+
+//written next to the stats file and then moved over it, so a reader never sees half a file
+#[cfg(feature = "stats_file")]
+fn write_stats_file(game_stats: Res<GameStats>, time: Res<Time>, mut since_write: Local<f32>) {
+    *since_write += time.delta_secs();
+    if *since_write < STATS_FILE_INTERVAL {
+        return;
+    }
+    *since_write = 0.0;
+
+    let contents = match ron::ser::to_string_pretty(&*game_stats, ron::ser::PrettyConfig::default())
+    {
+        Ok(contents) => contents,
+        Err(error) => {
+            warn!("could not serialize the game stats: {}", error);
+            return;
+        }
+    };
+    let temporary_path = format!("{}.tmp", STATS_FILE_PATH);
+    if let Err(error) = std::fs::write(&temporary_path, contents)
+        .and_then(|()| std::fs::rename(&temporary_path, STATS_FILE_PATH))
+    {
+        warn!("could not write {}: {}", STATS_FILE_PATH, error);
+    }
+}
+
+//the settings are loaded before everything else in Startup, the window starts out windowed
+//a replay plays back on the arena it was recorded on, not the one in the settings file
+fn load_settings(
+    mut commands: Commands,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    replay_player: Option<Res<ReplayPlayer>>,
+) {
+    let settings = Settings::load();
+    window.mode = window_mode(settings.fullscreen);
+    window.present_mode = settings.vsync.present_mode();
+    commands.insert_resource(
+        replay_player.map_or(settings.arena.clone(), |replay_player| {
+            replay_player.arena_shape.clone()
+        }),
+    );
+    commands.insert_resource(settings);
+}
+
+//the quit key quits right away in the menu; during a run it asks first
+fn quit_on_key(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    game_state: Res<State<GameState>>,
+    prompt_visibility: Single<&mut Visibility, With<QuitPrompt>>,
+    mut app_exit_event_writer: EventWriter<AppExit>,
+) {
+    let mut prompt_visibility = prompt_visibility.into_inner();
+    let quit_pressed = keyboard_input.just_pressed(settings.general_bindings.quit);
+    let needs_confirmation = *game_state.get() == GameState::Playing;
+    if *prompt_visibility != Visibility::Hidden {
+        if quit_pressed {
+            app_exit_event_writer.send(AppExit::Success);
+        } else if keyboard_input.get_just_pressed().next().is_some() {
+            *prompt_visibility = Visibility::Hidden;
+        }
+    } else if quit_pressed {
+        if needs_confirmation {
+            *prompt_visibility = Visibility::Inherited;
+        } else {
+            app_exit_event_writer.send(AppExit::Success);
+        }
+    }
+}
+
+//settings changed in the very frame the game quits would otherwise be lost
+fn save_settings_on_exit(mut app_exit_event_reader: EventReader<AppExit>, settings: Res<Settings>) {
+    if app_exit_event_reader.read().count() > 0 {
+        settings.save();
+    }
+}
+
+//every change to the settings is written to disk right away
+fn save_settings_on_change(settings: Res<Settings>) {
+    //loading the settings counts as a change as well, but there is nothing new to write then
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
+
+//F11 switches between windowed and borderless fullscreen
+fn toggle_fullscreen(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut settings: ResMut<Settings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F11) {
+        settings.fullscreen = window.mode == WindowMode::Windowed;
+        window.mode = window_mode(settings.fullscreen);
+    }
+}
+
+fn cycle_vsync_mode(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::KeyV) {
+        settings.vsync = settings.vsync.next();
+        info!("vsync mode changed to {:?}", settings.vsync);
+    }
+}
+
+fn apply_vsync_mode(settings: Res<Settings>, mut window: Single<&mut Window, With<PrimaryWindow>>) {
+    let present_mode = settings.vsync.present_mode();
+    if settings.is_changed() && window.present_mode != present_mode {
+        window.present_mode = present_mode;
+    }
+}
+
+//holds the frames to the cap when one is set: each frame ends at a fixed deadline after the last
+//one instead of a frame time after it started, so the small overshoots don't add up
+//only sleeps, a spin loop would be more exact but burns the power the cap is meant to save
+fn limit_frame_rate(settings: Res<Settings>, mut deadline: Local<Option<std::time::Instant>>) {
+    if settings.max_fps == 0 {
+        *deadline = None;
+        return;
+    }
+
+    let frame_duration = std::time::Duration::from_secs_f64(1.0 / settings.max_fps as f64);
+    let now = std::time::Instant::now();
+    let Some(frame_deadline) = *deadline else {
+        *deadline = Some(now + frame_duration);
+        return;
+    };
+    if let Some(sleep) = frame_deadline.checked_duration_since(now) {
+        std::thread::sleep(sleep);
+    }
+    //a frame that was much too slow (loading, a dragged window) starts a new schedule instead of
+    //rushing the next frames to catch up
+    *deadline = Some(
+        if frame_deadline + frame_duration < std::time::Instant::now() {
+            std::time::Instant::now() + frame_duration
+        } else {
+            frame_deadline + frame_duration
+        },
+    );
+}
+
+fn toggle_rumble(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        settings.rumble = !settings.rumble;
+        info!(
+            "gamepad rumble {}",
+            if settings.rumble { "on" } else { "off" }
+        );
+    }
+}
+
+//harmful hits rumble every connected gamepad, harder the more oxygen they took
+fn rumble_on_hit(
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    mut rumble_request_writer: EventWriter<GamepadRumbleRequest>,
+    gamepads: Query<Entity, With<Gamepad>>,
+    settings: Res<Settings>,
+    bubble_effects: Res<BubbleEffects>,
+) {
+    for event in bubble_hit_event_reader.read() {
+        if !settings.rumble {
+            continue;
+        }
+
+        let strength = (bubble_effects.oxygen_loss(event.bubble_type)
+            / BUBBLE_EFFECT_OXYGEN_DECREASE_MINE)
+            .clamp(0.0, 1.0);
+        let intensity = match event.bubble_type {
+            BubbleType::Blood | BubbleType::Bomb | BubbleType::Mine => GamepadRumbleIntensity {
+                strong_motor: strength,
+                weak_motor: strength,
+            },
+            BubbleType::Dirt | BubbleType::Leech | BubbleType::Thief => {
+                GamepadRumbleIntensity::weak_motor(strength)
+            }
+            BubbleType::Regular
+            | BubbleType::Freeze
+            | BubbleType::Grow
+            | BubbleType::Rush
+            | BubbleType::Invert
+            | BubbleType::Warp
+            | BubbleType::Purge
+            | BubbleType::Repel
+            | BubbleType::Timed => continue,
+        };
+
+        //with no gamepad connected there is nothing to send
+        for gamepad in &gamepads {
+            rumble_request_writer.send(GamepadRumbleRequest::Add {
+                gamepad,
+                intensity,
+                duration: std::time::Duration::from_secs_f32(RUMBLE_DURATION),
+            });
+        }
+    }
+}
+
+fn toggle_adaptive_difficulty(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Settings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F10) {
+        settings.adaptive_difficulty = !settings.adaptive_difficulty;
+        info!(
+            "adaptive difficulty {} from the next run on",
+            if settings.adaptive_difficulty {
+                "on"
+            } else {
+                "off"
+            }
+        );
+    }
+}
+
+fn toggle_new_layout_each_run(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Settings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        settings.new_layout_each_run = !settings.new_layout_each_run;
+        info!(
+            "new plant layout each run {}",
+            if settings.new_layout_each_run {
+                "on"
+            } else {
+                "off"
+            }
+        );
+    }
+}
+
+fn toggle_reduced_motion(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Settings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        settings.reduced_motion = !settings.reduced_motion;
+        info!(
+            "reduced motion {}",
+            if settings.reduced_motion { "on" } else { "off" }
+        );
+    }
+}
+
+fn toggle_underwater_distortion(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Settings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        settings.underwater_distortion = !settings.underwater_distortion;
+    }
+}
+
+//the distortion only runs while the camera has the component, so turning it off costs nothing
+fn update_underwater_distortion(
+    mut commands: Commands,
+    camera_query: Single<(Entity, Option<&mut UnderwaterDistortion>), With<Camera3d>>,
+    oxygen_level: Single<&OxygenLevel, With<Player>>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let (camera_entity, distortion) = camera_query.into_inner();
+    if !settings.distortion_enabled() {
+        if distortion.is_some() {
+            commands
+                .entity(camera_entity)
+                .remove::<UnderwaterDistortion>();
+        }
+        return;
+    }
+
+    let emptiness = 1.0 - (oxygen_level.0 / PLAYER_OXYGEN_START_SUPPLY).clamp(0.0, 1.0);
+    let new_distortion = UnderwaterDistortion {
+        strength: UNDERWATER_DISTORTION_MIN_STRENGTH
+            .lerp(UNDERWATER_DISTORTION_MAX_STRENGTH, emptiness),
+        time: time.elapsed_secs_wrapped(),
+    };
+    match distortion {
+        Some(mut distortion) => *distortion = new_distortion,
+        None => {
+            commands.entity(camera_entity).insert(new_distortion);
+        }
+    }
+}
+
+//F4 cycles through the color palettes
+fn cycle_color_palette(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        settings.color_palette = settings.color_palette.next();
+        info!("color palette changed to {:?}", settings.color_palette);
+    }
+}
+
+//recolor the bubbles that are already around when the palette changes
+#[allow(clippy::type_complexity)]
+fn apply_color_palette(
+    settings: Res<Settings>,
+    bubble_models: Res<BubbleModels>,
+    mut bubble_query: Query<(&Bubble, &mut PointLight)>,
+    mut orb_query: Query<(&Bubble, &mut MeshMaterial3d<StandardMaterial>), With<BubbleOrb>>,
+    mut shadow_query: Query<
+        (&Parent, &mut MeshMaterial3d<StandardMaterial>),
+        (With<BubbleShadow>, Without<BubbleOrb>),
+    >,
+    mut silhouette_query: Query<
+        (&Parent, &mut MeshMaterial3d<StandardMaterial>),
+        (
+            With<SilhouettePart>,
+            Without<BubbleOrb>,
+            Without<BubbleShadow>,
+        ),
+    >,
+    bubble_type_query: Query<&Bubble>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (bubble, mut point_light) in &mut bubble_query {
+        point_light.color =
+            bubble_color(bubble.bubble_type, settings.color_palette, &bubble_models);
+    }
+    for (bubble, mut material) in &mut orb_query {
+        if let Some(orb_material) = bubble_models
+            .orb_materials
+            .get(&(bubble.bubble_type, settings.color_palette))
+        {
+            material.0 = orb_material.clone();
+        }
+    }
+    for (parent, mut material) in &mut shadow_query {
+        let Ok(bubble) = bubble_type_query.get(parent.get()) else {
+            continue;
+        };
+        if let Some(shadow_material) = bubble_models
+            .shadow_materials
+            .get(&(bubble.bubble_type, settings.color_palette))
+        {
+            material.0 = shadow_material.clone();
+        }
+    }
+    for (parent, mut material) in &mut silhouette_query {
+        let Ok(bubble) = bubble_type_query.get(parent.get()) else {
+            continue;
+        };
+        if let Some(orb_material) = bubble_models
+            .orb_materials
+            .get(&(bubble.bubble_type, settings.color_palette))
+        {
+            material.0 = orb_material.clone();
+        }
+    }
+}
+
+//bubbles that are already around keep their look, only new ones are spawned in the new style
+fn toggle_bubble_style(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::KeyO) {
+        settings.bubble_style = match settings.bubble_style {
+            BubbleStyle::Models => BubbleStyle::Orbs,
+            BubbleStyle::Orbs => BubbleStyle::Models,
+        };
+        info!("bubble style changed to {:?}", settings.bubble_style);
+    }
+}
+
+fn pulse_mine_lights(
+    mut mine_query: Query<(&Bubble, &mut PointLight), Without<SpawnFade>>,
+    time: Res<Time>,
+) {
+    let pulse = 0.5 + 0.5 * (time.elapsed_secs() * MINE_PULSE_FREQUENCY).sin();
+    for (bubble, mut point_light) in &mut mine_query {
+        if bubble.bubble_type == BubbleType::Mine {
+            point_light.intensity = BUBBLE_LIGHT_INTENSITY * (0.25 + 0.75 * pulse);
+        }
+    }
+}
+
+//the pulse speeds up as the fuse burns down; the phase is the integral of the frequency,
+//so the bubble never jumps between two sizes while the frequency changes
+fn pulse_timed_bubbles(
+    mut timed_query: Query<(&mut Transform, &Bubble, &Countdown), Without<SpawnFade>>,
+) {
+    for (mut transform, bubble, countdown) in &mut timed_query {
+        let elapsed = countdown.timer.elapsed_secs();
+        let phase = 2.0
+            * PI
+            * (TIMED_PULSE_START_FREQUENCY * elapsed
+                + (TIMED_PULSE_END_FREQUENCY - TIMED_PULSE_START_FREQUENCY) * elapsed * elapsed
+                    / (2.0 * TIMED_FUSE));
+        let pulse = 0.5 + 0.5 * phase.sin();
+        transform.scale = Vec3::splat(bubble.radius() * (1.0 + TIMED_PULSE_SCALE * pulse));
+    }
+}
+
+//a timed bubble that runs out goes off where it is, the player only loses oxygen when they are
+//inside the blast at that moment, so they can lure it somewhere harmless and swim away
+#[allow(clippy::too_many_arguments)]
+fn detonate_timed_bubbles(
+    mut commands: Commands,
+    mut timed_query: Query<(Entity, &Transform, &mut Countdown)>,
+    player_query: Single<(&Transform, &mut OxygenLevel), With<Player>>,
+    floating_number_query: Query<(Entity, &FloatingText), With<FloatingNumber>>,
+    mut bonus_reserve: ResMut<BonusReserve>,
+    bubble_effects: Res<BubbleEffects>,
+    oxygen_trail: Res<OxygenTrail>,
+    god_mode: Res<GodMode>,
+    game_clock: Res<GameClock>,
+) {
+    let (player_transform, mut oxygen_level) = player_query.into_inner();
+    let mut floating_numbers = floating_numbers_oldest_first(&floating_number_query);
+    let blast_oxygen = bubble_effects
+        .0
+        .get(&BubbleType::Timed)
+        .map_or(0.0, |effect| effect.blast_oxygen);
+    for (entity, transform, mut countdown) in &mut timed_query {
+        if !countdown.timer.tick(game_clock.delta()).just_finished() {
+            continue;
+        }
+
+        let center = transform.translation;
+        let player_distance = center.xz().distance(player_transform.translation.xz());
+        if player_distance <= TIMED_BLAST_RADIUS {
+            info!("caught in the blast of a timed bubble");
+            if !god_mode.0 || blast_oxygen > 0.0 {
+                change_oxygen(
+                    &mut commands,
+                    &mut floating_numbers,
+                    &mut oxygen_level,
+                    &mut bonus_reserve,
+                    player_transform.translation,
+                    blast_oxygen,
+                );
+            }
+        }
+
+        for index in 0..TIMED_BLAST_PARTICLES {
+            let offset = Vec2::from_angle(index as f32 / TIMED_BLAST_PARTICLES as f32 * 2.0 * PI)
+                * TIMED_BLAST_RADIUS;
+            commands.spawn((
+                OxygenTrailParticle {
+                    lifetime: Timer::from_seconds(OXYGEN_TRAIL_PARTICLE_LIFETIME, TimerMode::Once),
+                },
+                Mesh3d(oxygen_trail.mesh.clone()),
+                MeshMaterial3d(oxygen_trail.material.clone()),
+                Transform::from_translation(center + Vec3::new(offset.x, 0.0, offset.y)),
+            ));
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+//keeps the number of bubbles bounded even if some never reach (or miss) the player
+fn expire_bubbles(
+    mut commands: Commands,
+    mut bubble_query: Query<(Entity, &mut Lifetime, &Bubble)>,
+    game_clock: Res<GameClock>,
+) {
+    for (entity, mut lifetime, bubble) in &mut bubble_query {
+        if lifetime.timer.tick(game_clock.delta()).just_finished() {
+            debug!("bubble of type {:?} expired", bubble.bubble_type);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+//grows freshly spawned bubbles and their lights up to full size
+//collisions always use the full radius of the bubble, so this is purely visual
+fn update_spawn_fade(
+    mut commands: Commands,
+    mut fading_bubbles: Query<(
+        Entity,
+        &mut SpawnFade,
+        &mut Transform,
+        Option<&mut PointLight>,
+        &Bubble,
+    )>,
+    time: Res<Time>,
+) {
+    for (entity, mut spawn_fade, mut transform, point_light, bubble) in &mut fading_bubbles {
+        spawn_fade.timer.tick(time.delta());
+        let progress = spawn_fade.timer.fraction();
+        let scale = bubble.radius() * (BUBBLE_SPAWN_FADE_MIN_SCALE.lerp(1.0, progress));
+        transform.scale = Vec3::splat(scale);
+        if let Some(mut point_light) = point_light {
+            point_light.intensity = BUBBLE_LIGHT_INTENSITY * progress;
+        }
+
+        if spawn_fade.timer.finished() {
+            commands.entity(entity).remove::<SpawnFade>();
+        }
+    }
+}
+
+//moves the shadows back down to the floor, the bubbles above them bob and grow
+fn update_bubble_shadows(
+    bubble_query: Query<(&Transform, &Children), With<Bubble>>,
+    mut shadow_query: Query<&mut Transform, (With<BubbleShadow>, Without<Bubble>)>,
+) {
+    for (bubble_transform, children) in &bubble_query {
+        let mut shadow_iter = shadow_query.iter_many_mut(children);
+        while let Some(mut shadow_transform) = shadow_iter.fetch_next() {
+            shadow_transform.translation.y = (BUBBLE_SHADOW_HEIGHT
+                - bubble_transform.translation.y)
+                / bubble_transform.scale.y.max(f32::EPSILON);
+        }
+    }
+}
+
+//thieves steer towards the nearest regular bubble and pop it on contact, so its oxygen is gone
+//before the player gets there; without a regular bubble around they go for the player instead
+fn steer_thieves(
+    mut commands: Commands,
+    mut thief_query: Query<(&Transform, &mut Velocity, &Bubble)>,
+    target_query: Query<(Entity, &Transform, &Bubble)>,
+    player_transform: Single<&Transform, With<Player>>,
+    game_clock: Res<GameClock>,
+) {
+    let turn_amount = 1.0 - (-THIEF_TURN_RATE * game_clock.delta_secs()).exp();
+    let mut stolen = HashSet::new();
+    for (transform, mut velocity, bubble) in &mut thief_query {
+        if bubble.bubble_type != BubbleType::Thief {
+            continue;
+        }
+
+        let position = transform.translation.xz();
+        let target = target_query
+            .iter()
+            .filter(|(entity, _, target)| {
+                target.bubble_type == BubbleType::Regular && !stolen.contains(entity)
+            })
+            .map(|(entity, target_transform, target)| {
+                (entity, target_transform.translation.xz(), target.radius())
+            })
+            .min_by(|(_, a, _), (_, b, _)| {
+                a.distance_squared(position)
+                    .total_cmp(&b.distance_squared(position))
+            });
+        let target_position = match target {
+            Some((entity, target_position, target_radius)) => {
+                if target_position.distance(position) <= bubble.radius() + target_radius {
+                    commands.entity(entity).despawn_recursive();
+                    stolen.insert(entity);
+                    spawn_floating_text(
+                        &mut commands,
+                        transform.translation,
+                        "Stolen".to_string(),
+                        GRAY.into(),
+                    );
+                    continue;
+                }
+                target_position
+            }
+            None => player_transform.translation.xz(),
+        };
+
+        let desired = (target_position - position).normalize_or_zero() * THIEF_SPEED;
+        velocity.0 = velocity.0.lerp(desired, turn_amount);
+    }
+}
+
+//with offscreen_throttling, bubbles outside the view only collect their movement and apply it
+//every OFFSCREEN_UPDATE_INTERVAL ticks, which saves touching their transforms (and everything that
+//reacts to that) on most ticks; the total distance stays the same
+//this changes the gameplay: the stale positions off screen decide what thieves steal, what bomb
+//and timed blasts pop and what repel pushes. the view is not part of a replay, so this is off
+//while recording or playing one, and runs with it don't count for the best times
+#[allow(clippy::too_many_arguments)]
+fn move_bubbles(
+    mut bubble_query: Query<(
+        Entity,
+        &mut Transform,
+        &Velocity,
+        &Bubble,
+        &BobPhase,
+        &mut SkippedMovement,
+    )>,
+    game_clock: Res<GameClock>,
+    current: Res<Current>,
+    active_effects: Res<ActiveEffects>,
+    settings: Res<Settings>,
+    camera_frustum: Single<&Frustum, With<Camera3d>>,
+    replay_recorder: Option<Res<ReplayRecorder>>,
+    replay_player: Option<Res<ReplayPlayer>>,
+    mut tick: Local<u32>,
+) {
+    *tick = tick.wrapping_add(1);
+    let throttle =
+        settings.offscreen_throttling && replay_recorder.is_none() && replay_player.is_none();
+
+    //the current rests while the player is frozen
+    let drift = if active_effects.is_active(EffectKind::Freeze) {
+        Vec2::ZERO
+    } else {
+        current.direction * current.strength
+    };
+
+    //note: bubbles move on the x-z-plane; with x pointing right and z pointing up
+    //the bobbing only changes y, so it never affects the collisions
+    let bob_angle = game_clock.elapsed_secs() * BUBBLE_BOB_FREQUENCY * 2.0 * PI;
+    for (entity, mut transform, velocity, bubble, bob_phase, mut skipped_movement) in
+        &mut bubble_query
+    {
+        //mines stay parked where they spawned
+        let movement = if bubble.bubble_type == BubbleType::Mine {
+            Vec2::ZERO
+        } else {
+            (velocity.0 + drift) * game_clock.delta_secs()
+        };
+
+        //the bubbles take turns, so not all off screen bubbles catch up on the same tick
+        if throttle
+            && !entity
+                .index()
+                .wrapping_add(*tick)
+                .is_multiple_of(OFFSCREEN_UPDATE_INTERVAL)
+            && !camera_frustum.intersects_sphere(
+                &bevy::render::primitives::Sphere {
+                    center: transform.translation.into(),
+                    radius: bubble.radius() + OFFSCREEN_MARGIN,
+                },
+                true,
+            )
+        {
+            skipped_movement.0 += movement;
+            continue;
+        }
+        let movement = movement + std::mem::take(&mut skipped_movement.0);
+
+        //the player always stays at y = 0, so this is the hover offset plus the bob
+        transform.translation.y =
+            BUBBLE_HOVER_OFFSET + (bob_angle + bob_phase.0).sin() * BUBBLE_BOB_AMPLITUDE;
+        transform.translation.x += movement.x;
+        transform.translation.z += movement.y;
+    }
+}
+
+fn change_current(
+    game_clock: Res<GameClock>,
+    mut timer: ResMut<CurrentChangeTimer>,
+    mut current: ResMut<Current>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    if timer.0.tick(game_clock.delta()).just_finished() {
+        current.direction = Rot2::degrees(game_rng.rng.gen::<f32>() * 360.0) * Vec2::X;
+        info!("current changed direction to {:?}", current.direction);
+    }
+}
+
+//outline of the safe area on the sea floor
+fn draw_arena_boundary(
+    mut gizmos: Gizmos,
+    arena_shape: Res<ArenaShape>,
+    plateau_size: Res<PlateauSize>,
+    invert_arena: Res<InvertArena>,
+) {
+    let scale = plateau_size.scale;
+    //red while inverted, the inside is what drains then
+    let color = if invert_arena.0 {
+        Color::srgba(1.0, 0.3, 0.3, 0.6)
+    } else {
+        Color::srgba(0.6, 0.9, 1.0, 0.5)
+    };
+    let floor = Isometry3d::new(
+        Vec3::Y * ARENA_BOUNDARY_HEIGHT,
+        Quat::from_rotation_x(PI / 2.0),
+    );
+    match arena_shape.into_inner() {
+        ArenaShape::Circle { radius } => {
+            gizmos.circle(floor, radius * scale, color).resolution(64);
+        }
+        ArenaShape::Square { half_extent } => {
+            gizmos.rect(floor, Vec2::splat(half_extent * 2.0 * scale), color);
+        }
+        ArenaShape::Polygon { points } => {
+            gizmos.linestrip(
+                points.iter().chain(points.first()).map(|point| {
+                    Vec3::new(point.x * scale, ARENA_BOUNDARY_HEIGHT, point.y * scale)
+                }),
+                color,
+            );
+        }
+    }
+
+    //the regenerating center is always a circle, whatever the arena shape
+    if invert_arena.0 {
+        return;
+    }
+    gizmos
+        .circle(
+            floor,
+            PLATEAU_REGEN_RADIUS,
+            Color::srgba(0.4, 1.0, 0.6, 0.35),
+        )
+        .resolution(48);
+}
+
+//lean the algae in the direction of the current so it can be seen
+fn sway_algae(
+    mut algae_query: Query<(&mut Transform, &AlgaeBaseRotation), With<Environment>>,
+    current: Res<Current>,
+    time: Res<Time>,
+) {
+    //the lean axis is perpendicular to the current on the x-z-plane
+    let lean_axis = Vec3::new(current.direction.y, 0.0, -current.direction.x);
+    let wobble = 0.75 + 0.25 * (time.elapsed_secs() * 2.0).sin();
+    let lean = Quat::from_axis_angle(
+        lean_axis,
+        CURRENT_ALGAE_SWAY_ANGLE * wobble * current.strength / CURRENT_STRENGTH,
+    );
+    for (mut transform, base_rotation) in &mut algae_query {
+        transform.rotation = lean * base_rotation.0;
+    }
+}
+
+//adds oxygen to the tank and banks whatever does not fit into the bonus reserve
+fn add_oxygen(oxygen_level: &mut OxygenLevel, bonus_reserve: &mut BonusReserve, amount: f32) {
+    oxygen_level.0 += amount;
+    if oxygen_level.0 > PLAYER_OXYGEN_MAX {
+        bonus_reserve.0 += oxygen_level.0 - PLAYER_OXYGEN_MAX;
+        oxygen_level.0 = PLAYER_OXYGEN_MAX;
+    }
+}
+
+//the floating numbers on screen, as spawn_floating_number expects them
+fn floating_numbers_oldest_first(
+    floating_number_query: &Query<(Entity, &FloatingText), With<FloatingNumber>>,
+) -> Vec<Entity> {
+    let mut floating_numbers: Vec<_> = floating_number_query.iter().collect();
+    floating_numbers
+        .sort_by_key(|(_, floating_text)| std::cmp::Reverse(floating_text.lifetime.elapsed()));
+    floating_numbers
+        .into_iter()
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+//the oxygen a bubble gave or took, shown as a floating number above the player
+fn change_oxygen(
+    commands: &mut Commands,
+    floating_numbers: &mut Vec<Entity>,
+    oxygen_level: &mut OxygenLevel,
+    bonus_reserve: &mut BonusReserve,
+    player_position: Vec3,
+    oxygen: f32,
+) {
+    if oxygen > 0.0 {
+        add_oxygen(oxygen_level, bonus_reserve, oxygen);
+    } else {
+        oxygen_level.0 += oxygen;
+    }
+    if oxygen != 0.0 {
+        spawn_floating_number(commands, floating_numbers, player_position, oxygen);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_bubble_hit(
+    mut commands: Commands,
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    player_query: Single<(&Transform, &mut OxygenLevel)>,
+    floating_number_query: Query<(Entity, &FloatingText), With<FloatingNumber>>,
+    mut active_effects: ResMut<ActiveEffects>,
+    mut bonus_reserve: ResMut<BonusReserve>,
+    god_mode: Res<GodMode>,
+    bubble_effects: Res<BubbleEffects>,
+    bubble_models: Res<BubbleModels>,
+    settings: Res<Settings>,
+) {
+    let (player_transform, mut oxygen_level) = player_query.into_inner();
+    let mut floating_numbers = floating_numbers_oldest_first(&floating_number_query);
+    for event in bubble_hit_event_reader.read() {
+        let Some(effect) = bubble_effects.0.get(&event.bubble_type) else {
+            continue;
+        };
+        if god_mode.0 && effect.oxygen < 0.0 {
+            continue;
+        }
+
+        let oxygen = if effect.scales_with_value {
+            effect.oxygen * event.value
+        } else {
+            effect.oxygen
+        };
+        change_oxygen(
+            &mut commands,
+            &mut floating_numbers,
+            &mut oxygen_level,
+            &mut bonus_reserve,
+            player_transform.translation,
+            oxygen,
+        );
+        if let Some(kind) = effect.timed_effect {
+            active_effects.refresh(kind, effect.duration);
+        }
+        //the reserve is worth points at game over, so this is a trade and not a free heal
+        if effect.refill {
+            oxygen_level.0 = PLAYER_OXYGEN_MAX;
+            bonus_reserve.0 = 0.0;
+            spawn_floating_text(
+                &mut commands,
+                player_transform.translation,
+                "Reserve purged".to_string(),
+                bubble_color(event.bubble_type, settings.color_palette, &bubble_models),
+            );
+        }
+    }
+}
+
+//moves the player to a random spot on the plateau when a warp bubble was hit, with a flash of
+//particles where they left and where they arrived
+#[allow(clippy::too_many_arguments)]
+fn warp_player(
+    mut commands: Commands,
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    mut player_transform: Single<&mut Transform, With<Player>>,
+    camera_follow: Single<&mut CameraFollow>,
+    mut dash: ResMut<Dash>,
+    mut game_rng: ResMut<GameRng>,
+    arena_shape: Res<ArenaShape>,
+    oxygen_trail: Res<OxygenTrail>,
+) {
+    let mut camera_follow = camera_follow.into_inner();
+    for event in bubble_hit_event_reader.read() {
+        if event.bubble_type != BubbleType::Warp {
+            continue;
+        }
+
+        //random_point only returns points inside the arena (or its center)
+        let origin = player_transform.translation;
+        let target = arena_shape.random_point(&mut game_rng.rng, origin.xz(), WARP_MIN_DISTANCE);
+        let destination = Vec3::new(target.x, origin.y, target.y);
+        player_transform.translation = destination;
+
+        //the dash sweep must not reach across the arena, and the camera jumps along instead of
+        //sliding over to the new spot
+        dash.segment_start = destination;
+        camera_follow.position += destination - origin;
+
+        for center in [origin, destination] {
+            for index in 0..WARP_FLASH_PARTICLES {
+                let offset =
+                    Vec2::from_angle(index as f32 / WARP_FLASH_PARTICLES as f32 * 2.0 * PI)
+                        * WARP_FLASH_RADIUS;
+                commands.spawn((
+                    OxygenTrailParticle {
+                        lifetime: Timer::from_seconds(
+                            OXYGEN_TRAIL_PARTICLE_LIFETIME,
+                            TimerMode::Once,
+                        ),
+                    },
+                    Mesh3d(oxygen_trail.mesh.clone()),
+                    MeshMaterial3d(oxygen_trail.material.clone()),
+                    Transform::from_translation(center + Vec3::new(offset.x, 0.5, offset.y)),
+                ));
+            }
+        }
+    }
+}
+
+//a repel bubble pushes every bubble away from the player; the push stays in their velocity, so they
+//keep drifting off until they leave or expire, and a ring of particles shows the shockwave
+fn repel_bubbles(
+    mut commands: Commands,
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    player_transform: Single<&Transform, With<Player>>,
+    mut bubble_query: Query<(&Transform, &mut Velocity), With<Bubble>>,
+    oxygen_trail: Res<OxygenTrail>,
+) {
+    for event in bubble_hit_event_reader.read() {
+        if event.bubble_type != BubbleType::Repel {
+            continue;
+        }
+
+        let center = player_transform.translation;
+        for (transform, mut velocity) in &mut bubble_query {
+            let away = (transform.translation - center).xz().normalize_or_zero();
+            velocity.0 = (velocity.0 + away * REPEL_IMPULSE).clamp_length_max(REPEL_MAX_SPEED);
+        }
+
+        for index in 0..REPEL_RING_PARTICLES {
+            let offset = Vec2::from_angle(index as f32 / REPEL_RING_PARTICLES as f32 * 2.0 * PI)
+                * REPEL_RING_RADIUS;
+            commands.spawn((
+                OxygenTrailParticle {
+                    lifetime: Timer::from_seconds(OXYGEN_TRAIL_PARTICLE_LIFETIME, TimerMode::Once),
+                },
+                Mesh3d(oxygen_trail.mesh.clone()),
+                MeshMaterial3d(oxygen_trail.material.clone()),
+                Transform::from_translation(center + Vec3::new(offset.x, 0.5, offset.y)),
+            ));
+        }
+    }
+}
+
+//attaches leeches that hit the player, drains oxygen for each attached one and lets them go
+//when their time is up or the player shakes them off by quickly reversing direction
+#[allow(clippy::too_many_arguments)]
+fn handle_leeches(
+    mut commands: Commands,
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    player_query: Single<(Entity, &Transform, &mut OxygenLevel), With<Player>>,
+    mut leech_query: Query<(Entity, &mut Leeching)>,
+    bubble_models: Res<BubbleModels>,
+    settings: Res<Settings>,
+    movement_input: Res<MovementInput>,
+    mut leech_shake: ResMut<LeechShake>,
+    is_game_over: Res<IsGameOver>,
+    god_mode: Res<GodMode>,
+    game_clock: Res<GameClock>,
+) {
+    let (player_entity, player_transform, mut oxygen_level) = player_query.into_inner();
+    for event in bubble_hit_event_reader.read() {
+        if event.bubble_type != BubbleType::Leech {
+            continue;
+        }
+        if !bubble_models.is_ready(BubbleType::Leech) {
+            continue;
+        }
+
+        //cling to the side of the player the leech came from
+        let offset = (event.position - player_transform.translation)
+            .with_y(0.0)
+            .normalize_or(Vec3::X)
+            * PLAYER_RADIUS
+            + Vec3::Y * BUBBLE_HOVER_OFFSET;
+        let mut leech_entity = commands.spawn((
+            Leeching {
+                timer: Timer::from_seconds(LEECH_DURATION, TimerMode::Once),
+            },
+            Transform::from_translation(offset)
+                .with_scale(Vec3::splat(BubbleType::Leech.radius() * 0.7)),
+            PointLight {
+                color: bubble_color(BubbleType::Leech, settings.color_palette, &bubble_models),
+                radius: BUBBLE_RADIUS,
+                intensity: BUBBLE_LIGHT_INTENSITY * 0.5,
+                range: BUBBLE_RADIUS * 1.2,
+                ..Default::default()
+            },
+        ));
+        bubble_models.insert_model(&mut leech_entity, BubbleType::Leech);
+        bubble_models.insert_silhouette(
+            &mut leech_entity,
+            BubbleType::Leech,
+            settings.color_palette,
+        );
+        let leech_entity = leech_entity.id();
+        commands.entity(player_entity).add_child(leech_entity);
+        info!("a leech attached itself");
+    }
+
+    if is_game_over.0 || leech_query.is_empty() {
+        return;
+    }
+
+    //count how often the movement direction flipped within the shake off window
+    let direction = movement_input.0.normalize_or_zero();
+    if leech_shake.window.tick(game_clock.delta()).finished() {
+        leech_shake.reversals = 0;
+    }
+    if direction != Vec2::ZERO {
+        if leech_shake.last_direction.dot(direction) < 0.0 {
+            if leech_shake.reversals == 0 {
+                leech_shake.window = Timer::from_seconds(LEECH_SHAKE_OFF_WINDOW, TimerMode::Once);
+            }
+            leech_shake.reversals += 1;
+        }
+        leech_shake.last_direction = direction;
+    }
+    let shaken_off = leech_shake.reversals >= LEECH_SHAKE_OFF_REVERSALS;
+    if shaken_off {
+        leech_shake.reversals = 0;
+        info!("leeches shaken off");
+    }
+
+    for (leech_entity, mut leeching) in &mut leech_query {
+        if !god_mode.0 {
+            oxygen_level.0 -= LEECH_DRAIN_PER_SECOND * game_clock.delta_secs();
+        }
+        if leeching.timer.tick(game_clock.delta()).finished() || shaken_off {
+            commands.entity(leech_entity).despawn_recursive();
+        }
+    }
+}
+
+//the safe zone eases towards its grown size while a grow bubble is active and back afterwards,
+//and is turned around while an invert bubble is active
+fn update_plateau_size(
+    mut plateau_size: ResMut<PlateauSize>,
+    mut invert_arena: ResMut<InvertArena>,
+    active_effects: Res<ActiveEffects>,
+    game_clock: Res<GameClock>,
+) {
+    invert_arena.set_if_neq(InvertArena(active_effects.is_active(EffectKind::Invert)));
+
+    let target_scale = if active_effects.is_active(EffectKind::Grow) {
+        PLATEAU_GROW_FACTOR
+    } else {
+        1.0
+    };
+    let max_step = PLATEAU_GROW_SPEED * game_clock.delta_secs();
+    plateau_size.scale += (target_scale - plateau_size.scale).clamp(-max_step, max_step);
+}
+
+//slowly moves banked oxygen back into the tank whenever it is not full
+fn transfer_bonus_reserve(
+    mut oxygen_level: Single<&mut OxygenLevel>,
+    mut bonus_reserve: ResMut<BonusReserve>,
+    is_game_over: Res<IsGameOver>,
+    game_clock: Res<GameClock>,
+) {
+    if is_game_over.0 || bonus_reserve.0 <= 0.0 || oxygen_level.0 >= PLAYER_OXYGEN_MAX {
+        return;
+    }
+
+    let transfer = (game_clock.delta_secs() * BONUS_RESERVE_TRANSFER_PER_SECOND)
+        .min(bonus_reserve.0)
+        .min(PLAYER_OXYGEN_MAX - oxygen_level.0);
+    bonus_reserve.0 -= transfer;
+    oxygen_level.0 += transfer;
+}
+
+fn update_bonus_reserve_bar(
+    bonus_reserve: Res<BonusReserve>,
+    mut bar_node: Single<&mut Node, With<BonusReserveBar>>,
+) {
+    //a full bar means the reserve could refill an empty tank completely
+    let fill = (bonus_reserve.0 / PLAYER_OXYGEN_MAX).clamp(0.0, 1.0);
+    bar_node.width = Val::Percent(fill * 100.0);
+}
+
+fn update_dash_indicator(
+    dash: Res<Dash>,
+    god_mode: Res<GodMode>,
+    oxygen_level: Single<&OxygenLevel, With<Player>>,
+    indicator_query: Single<(&mut Node, &mut BackgroundColor), With<DashIndicator>>,
+) {
+    let (mut node, mut background_color) = indicator_query.into_inner();
+    node.width = Val::Percent(dash.cooldown.fraction() * 100.0);
+    background_color.0 = if dash.can_dash(oxygen_level.0, god_mode.0) {
+        WHITE.into()
+    } else {
+        GRAY.into()
+    };
+}
+
+fn check_level_up(
+    score: Res<Score>,
+    mut level: ResMut<Level>,
+    settings: Res<Settings>,
+    mut level_up_event_writer: EventWriter<LevelUpEvent>,
+) {
+    //a big chain can skip a level, every level passed gets its own event
+    while let Some((_, end)) = settings.level_range(level.0) {
+        if score.0 < end {
+            break;
+        }
+        level.0 += 1;
+        level_up_event_writer.send(LevelUpEvent { level: level.0 });
+    }
+}
+
+fn update_level_progress(
+    score: Res<Score>,
+    level: Res<Level>,
+    settings: Res<Settings>,
+    mut level_up_event_reader: EventReader<LevelUpEvent>,
+    mut level_text: Single<&mut Text, With<LevelText>>,
+    bar_query: Single<(&mut Node, &mut LevelBar)>,
+    time: Res<Time>,
+) {
+    let (mut bar_node, mut level_bar) = bar_query.into_inner();
+    for event in level_up_event_reader.read() {
+        info!("level {} reached", event.level);
+        level_bar.flash.reset();
+    }
+    level_bar.flash.tick(time.delta());
+
+    let fill = match settings.level_range(level.0) {
+        Some((start, end)) => {
+            level_text.0 = format!(
+                "Level {}: {} / {}",
+                level.0,
+                score.0.saturating_sub(start),
+                end.saturating_sub(start)
+            );
+            score.0.saturating_sub(start) as f32 / end.saturating_sub(start).max(1) as f32
+        }
+        None => {
+            level_text.0 = format!("Level {} (max)", level.0);
+            1.0
+        }
+    };
+    //right after a level up the bar stays full for a moment before it starts over
+    let fill = if level_bar.flash.finished() {
+        fill
+    } else {
+        1.0
+    };
+    bar_node.width = Val::Percent(fill.clamp(0.0, 1.0) * 100.0);
+}
+
+fn toggle_controls_hint(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::F1) {
+        settings.controls_hint = !settings.controls_hint;
+    }
+}
+
+fn toggle_bubble_legend(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::KeyL) {
+        settings.bubble_legend = !settings.bubble_legend;
+    }
+}
+
+//the oxygen, the refill, the timed effects and the blast are read from BubbleEffects, so the legend matches a
+//changed manifest; the rest is what the type does in code
+fn bubble_effect_summary(bubble_type: BubbleType, effect: Option<&BubbleEffect>) -> String {
+    let mut parts = Vec::new();
+    if let Some(effect) = effect {
+        if effect.refill {
+            parts.push("fills the tank, empties the reserve".to_string());
+        }
+        if effect.oxygen != 0.0 {
+            parts.push(format!("{:+.1} oxygen", effect.oxygen));
+        }
+        if let Some(kind) = effect.timed_effect {
+            parts.push(format!(
+                "{} for {:.0}s",
+                kind.description(),
+                effect.duration
+            ));
+        }
+        if effect.blast_oxygen != 0.0 {
+            parts.push(format!(
+                "{:+.1} oxygen if you are close when it goes off",
+                effect.blast_oxygen
+            ));
+        }
+    }
+    match bubble_type {
+        BubbleType::Mine => parts.push("stays where it spawned".to_string()),
+        BubbleType::Bomb => parts.push("pops the bubbles around it".to_string()),
+        BubbleType::Leech => parts.push(format!(
+            "-{:.1} oxygen/s for {:.0}s, shake it off",
+            LEECH_DRAIN_PER_SECOND, LEECH_DURATION
+        )),
+        BubbleType::Warp => parts.push("moves you somewhere else".to_string()),
+        BubbleType::Repel => parts.push("pushes all bubbles away".to_string()),
+        BubbleType::Thief => parts.push("steals regular bubbles".to_string()),
+        BubbleType::Timed => parts.push(format!("fuse of {:.0}s", TIMED_FUSE)),
+        _ => {}
+    }
+    parts.join(", ")
+}
+
+//rebuilt when the palette, the effects or the loaded bubble types change; types that are not in
+//the manifest never spawn and are left out
+fn update_bubble_legend(
+    mut commands: Commands,
+    legend_query: Single<(Entity, &mut Visibility), With<BubbleLegend>>,
+    settings: Res<Settings>,
+    bubble_effects: Res<BubbleEffects>,
+    bubble_models: Res<BubbleModels>,
+) {
+    if !settings.is_changed() && !bubble_effects.is_changed() && !bubble_models.is_changed() {
+        return;
+    }
+
+    let (legend_entity, mut visibility) = legend_query.into_inner();
+    *visibility = if settings.bubble_legend {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    commands
+        .entity(legend_entity)
+        .despawn_descendants()
+        .with_children(|parent| {
+            for bubble_type in BubbleType::ALL
+                .into_iter()
+                .filter(|bubble_type| bubble_models.light_colors.contains_key(bubble_type))
+            {
+                parent
+                    .spawn(Node {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(6.0),
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn((
+                            Node {
+                                width: Val::Px(10.0),
+                                height: Val::Px(10.0),
+                                ..default()
+                            },
+                            BorderRadius::MAX,
+                            BackgroundColor(bubble_color(
+                                bubble_type,
+                                settings.color_palette,
+                                &bubble_models,
+                            )),
+                        ));
+                        row.spawn((
+                            Text::new(format!(
+                                "{}: {}",
+                                bubble_type.name(),
+                                bubble_effect_summary(
+                                    bubble_type,
+                                    bubble_effects.0.get(&bubble_type)
+                                )
+                            )),
+                            TextFont {
+                                font_size: 14.0,
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+//KeyW is shown as W, Escape as Escape
+fn key_name(key_code: KeyCode) -> String {
+    format!("{:?}", key_code).replace("Key", "")
+}
+
+//lists the current bindings, so the hint stays correct when they change
+fn update_controls_hint(
+    hint_query: Single<(&mut Text, &mut Visibility), With<ControlsHint>>,
+    settings: Res<Settings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let (mut text, mut visibility) = hint_query.into_inner();
+    *visibility = if settings.controls_hint {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    let movement_bindings = &settings.movement_bindings;
+    text.0 = format!(
+        "Move: {} {} {} {}\nDash: {}\nPanic clear: {}\nQuit: {}\nR: restart after the game over\nG: restart with a new plant layout\n{}: skip the tutorial\nT: trajectory lines\nF2: new plant layout each run\nF4: color palette\nF7: gamepad rumble\nF8: underwater distortion\nF9: reduced motion\nF10: adaptive difficulty\nF11: fullscreen\nV: vsync mode\nO: simple bubbles\nL: bubble legend\nF1: hide this hint",
+        key_name(movement_bindings.up),
+        key_name(movement_bindings.left),
+        key_name(movement_bindings.down),
+        key_name(movement_bindings.right),
+        key_name(movement_bindings.dash),
+        key_name(movement_bindings.panic_clear),
+        key_name(settings.general_bindings.quit),
+        key_name(TUTORIAL_SKIP_KEY),
+    );
+}
+
+fn toggle_spawn_tuning_panel(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut panel_visibility: Single<&mut Visibility, With<SpawnTuningPanel>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        panel_visibility.toggle_visible_hidden();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_tuning_panel(
+    panel_query: Single<(&mut Text, &Visibility), With<SpawnTuningPanel>>,
+    spawn_timer: Res<BubbleSpawnTimer>,
+    bubble_query: Query<(), With<Bubble>>,
+    player_transform: Single<&Transform, With<Player>>,
+    game_mode: Res<GameMode>,
+    arena_shape: Res<ArenaShape>,
+    plateau_size: Res<PlateauSize>,
+    spawn_bias: Res<SpawnBias>,
+    difficulty: Res<Difficulty>,
+    survival_time: Res<SurvivalTime>,
+    invert_arena: Res<InvertArena>,
+    playfield: Res<PlayfieldMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+) {
+    let (mut text, visibility) = panel_query.into_inner();
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+
+    let off_plateau = is_off_plateau(
+        player_transform.translation,
+        &arena_shape,
+        plateau_size.scale,
+    );
+    let drain_rate = oxygen_drain_rate(
+        player_transform.translation,
+        *game_mode,
+        *playfield,
+        *difficulty_preset,
+        &arena_shape,
+        plateau_size.scale,
+        invert_arena.0,
+    );
+    let off_plateau_drain = drain_rate - PLAYER_OXYGEN_DECREASE_PER_SECOND;
+
+    text.0 = format!(
+        "spawn interval: {:.2}s\nactive bubbles: {}\noxygen drain: {:.2}/s (base {:.2} + off-plateau {:.2})\noff plateau: {}\nharmful spawn bias: {:+.2}\nbubble speed: {:.3}",
+        spawn_timer.0.duration().as_secs_f32(),
+        bubble_query.iter().count(),
+        drain_rate,
+        PLAYER_OXYGEN_DECREASE_PER_SECOND,
+        off_plateau_drain,
+        off_plateau,
+        spawn_bias.0,
+        difficulty.bubble_speed(survival_time.0),
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn emit_oxygen_trail(
+    mut commands: Commands,
+    mut oxygen_trail: ResMut<OxygenTrail>,
+    particle_query: Query<(), With<OxygenTrailParticle>>,
+    player_transform: Single<&Transform, With<Player>>,
+    is_game_over: Res<IsGameOver>,
+    game_mode: Res<GameMode>,
+    arena_shape: Res<ArenaShape>,
+    plateau_size: Res<PlateauSize>,
+    invert_arena: Res<InvertArena>,
+    playfield: Res<PlayfieldMode>,
+    difficulty_preset: Res<DifficultyPreset>,
+    time: Res<Time>,
+) {
+    if is_game_over.0 {
+        return;
+    }
+
+    let player_translation = player_transform.translation;
+    oxygen_trail.pending_particles += oxygen_drain_rate(
+        player_translation,
+        *game_mode,
+        *playfield,
+        *difficulty_preset,
+        &arena_shape,
+        plateau_size.scale,
+        invert_arena.0,
+    ) * OXYGEN_TRAIL_PARTICLES_PER_DRAIN
+        * time.delta_secs();
+
+    let mut rng = rand::thread_rng();
+    let mut particle_count = particle_query.iter().count();
+    while oxygen_trail.pending_particles >= 1.0 {
+        oxygen_trail.pending_particles -= 1.0;
+        if particle_count >= OXYGEN_TRAIL_MAX_PARTICLES {
+            continue;
         }
+        particle_count += 1;
 
-        // spawn the game over sound
-        commands.spawn(AudioPlayer::new(
-            asset_server.load("background rumbling.wav"),
+        let jitter = Vec3::new(rng.gen::<f32>() - 0.5, 0.0, rng.gen::<f32>() - 0.5) * 0.3;
+        commands.spawn((
+            OxygenTrailParticle {
+                lifetime: Timer::from_seconds(OXYGEN_TRAIL_PARTICLE_LIFETIME, TimerMode::Once),
+            },
+            Mesh3d(oxygen_trail.mesh.clone()),
+            MeshMaterial3d(oxygen_trail.material.clone()),
+            Transform::from_translation(player_translation + Vec3::Y * 0.5 + jitter),
         ));
     }
 }
 
-fn show_game_over_screen(
+//trail particles rise and shrink until their lifetime runs out
+fn update_oxygen_trail(
     mut commands: Commands,
-    mut game_over_event_reader: EventReader<GameOverEvent>,
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    camera_transform: Single<&Transform, With<Camera3d>>,
-    player_entity: Single<Entity, With<Player>>,
+    mut particle_query: Query<(Entity, &mut OxygenTrailParticle, &mut Transform)>,
+    settings: Res<Settings>,
+    time: Res<Time>,
 ) {
-    let mut is_game_over = false;
-    for _event in game_over_event_reader.read() {
-        is_game_over = true;
+    let rise_speed = settings.particle_speed(OXYGEN_TRAIL_RISE_SPEED);
+    for (entity, mut particle, mut transform) in &mut particle_query {
+        particle.lifetime.tick(time.delta());
+        transform.translation.y += rise_speed * time.delta_secs();
+        transform.scale = Vec3::splat(1.0 - particle.lifetime.fraction());
+
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn();
+        }
     }
+}
 
-    if !is_game_over {
+//a rushing player leaves streaks pointing the way they are moving
+fn emit_rush_streaks(
+    mut commands: Commands,
+    mut rush_streaks: ResMut<RushStreaks>,
+    active_effects: Res<ActiveEffects>,
+    movement_input: Res<MovementInput>,
+    player_transform: Single<&Transform, With<Player>>,
+    time: Res<Time>,
+) {
+    if !active_effects.is_active(EffectKind::Rush) || movement_input.0 == Vec2::ZERO {
+        return;
+    }
+    if !rush_streaks.timer.tick(time.delta()).just_finished() {
         return;
     }
 
-    // create quad handlePrimitiveTopology::Qua, asset_usa
-    let screen_mesh_handle = meshes.add(Plane3d::default());
+    let direction = Vec3::new(movement_input.0.x, 0.0, movement_input.0.y);
+    let translation = player_transform.translation + Vec3::Y * BUBBLE_HOVER_OFFSET;
+    commands.spawn((
+        RushStreak {
+            lifetime: Timer::from_seconds(RUSH_STREAK_LIFETIME, TimerMode::Once),
+        },
+        Mesh3d(rush_streaks.mesh.clone()),
+        MeshMaterial3d(rush_streaks.material.clone()),
+        Transform::from_translation(translation).looking_to(direction, Vec3::Y),
+    ));
+}
 
-    // create texture handle
-    let texture_handle = materials.add(StandardMaterial {
-        base_color: Color::WHITE,
-        base_color_texture: Some(asset_server.load("Game Over2.png")),
-        perceptual_roughness: 1.0,
-        ..default()
+//streaks thin out until their lifetime runs out
+fn update_rush_streaks(
+    mut commands: Commands,
+    mut streak_query: Query<(Entity, &mut RushStreak, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (entity, mut streak, mut transform) in &mut streak_query {
+        streak.lifetime.tick(time.delta());
+        let remaining = 1.0 - streak.lifetime.fraction();
+        transform.scale = Vec3::new(remaining, remaining, 1.0);
+
+        if streak.lifetime.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+//counts down every active effect and drops the ones that ran out
+fn tick_effects(mut active_effects: ResMut<ActiveEffects>, game_clock: Res<GameClock>) {
+    let delta_secs = game_clock.delta_secs();
+    active_effects.0.retain(|_, time_remaining| {
+        *time_remaining -= delta_secs;
+        *time_remaining > 0.0
     });
+}
 
-    // calculate camera-attached transform & rotation
-    let camera_transform = camera_transform.into_inner();
-    let screen_location =
-        camera_transform.translation + camera_transform.forward() * GAME_OVER_SCREEN_DISTANCE;
+//icy halo around the player and the shrinking indicator while frozen
+fn show_freeze_effect(
+    mut commands: Commands,
+    active_effects: Res<ActiveEffects>,
+    freeze_halo: Res<FreezeHalo>,
+    halo_query: Query<Entity, With<FreezeHaloRing>>,
+    player_entity: Single<Entity, With<Player>>,
+    indicator_query: Single<(&mut Node, &mut Visibility), With<FreezeIndicator>>,
+) {
+    let (mut indicator_node, mut indicator_visibility) = indicator_query.into_inner();
+    if active_effects.is_active(EffectKind::Freeze) {
+        if halo_query.is_empty() {
+            let halo_entity = commands
+                .spawn((
+                    FreezeHaloRing,
+                    Mesh3d(freeze_halo.mesh.clone()),
+                    MeshMaterial3d(freeze_halo.material.clone()),
+                    Transform::from_xyz(0.0, BUBBLE_HOVER_OFFSET, 0.0),
+                ))
+                .id();
+            commands.entity(*player_entity).add_child(halo_entity);
+        }
 
-    let game_over_screen_id = commands
-        .spawn((
-            Mesh3d(screen_mesh_handle.clone()),
-            MeshMaterial3d(texture_handle.clone()),
-            Transform::from_translation(screen_location).with_rotation(Quat::from_euler(
-                EulerRot::XYZ,
-                0.4,
-                0.0,
-                0.0,
-            )),
-        ))
-        .id();
+        let fraction = (active_effects.remaining(EffectKind::Freeze)
+            / BUBBLE_EFFECT_FREEZE_DURATION)
+            .clamp(0.0, 1.0);
+        indicator_node.width = Val::Px(FREEZE_INDICATOR_SIZE * fraction);
+        indicator_node.height = Val::Px(FREEZE_INDICATOR_SIZE * fraction);
+        *indicator_visibility = Visibility::Inherited;
+    } else {
+        for halo_entity in &halo_query {
+            commands.entity(halo_entity).despawn_recursive();
+        }
+        *indicator_visibility = Visibility::Hidden;
+    }
+}
 
-    commands
-        .entity(player_entity.into_inner())
-        .add_child(game_over_screen_id);
+fn update_bubble_grid(
+    mut bubble_grid: ResMut<BubbleGrid>,
+    bubble_query: Query<(Entity, &Transform), With<Bubble>>,
+) {
+    bubble_grid.clear();
+    for (entity, transform) in &bubble_query {
+        bubble_grid.insert(entity, transform.translation);
+    }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // create a player entity and the camera
-    // we need to do this in setup because the player_movement requires the an entity with
-    // a player component Tag and a Transform
-    let camera_direction: Vec3 = Vec3::normalize(Vec3::new(0.0, 1.0, 0.0));
-    commands
-        .spawn((
-            Player,
-            OxygenLevel(PLAYER_OXYGEN_START_SUPPLY),
-            Transform::default(),
-            InheritedVisibility::VISIBLE,
-        ))
-        .with_children(|parent| {
-            parent.spawn((
-                Camera3d::default(),
-                Transform::from_xyz(0.0, 10.0, 3.0).looking_at(camera_direction, Vec3::Y),
-            ));
+//regular bubbles that touch merge into a bigger one, harmful bubbles pop the regular ones they touch
+fn bubble_interactions(
+    mut commands: Commands,
+    mut bubble_query: Query<(Entity, &mut Transform, &mut Bubble, Option<&mut PointLight>)>,
+    bubble_grid: Res<BubbleGrid>,
+) {
+    if !BUBBLE_MERGING_ENABLED {
+        return;
+    }
 
-            parent.spawn((
-                SpotLight {
-                    color: GREY.into(),
-                    intensity: 500_000.0,
-                    range: 5.0,
-                    radius: 10.0,
-                    inner_angle: 80.0,
-                    shadows_enabled: true,
-                    ..Default::default()
-                },
-                Transform::from_xyz(0.0, 2.0, 0.0).looking_at(Vec3::ZERO, Vec3::Y),
-            ));
+    let bubbles: Vec<(Entity, BoundingSphere, BubbleType, f32)> = bubble_query
+        .iter()
+        .map(|(entity, transform, bubble, _)| {
+            (
+                entity,
+                //bubbles bob out of step, only the x-z plane decides whether they touch
+                BoundingSphere::new(transform.translation.with_y(0.0), bubble.radius()),
+                bubble.bubble_type,
+                bubble.value,
+            )
+        })
+        .collect();
+    let bubble_indices: HashMap<Entity, usize> = bubbles
+        .iter()
+        .enumerate()
+        .map(|(index, (entity, _, _, _))| (*entity, index))
+        .collect();
 
-            parent.spawn((
-                SpotLight {
-                    color: WHITE.into(),
-                    intensity: 100_000.0,
-                    range: GAME_OVER_SCREEN_DISTANCE * 2.0,
-                    radius: 10.0,
-                    inner_angle: 1.0,
-                    outer_angle: 100.0,
-                    ..Default::default()
-                },
-                Transform::from_xyz(0.0, 10.0, 3.0).looking_at(camera_direction, Vec3::Y),
-            ));
-        });
+    let mut removed: HashSet<Entity> = HashSet::new();
+    let mut merged_values: HashMap<Entity, f32> = HashMap::new();
+    for (index, (entity, sphere, bubble_type, value)) in bubbles.iter().enumerate() {
+        if removed.contains(entity) {
+            continue;
+        }
+        let search_radius = sphere.radius() + BUBBLE_MAX_RADIUS;
+        for other_entity in bubble_grid.nearby(sphere.center.into(), search_radius) {
+            //every pair is handled once, by the bubble that comes first
+            let Some(&other_index) = bubble_indices.get(&other_entity) else {
+                continue;
+            };
+            if other_index <= index || removed.contains(&other_entity) {
+                continue;
+            }
+            let (_, other_sphere, other_type, other_value) = bubbles[other_index];
+            if !sphere.intersects(&other_sphere) {
+                continue;
+            }
 
-    // create light
-    commands.insert_resource(AmbientLight {
-        color: ROYAL_BLUE.into(),
-        brightness: 100.0,
-    });
+            match (*bubble_type, other_type) {
+                (BubbleType::Regular, BubbleType::Regular) => {
+                    //only merge bubbles that have not merged this tick so sizes stay readable
+                    if merged_values.contains_key(entity)
+                        || merged_values.contains_key(&other_entity)
+                    {
+                        continue;
+                    }
+                    merged_values
+                        .insert(*entity, (value + other_value).min(BUBBLE_MERGE_MAX_VALUE));
+                    removed.insert(other_entity);
+                }
+                (BubbleType::Regular, harmful) if harmful.is_harmful() => {
+                    removed.insert(*entity);
+                }
+                (harmful, BubbleType::Regular) if harmful.is_harmful() => {
+                    removed.insert(other_entity);
+                }
+                _ => {}
+            }
+        }
+    }
 
-    /*
-    //FOR DEBUGGING
-    commands.insert_resource(AmbientLight {
-        color: WHITE.into(),
-        brightness: 10_000.0,
-    });
-     */
+    for (entity, value) in merged_values {
+        if removed.contains(&entity) {
+            continue;
+        }
+        if let Ok((_, mut transform, mut bubble, point_light)) = bubble_query.get_mut(entity) {
+            bubble.value = value;
+            transform.scale = Vec3::splat(bubble.radius());
+            if let Some(mut point_light) = point_light {
+                point_light.radius = bubble.radius();
+                point_light.range = bubble.radius() * 1.2;
+                point_light.intensity = BUBBLE_LIGHT_INTENSITY;
+            }
+            //a merged bubble is fully grown, stop the spawn fade from shrinking it again
+            commands.entity(entity).remove::<SpawnFade>();
+        }
+    }
+    for entity in removed {
+        commands.entity(entity).despawn_recursive();
+    }
+}
 
-    // create flag resources
-    commands.insert_resource(IsGameOver(false));
+//a bomb the player touched pops every bubble around it, and bombs caught in the blast go off too
+fn detonate_bombs(
+    mut commands: Commands,
+    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
+    mut chain_event_writer: EventWriter<ChainEvent>,
+    bubble_query: Query<(&Transform, &Bubble)>,
+    bubble_grid: Res<BubbleGrid>,
+) {
+    for event in bubble_hit_event_reader.read() {
+        if event.bubble_type != BubbleType::Bomb {
+            continue;
+        }
 
-    commands.insert_resource(BubbleFreezeEffect {
-        time_remaining: 0.0,
-    });
+        let mut popped: HashSet<Entity> = HashSet::new();
+        let mut blasts = vec![event.position];
+        while let Some(blast_center) = blasts.pop() {
+            let blast_sphere = BoundingSphere::new(blast_center.with_y(0.0), BOMB_BLAST_RADIUS);
+            for entity in bubble_grid.nearby(blast_center, BOMB_BLAST_RADIUS + BUBBLE_MAX_RADIUS) {
+                let Ok((transform, bubble)) = bubble_query.get(entity) else {
+                    continue;
+                };
+                let bubble_sphere =
+                    BoundingSphere::new(transform.translation.with_y(0.0), bubble.radius());
+                if popped.contains(&entity) || !blast_sphere.intersects(&bubble_sphere) {
+                    continue;
+                }
+                popped.insert(entity);
+                if bubble.bubble_type == BubbleType::Bomb {
+                    blasts.push(transform.translation);
+                }
+            }
+        }
 
-    info!("init loading assets...");
+        for entity in &popped {
+            commands.entity(*entity).despawn_recursive();
+        }
+        if !popped.is_empty() {
+            info!("bomb popped a chain of {} bubbles", popped.len());
+            chain_event_writer.send(ChainEvent {
+                length: popped.len() as u32,
+                center: event.position,
+            });
+        }
+    }
+}
 
-    //store material mapping for the bubbles
-    commands.insert_resource(BubbleModels(HashMap::from([])));
+//longer chains are worth more per bubble
+fn score_chains(
+    mut commands: Commands,
+    mut chain_event_reader: EventReader<ChainEvent>,
+    mut score: ResMut<Score>,
+) {
+    for event in chain_event_reader.read() {
+        score.0 += CHAIN_POINTS_PER_POP * event.length * event.length;
+        spawn_floating_text(
+            &mut commands,
+            event.center,
+            format!("x{} chain!", event.length),
+            ORANGE.into(),
+        );
+    }
+}
 
-    //load gltF files
-    commands.insert_resource(AssetsLoadingGltf(HashMap::from([
-        ("player_character".into(), asset_server.load("Player.glb")),
-        ("alge".into(), asset_server.load("Alge.glb")),
-        ("sand".into(), asset_server.load("Sand.glb")),
-        ("plateau".into(), asset_server.load("Plateau.glb")),
-        ("bubble_rot".into(), asset_server.load("Bubble Rot.glb")),
-        ("bubble_dirt".into(), asset_server.load("Bubble Dirt.glb")),
-        (
-            "bubble_freeze".into(),
-            asset_server.load("Bubble Freeze.glb"),
-        ),
-        (
-            "bubble_regular".into(),
-            asset_server.load("Bubble Regular.glb"),
-        ),
-        ("gauge".into(), asset_server.load("Gauge.glb")),
-    ])));
+fn spawn_floating_text(
+    commands: &mut Commands,
+    world_position: Vec3,
+    text: String,
+    color: Color,
+) -> Entity {
+    commands
+        .spawn((
+            FloatingText {
+                world_position,
+                lifetime: Timer::from_seconds(FLOATING_TEXT_LIFETIME, TimerMode::Once),
+            },
+            Text::new(text),
+            TextFont {
+                font_size: 22.0,
+                ..default()
+            },
+            TextColor(color),
+            Node {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            //hidden until it has been placed on screen once
+            Visibility::Hidden,
+        ))
+        .id()
+}
 
-    info!("player character should load now...");
+//"+2" in green or "-4" in red above the player; floating_numbers holds the numbers on screen,
+//oldest first, and the oldest goes away when there are already FLOATING_NUMBER_MAX of them
+fn spawn_floating_number(
+    commands: &mut Commands,
+    floating_numbers: &mut Vec<Entity>,
+    player_position: Vec3,
+    oxygen: f32,
+) {
+    if floating_numbers.len() >= FLOATING_NUMBER_MAX {
+        commands
+            .entity(floating_numbers.remove(0))
+            .despawn_recursive();
+    }
 
-    //play music
-    commands.spawn(AudioPlayer::new(asset_server.load("Music.ogg")));
+    let text = if oxygen.fract() == 0.0 {
+        format!("{:+.0}", oxygen)
+    } else {
+        format!("{:+.1}", oxygen)
+    };
+    let color = if oxygen > 0.0 { LIME } else { RED };
+    let entity = spawn_floating_text(
+        commands,
+        player_position + Vec3::Y * FLOATING_NUMBER_HEIGHT,
+        text,
+        color.into(),
+    );
+    commands.entity(entity).insert(FloatingNumber);
+    floating_numbers.push(entity);
+}
 
-    commands.spawn(AudioPlayer::new(
-        asset_server.load("Stereotypische unterwasser Atmo.mp3"),
-    ));
+fn update_floating_texts(
+    mut commands: Commands,
+    mut floating_texts: Query<(
+        Entity,
+        &mut FloatingText,
+        &mut Node,
+        &mut TextColor,
+        &mut Visibility,
+    )>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<Camera3d>>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let (camera, camera_transform) = camera_query.into_inner();
+    let rise_speed = settings.particle_speed(FLOATING_TEXT_RISE_SPEED);
+    for (entity, mut floating_text, mut node, mut text_color, mut visibility) in &mut floating_texts
+    {
+        floating_text.lifetime.tick(time.delta());
+        if floating_text.lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
 
-    commands.insert_resource(BubbleHitAudioSource(
-        asset_server.load("collect bubble.flac"),
-    ));
+        floating_text.world_position.y += rise_speed * time.delta_secs();
+        if let Ok(viewport_position) =
+            camera.world_to_viewport(camera_transform, floating_text.world_position)
+        {
+            node.left = Val::Px(viewport_position.x);
+            node.top = Val::Px(viewport_position.y);
+            *visibility = Visibility::Inherited;
+        }
+        text_color.0 = text_color
+            .0
+            .with_alpha(1.0 - floating_text.lifetime.fraction());
+    }
 }
 
-//effecgively doubles the oxygen loss when outside the plateau
-fn enforce_plateau_limits(
-    player_transform: Single<&Transform, With<Player>>,
-    mut oxygen_level: Single<&mut OxygenLevel>,
-    time: Res<Time>,
+//a bubble spawning off screen gets a ghost in its color on the closest point of the screen edge,
+//so its type and direction are known before it drifts into view
+//hard and hardcore runs get no previews, they are help like the refilling center
+fn show_spawn_previews(
+    mut commands: Commands,
+    new_bubbles: Query<(&Bubble, &Transform), Added<Bubble>>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<Camera3d>>,
+    settings: Res<Settings>,
+    bubble_models: Res<BubbleModels>,
+    game_mode: Res<GameMode>,
+    difficulty_preset: Res<DifficultyPreset>,
 ) {
-    let player_transform = player_transform.into_inner();
-    //if the player is ever attached anywhere this needs changing
-    let player_coordinates_2d = Vec2::from_array([
-        player_transform.translation.x,
-        player_transform.translation.z,
-    ]);
+    if !settings.spawn_preview
+        || *game_mode == GameMode::Hardcore
+        || *difficulty_preset == DifficultyPreset::Hard
+    {
+        return;
+    }
 
-    //info!("player translation 2d: {:?}", player_coordinates_2d);
+    let (camera, camera_transform) = camera_query.into_inner();
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return;
+    };
+    for (bubble, bubble_transform) in &new_bubbles {
+        let Ok(viewport_position) =
+            camera.world_to_viewport(camera_transform, bubble_transform.translation)
+        else {
+            continue;
+        };
+        let on_screen = viewport_position.cmpge(Vec2::ZERO).all()
+            && viewport_position.cmple(viewport_size).all();
+        if on_screen {
+            continue;
+        }
 
-    if player_coordinates_2d.length_squared() > powf(PLATEAU_RADIUS, 2.0) {
-        oxygen_level.0 -= time.delta_secs() * PLAYER_OXYGEN_DECREASE_PER_SECOND;
+        let half_size = SPAWN_PREVIEW_SIZE * 0.5 + SPAWN_PREVIEW_EDGE_MARGIN;
+        let edge_position =
+            viewport_position.clamp(Vec2::splat(half_size), viewport_size - half_size);
+        commands.spawn((
+            SpawnPreview {
+                lifetime: Timer::from_seconds(SPAWN_PREVIEW_DURATION, TimerMode::Once),
+            },
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(edge_position.x - SPAWN_PREVIEW_SIZE * 0.5),
+                top: Val::Px(edge_position.y - SPAWN_PREVIEW_SIZE * 0.5),
+                width: Val::Px(SPAWN_PREVIEW_SIZE),
+                height: Val::Px(SPAWN_PREVIEW_SIZE),
+                ..default()
+            },
+            BackgroundColor(
+                bubble_color(bubble.bubble_type, settings.color_palette, &bubble_models)
+                    .with_alpha(SPAWN_PREVIEW_ALPHA),
+            ),
+            BorderRadius::MAX,
+        ));
     }
 }
 
-fn clear_old_sounds(
+fn update_spawn_previews(
     mut commands: Commands,
-    bubble_hit_sounds: Query<(&AudioSink, Entity), With<BubbleHitSound>>,
+    mut previews: Query<(Entity, &mut SpawnPreview, &mut BackgroundColor)>,
+    time: Res<Time>,
 ) {
-    for (sound, entity) in bubble_hit_sounds.iter() {
-        // the audio sink being "empty" means there are not sound effects in it's internal queue for it to play
-        // this happens when a sound is only played once - like the hit effects
-        if sound.empty() {
-            commands.entity(entity).despawn();
-            //info!("despawning sound sink");
+    for (entity, mut preview, mut background_color) in &mut previews {
+        preview.lifetime.tick(time.delta());
+        if preview.lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        background_color.0 = background_color
+            .0
+            .with_alpha(SPAWN_PREVIEW_ALPHA * (1.0 - preview.lifetime.fraction()));
+    }
+}
+
+//runs first in the fixed chain, so every tick that is played with god mode on is caught
+fn latch_god_mode(god_mode: Res<GodMode>, mut god_mode_used: ResMut<GodModeUsed>) {
+    if god_mode.0 {
+        god_mode_used.0 = true;
+    }
+}
+
+fn toggle_god_mode(keyboard_input: Res<ButtonInput<KeyCode>>, mut god_mode: ResMut<GodMode>) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        god_mode.0 = !god_mode.0;
+        info!("god mode {}", if god_mode.0 { "on" } else { "off" });
+    }
+}
+
+//T toggles the trajectory assist, Shift+T the brighter debug lines in debug builds
+fn toggle_trajectories(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut show_trajectories: ResMut<ShowTrajectories>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+
+    if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        if cfg!(debug_assertions) {
+            show_trajectories.0 = !show_trajectories.0;
         }
+    } else {
+        settings.trajectory_assist = !settings.trajectory_assist;
+        info!(
+            "trajectory assist {}",
+            if settings.trajectory_assist {
+                "on"
+            } else {
+                "off"
+            }
+        );
     }
 }
 
-fn reduce_oxygen_level(
-    mut oxygen_level: Single<&mut OxygenLevel>,
-    time: Res<Time>,
-    mut game_over_event_writer: EventWriter<GameOverEvent>,
-    mut is_game_over: ResMut<IsGameOver>,
+//a line from every moving bubble to where it will be after TRAJECTORY_LOOKAHEAD seconds
+fn draw_bubble_trajectories(
+    bubble_query: Query<(&Transform, &Velocity, &Bubble)>,
+    current: Res<Current>,
+    active_effects: Res<ActiveEffects>,
+    settings: Res<Settings>,
+    bubble_models: Res<BubbleModels>,
+    show_trajectories: Res<ShowTrajectories>,
+    mut gizmos: Gizmos,
 ) {
-    if is_game_over.0 {
+    let alpha = if show_trajectories.0 {
+        1.0
+    } else if settings.trajectory_assist {
+        TRAJECTORY_ASSIST_ALPHA
+    } else {
         return;
-    }
+    };
 
-    if oxygen_level.0 <= 0.0_f32 {
-        game_over_event_writer.send(GameOverEvent {});
-        is_game_over.0 = true;
-        return;
+    //same movement as in move_bubbles
+    let drift = if active_effects.is_active(EffectKind::Freeze) {
+        Vec2::ZERO
     } else {
-        oxygen_level.0 -= time.delta_secs() * PLAYER_OXYGEN_DECREASE_PER_SECOND;
+        current.direction * current.strength
+    };
+    for (transform, velocity, bubble) in &bubble_query {
+        if bubble.bubble_type == BubbleType::Mine {
+            continue;
+        }
+        let movement = velocity.0 + drift;
+        let start = transform.translation;
+        let end = start + Vec3::new(movement.x, 0.0, movement.y) * TRAJECTORY_LOOKAHEAD;
+        gizmos.line(
+            start,
+            end,
+            bubble_color(bubble.bubble_type, settings.color_palette, &bubble_models)
+                .with_alpha(alpha),
+        );
     }
 }
 
-fn player_effects(
+//F6 toggles drawing the real hitbox, [ and ] shrink and grow it while it is shown
+fn debug_hitbox(
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    player_query: Single<(&mut Transform, &OxygenLevel), With<Player>>,
-    zeiger_query: Option<Single<&mut Transform, (With<Zeiger>, Without<Player>)>>,
-    time: Res<Time>,
-    is_game_over: Res<IsGameOver>,
-    bubble_freeze_effect: Res<BubbleFreezeEffect>,
+    mut show_hitbox: ResMut<ShowHitbox>,
+    mut player_stats: ResMut<PlayerStats>,
+    player_transform: Single<&Transform, With<Player>>,
+    mut gizmos: Gizmos,
 ) {
-    //block input after game over or when frozen
-    if is_game_over.0 || bubble_freeze_effect.time_remaining > 0.0 {
+    if !cfg!(debug_assertions) {
         return;
     }
 
-    let mut movement: Vec2 = Vec2::new(0.0, 0.0);
-    if keyboard_input.pressed(KeyCode::KeyE) {
-        movement += Vec2::new(0.0, -1.0);
-    }
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        movement += Vec2::new(0.0, 1.0);
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        show_hitbox.0 = !show_hitbox.0;
     }
-    if keyboard_input.pressed(KeyCode::KeyS) {
-        movement += Vec2::new(-1.0, 0.0);
+    if !show_hitbox.0 {
+        return;
     }
-    if keyboard_input.pressed(KeyCode::KeyF) {
-        movement += Vec2::new(1.0, 0.0);
+
+    if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+        player_stats.radius = (player_stats.radius - PLAYER_RADIUS_STEP).max(PLAYER_RADIUS_STEP);
+        info!("player radius: {:.2}", player_stats.radius);
     }
-    let (mut player_transform, oxygen_level) = player_query.into_inner();
-    if Vec2::length_squared(movement) > 0.0 {
-        movement = time.delta_secs() * PLAYER_MOVEMENT_SPEED * Vec2::normalize(movement);
-        player_transform.translation.x += movement.x;
-        player_transform.translation.z += movement.y;
+    if keyboard_input.just_pressed(KeyCode::BracketRight) {
+        player_stats.radius += PLAYER_RADIUS_STEP;
+        info!("player radius: {:.2}", player_stats.radius);
     }
 
-    if let Some(zeiger_query) = zeiger_query {
-        let mut zeiger_transform = zeiger_query.into_inner();
-        let mut angle = (oxygen_level.0 / PLAYER_OXYGEN_START_SUPPLY).clamp(0.0, 1.0);
-        angle = (angle - 0.5) * 2.0;
-        angle *= -3.0 * PI / 4.0;
-        zeiger_transform.rotation = Quat::from_axis_angle(Vec3::Y, angle);
-    }
+    gizmos.sphere(
+        Isometry3d::from_translation(player_transform.translation),
+        player_stats.radius,
+        LIME,
+    );
 }
 
-fn bubble_spawns(
-    mut commands: Commands,
-    time: Res<Time>,
-    mut timer: ResMut<BubbleSpawnTimer>,
-    bubble_models: Res<BubbleModels>,
+//F5 toggles a readout of the bubble closest to the player, with a line to it
+fn debug_bubble_inspector(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    inspector_query: Single<(&mut Text, &mut Visibility), With<BubbleInspectorText>>,
     player_transform: Single<&Transform, With<Player>>,
-    is_game_over: Res<IsGameOver>,
+    bubble_query: Query<(&Transform, &Bubble, Option<&Velocity>, Option<&Lifetime>)>,
+    mut gizmos: Gizmos,
 ) {
-    if is_game_over.into_inner().0 {
+    if !cfg!(debug_assertions) {
         return;
     }
 
-    //do not run until all models are loaded
-    let mut rng = rand::thread_rng();
-
-    //randomly decide bubble type
-    let bubble_type = match rng.gen_range(0..4) {
-        0 => BubbleType::Regular,
-        1 => BubbleType::Blood,
-        2 => BubbleType::Dirt,
-        3 => BubbleType::Freeze,
-        _ => BubbleType::Regular,
-    };
-
-    if bubble_models.0.get(&bubble_type).is_none() {
-        warn!("no model loaded for bubble type {:?}", &bubble_type);
-        //just don't spawn until all models are loaded
+    let (mut text, mut visibility) = inspector_query.into_inner();
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        visibility.toggle_visible_hidden();
+    }
+    if *visibility == Visibility::Hidden {
         return;
     }
 
-    if timer.0.tick(time.delta()).just_finished() {
-        let player_translation = player_transform.into_inner().translation;
-        let random_rotation = rng.gen::<f32>();
-        let rotation_vector = Rot2::degrees(random_rotation * 360.0);
+    let player_translation = player_transform.translation;
+    let nearest_bubble = bubble_query.iter().min_by(|(a, ..), (b, ..)| {
+        a.translation
+            .distance_squared(player_translation)
+            .total_cmp(&b.translation.distance_squared(player_translation))
+    });
+    let Some((bubble_transform, bubble, velocity, lifetime)) = nearest_bubble else {
+        text.0 = "no bubbles".to_string();
+        return;
+    };
 
-        // generate random position on edge of circle around player transform
-        let spawn_location = Vec3::from_array([
-            player_translation.x + rotation_vector.cos * BUBBLE_SPAWN_RADIUS,
-            player_translation.y + BUBBLE_HOVER_OFFSET,
-            player_translation.z + rotation_vector.sin * BUBBLE_SPAWN_RADIUS,
-        ]);
+    gizmos.line(player_translation, bubble_transform.translation, WHITE);
+    text.0 = format!(
+        "nearest bubble: {}\ndistance: {:.2}\nspeed: {:.2}\nlifetime left: {:.1}s",
+        bubble.bubble_type.name(),
+        bubble_transform.translation.distance(player_translation),
+        velocity.map_or(0.0, |velocity| velocity.0.length()),
+        lifetime.map_or(0.0, |lifetime| lifetime.timer.remaining_secs()),
+    );
+}
 
-        // calculate movement angle directly at player
-        let bubble_movement_direction = Vec2::from([
-            (player_translation.x - spawn_location.x) * BUBBLE_MOVEMENT_SPEED,
-            (player_translation.z - spawn_location.z) * BUBBLE_MOVEMENT_SPEED,
-        ]);
+//C toggles the circle around the player that bubbles spawn on, labeled with its radius
+fn debug_spawn_circle(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    label_query: Single<(&mut Text, &mut Node, &mut Visibility), With<SpawnCircleLabel>>,
+    player_transform: Single<&Transform, With<Player>>,
+    camera_query: Single<(&Camera, &GlobalTransform), With<Camera3d>>,
+    playfield: Res<PlayfieldMode>,
+    mut gizmos: Gizmos,
+) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
 
-        commands.spawn((
-            Transform::from_translation(spawn_location).with_scale(Vec3::splat(BUBBLE_RADIUS)),
-            Velocity(bubble_movement_direction),
-            SceneRoot(bubble_models.0.get(&bubble_type).unwrap().clone().unwrap()),
-            MeshMaterial3d::<StandardMaterial>::default(),
-            PointLight {
-                color: match &bubble_type {
-                    BubbleType::Blood => RED.into(),
-                    BubbleType::Dirt => GREEN.into(),
-                    BubbleType::Freeze => WHITE.into(),
-                    BubbleType::Regular => YELLOW.into(),
-                },
-                radius: BUBBLE_RADIUS,
-                intensity: 10_000.0,
-                range: BUBBLE_RADIUS * 1.2,
-                ..Default::default()
-            },
-            Bubble {
-                bubble_type: bubble_type,
-            },
-        ));
+    let (mut text, mut node, mut visibility) = label_query.into_inner();
+    if keyboard_input.just_pressed(KeyCode::KeyC) {
+        visibility.toggle_visible_hidden();
+    }
+    if *visibility == Visibility::Hidden {
+        return;
     }
-}
 
-fn move_bubbles(
-    mut bubble_query: Query<(&mut Transform, &Velocity), With<Bubble>>,
-    time: Res<Time>,
-) {
-    //note: bubbles move on the x-z-plane; with x pointing right and z pointing up
-    for (mut transform, velocity) in &mut bubble_query {
-        transform.translation.x += velocity.0.x * time.delta_secs();
-        transform.translation.z += velocity.0.y * time.delta_secs();
+    let center = player_transform.translation;
+    let radius = playfield.spawn_radius();
+    gizmos
+        .circle(
+            Isometry3d::new(center, Quat::from_rotation_x(PI / 2.0)),
+            radius,
+            YELLOW,
+        )
+        .resolution(64);
+
+    let (camera, camera_transform) = camera_query.into_inner();
+    if let Ok(viewport_position) =
+        camera.world_to_viewport(camera_transform, center + Vec3::X * radius)
+    {
+        node.left = Val::Px(viewport_position.x);
+        node.top = Val::Px(viewport_position.y);
     }
+    text.0 = format!("spawn radius: {:.1}", radius);
 }
 
-fn handle_bubble_hit(
-    mut bubble_hit_event_reader: EventReader<BubbleHitEvent>,
-    mut oxygen_level: Single<&mut OxygenLevel>,
-    mut bubble_freeze_effect: ResMut<BubbleFreezeEffect>,
-) {
-    for event in bubble_hit_event_reader.read() {
-        match event.bubble_type {
-            BubbleType::Regular => {
-                oxygen_level.0 += BUBBLE_EFFECT_OXYGEN_INCREASE;
-            }
-            BubbleType::Dirt => {
-                oxygen_level.0 -= BUBBLE_EFFECT_OXYGEN_DECREASE_SMALL;
-            }
-            BubbleType::Freeze => {
-                bubble_freeze_effect.time_remaining = BUBBLE_EFFECT_FREEZE_DURATION;
-                oxygen_level.0 += BUBBLE_EFFECT_OXYGEN_INCREASE * 0.5;
-            }
-            BubbleType::Blood => {
-                oxygen_level.0 -= BUBBLE_EFFECT_OXYGEN_DECREASE_BIG;
-            }
-        }
+//shortest distance from a point to the line segment between start and end
+fn distance_to_segment(point: Vec3, start: Vec3, end: Vec3) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared == 0.0 {
+        return point.distance(start);
     }
+    let along = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    point.distance(start + segment * along)
 }
 
-fn run_bubble_freeze_timer(time: Res<Time>, mut bubble_freeze_effect: ResMut<BubbleFreezeEffect>) {
-    if bubble_freeze_effect.time_remaining > 0.0 {
-        bubble_freeze_effect.time_remaining -= time.delta_secs();
-    }
+//only the geometry of a hit, check_collisions does everything that follows from it
+//everything is compared on the x-z plane, so the hover offset and the bobbing never decide a hit
+//returns the index and type of every bubble (position, type, radius) that hits the player;
+//the radius is passed along because merged bubbles are bigger than their type
+//touching counts as a hit; while dashing, everything within pop_radius of the dash segment
+//(start, end, pop_radius) is hit as well
+fn bubbles_hitting_player(
+    player_position: Vec3,
+    player_radius: f32,
+    dash_sweep: Option<(Vec3, Vec3, f32)>,
+    bubbles: &[(Vec3, BubbleType, f32)],
+) -> Vec<(usize, BubbleType)> {
+    let player_sphere = BoundingSphere::new(player_position.with_y(0.0), player_radius);
+    bubbles
+        .iter()
+        .enumerate()
+        .filter(|(_, (bubble_position, _, bubble_radius))| {
+            let bubble_position = bubble_position.with_y(0.0);
+            let bubble_sphere = BoundingSphere::new(bubble_position, *bubble_radius);
+            let dash_popped = dash_sweep.is_some_and(|(start, end, pop_radius)| {
+                distance_to_segment(bubble_position, start.with_y(0.0), end.with_y(0.0))
+                    <= pop_radius + bubble_radius
+            });
+            dash_popped || bubble_sphere.intersects(&player_sphere)
+        })
+        .map(|(index, (_, bubble_type, _))| (index, *bubble_type))
+        .collect()
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn check_collisions(
     mut commands: Commands,
     player_query: Single<&Transform, With<Player>>,
-    bubble_query: Query<(Entity, &Transform, &Bubble)>,
+    bubble_query: Query<(
+        Entity,
+        &Transform,
+        &Bubble,
+        Option<&Velocity>,
+        Has<NearMissCounted>,
+    )>,
     mut bubble_event_write: EventWriter<BubbleHitEvent>,
     bubble_hit_audio_source: Res<BubbleHitAudioSource>,
+    settings: Res<Settings>,
+    mut collection_stats: ResMut<CollectionStats>,
+    bubble_grid: Res<BubbleGrid>,
+    player_stats: Res<PlayerStats>,
+    mut near_misses: ResMut<NearMisses>,
+    mut score: ResMut<Score>,
+    dash: Res<Dash>,
 ) {
     let player_transform = player_query.into_inner();
-    let player_sphere = BoundingSphere::new(player_transform.translation, PLAYER_RADIUS);
-    for (bubble_entity, bubble_transform, bubble) in &bubble_query {
-        let bubble_sphere = BoundingSphere::new(bubble_transform.translation, BUBBLE_RADIUS);
-        if bubble_sphere.intersects(&player_sphere) {
+
+    //while dashing everything along the dash path within a wider radius is popped
+    let (dash_segment, pop_radius) = if dash.is_active() {
+        (
+            Some((dash.segment_start, player_transform.translation)),
+            player_stats.radius + DASH_POP_RADIUS_BONUS,
+        )
+    } else {
+        (None, player_stats.radius)
+    };
+    let search_center = dash_segment.map_or(player_transform.translation, |(start, end)| {
+        (start + end) * 0.5
+    });
+    let search_radius = dash_segment.map_or(0.0, |(start, end)| start.distance(end) * 0.5);
+    let nearby_bubbles = bubble_grid.nearby(
+        search_center,
+        search_radius + pop_radius + BUBBLE_MAX_RADIUS + NEAR_MISS_MARGIN,
+    );
+    let nearby_bubbles: Vec<_> = bubble_query.iter_many(nearby_bubbles).collect();
+    let bubble_geometry: Vec<_> = nearby_bubbles
+        .iter()
+        .map(|(_, bubble_transform, bubble, ..)| {
+            (
+                bubble_transform.translation,
+                bubble.bubble_type,
+                bubble.radius(),
+            )
+        })
+        .collect();
+    let mut hit = vec![false; nearby_bubbles.len()];
+    for (index, _) in bubbles_hitting_player(
+        player_transform.translation,
+        player_stats.radius,
+        dash_segment.map(|(start, end)| (start, end, pop_radius)),
+        &bubble_geometry,
+    ) {
+        hit[index] = true;
+    }
+
+    for (index, (bubble_entity, bubble_transform, bubble, velocity, near_miss_counted)) in
+        nearby_bubbles.into_iter().enumerate()
+    {
+        if !hit[index] {
+            if near_miss_counted || !bubble.bubble_type.is_harmful() {
+                continue;
+            }
+
+            //only count the miss once the bubble is inside the margin and already moving away,
+            //so a bubble that is still closing in can't be counted and then hit
+            let offset = (bubble_transform.translation - player_transform.translation).xz();
+            let near_miss_distance = player_stats.radius + bubble.radius() + NEAR_MISS_MARGIN;
+            let moving_away = velocity.is_some_and(|velocity| velocity.0.dot(offset) > 0.0);
+            if offset.length() <= near_miss_distance && moving_away {
+                commands.entity(bubble_entity).try_insert(NearMissCounted);
+                near_misses.0 += 1;
+                score.0 += NEAR_MISS_POINTS;
+                spawn_floating_text(
+                    &mut commands,
+                    bubble_transform.translation,
+                    "Close!".to_string(),
+                    WHITE.into(),
+                );
+            }
+        } else {
             commands.spawn((
                 BubbleHitSound,
                 AudioPlayer::new(bubble_hit_audio_source.0.clone()),
                 PlaybackSettings {
                     mode: PlaybackMode::Once,
+                    volume: Volume::new(settings.sfx_volume),
+                    speed: bubble.bubble_type.hit_sound_speed(),
+                    spatial: settings.spatial_audio,
                     ..default()
                 },
+                //only used for panning when spatial audio is on
+                Transform::from_translation(bubble_transform.translation),
             ));
 
-            commands.entity(bubble_entity).despawn();
+            commands.entity(bubble_entity).despawn_recursive();
 
             info!("hit by bubble of type {:?}", bubble.bubble_type);
+            *collection_stats.0.entry(bubble.bubble_type).or_insert(0) += 1;
             bubble_event_write.send(BubbleHitEvent {
-                bubble_type: match bubble.bubble_type {
-                    BubbleType::Regular => BubbleType::Regular,
-                    BubbleType::Dirt => BubbleType::Dirt,
-                    BubbleType::Freeze => BubbleType::Freeze,
-                    BubbleType::Blood => BubbleType::Blood,
-                },
+                bubble_type: bubble.bubble_type,
+                value: bubble.value,
+                position: bubble_transform.translation,
             });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::render::camera::CameraProjection;
+    use bevy::state::app::StatesPlugin;
+
+    //the real fixed update chain with every resource and entity it needs, only the player model is
+    //left out
+    //the GameClock moves one fixed tick of 1/64 s in every run of FixedUpdate, like in the game
+    fn gameplay_test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            .insert_state(GameState::Playing)
+            .add_event::<BubbleHitEvent>()
+            .add_event::<ChainEvent>()
+            .add_event::<GameOverEvent>()
+            .add_event::<WaveStartedEvent>()
+            .add_event::<WaveClearedEvent>()
+            .add_event::<SpawnThemeStartedEvent>()
+            .insert_resource(GameClock {
+                delta: std::time::Duration::from_secs_f32(1.0 / 64.0),
+                ..default()
+            })
+            .insert_resource(BubbleHitAudioSource(Handle::default()))
+            .insert_resource(GameRng::from_seed(1))
+            .insert_resource(OxygenTrail {
+                mesh: Handle::default(),
+                material: Handle::default(),
+                pending_particles: 0.0,
+            })
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<BubbleModels>()
+            .init_resource::<IsGameOver>()
+            .init_resource::<Settings>()
+            .init_resource::<BubbleGrid>()
+            .init_resource::<PlayerStats>()
+            .init_resource::<Dash>()
+            .init_resource::<MovementInput>()
+            .init_resource::<PlayerVelocity>()
+            .init_resource::<PanicClear>()
+            .init_resource::<StartOxygen>()
+            .init_resource::<CollectionStats>()
+            .init_resource::<NearMisses>()
+            .init_resource::<Score>()
+            .init_resource::<OxygenBonus>()
+            .init_resource::<ActiveEffects>()
+            .init_resource::<BonusReserve>()
+            .init_resource::<GodMode>()
+            .init_resource::<GodModeUsed>()
+            .init_resource::<BubbleEffects>()
+            .init_resource::<HitStop>()
+            .init_resource::<LeechShake>()
+            .init_resource::<Current>()
+            .init_resource::<CurrentChangeTimer>()
+            .init_resource::<OffPlateau>()
+            .init_resource::<InvertArena>()
+            .init_resource::<PlateauSize>()
+            .init_resource::<Wave>()
+            .init_resource::<SpawnQueue>()
+            .init_resource::<BubbleSpawnTimer>()
+            .init_resource::<RingBurst>()
+            .init_resource::<GameMode>()
+            .init_resource::<DifficultyPreset>()
+            .init_resource::<PlayfieldMode>()
+            .init_resource::<ArenaShape>()
+            .init_resource::<SpawnBias>()
+            .init_resource::<AdaptiveDifficulty>()
+            .init_resource::<SpawnArcBias>()
+            .init_resource::<Difficulty>()
+            .init_resource::<SurvivalTime>()
+            .init_resource::<SpawnTheme>()
+            .init_resource::<TrainingType>()
+            .init_resource::<Tutorial>()
+            .init_resource::<Finale>()
+            .add_systems(FixedUpdate, fixed_gameplay_systems());
+        app.world_mut()
+            .spawn((Player, Transform::default(), OxygenLevel(5.0)));
+        app.world_mut().spawn((
+            Camera3d::default(),
+            CameraFollow {
+                position: Vec3::ZERO,
+                look_ahead: Vec3::ZERO,
+            },
+        ));
+        app
+    }
+
+    //runs FixedUpdate at 64 ticks per second, with the Time advancing like in the game
+    fn run_for(app: &mut App, seconds: f32) {
+        for _ in 0..(seconds * 64.0) as u32 {
+            app.world_mut()
+                .resource_mut::<Time>()
+                .advance_by(std::time::Duration::from_secs_f32(1.0 / 64.0));
+            app.world_mut().run_schedule(FixedUpdate);
+        }
+    }
+
+    fn spawn_bubble_on_player(app: &mut App, bubble_type: BubbleType) {
+        app.world_mut().spawn((
+            Bubble {
+                bubble_type,
+                value: 1.0,
+                base_radius: BUBBLE_RADIUS,
+            },
+            Transform::from_translation(Vec3::Y * BUBBLE_HOVER_OFFSET),
+        ));
+    }
+
+    //puts gameplay_test_app in the middle of the first wave; every type has a placeholder model so
+    //each roll can be spawned
+    fn add_spawner(app: &mut App) {
+        let mut bubble_models = BubbleModels::default();
+        for bubble_type in BubbleType::ALL {
+            bubble_models
+                .placeholders
+                .insert(bubble_type, (Handle::default(), Handle::default()));
+        }
+        app.insert_resource(bubble_models).insert_resource(Wave {
+            number: 1,
+            bubbles_remaining: WAVE_FIRST_BUDGET,
+            rest_timer: Timer::from_seconds(WAVE_REST_DURATION, TimerMode::Once),
+            resting: false,
+        });
+    }
+
+    //bubbles taken from the wave budget, the spawner takes one for every bubble it queues
+    fn run_spawner_for(app: &mut App, seconds: f32) -> u32 {
+        run_for(app, seconds);
+        WAVE_FIRST_BUDGET - app.world().resource::<Wave>().bubbles_remaining
+    }
+
+    fn oxygen_level(app: &mut App) -> f32 {
+        app.world_mut()
+            .query_filtered::<&OxygenLevel, With<Player>>()
+            .single(app.world())
+            .0
+    }
+
+    //the oxygen after one fixed update in which only the drain of the tick happens
+    fn oxygen_after_idle_tick(oxygen: f32) -> f32 {
+        let mut app = gameplay_test_app();
+        app.world_mut()
+            .query_filtered::<&mut OxygenLevel, With<Player>>()
+            .single_mut(app.world_mut())
+            .0 = oxygen;
+        app.world_mut().run_schedule(FixedUpdate);
+        oxygen_level(&mut app)
+    }
+
+    #[test]
+    fn bubble_on_the_player_is_collected_during_a_run() {
+        let mut app = gameplay_test_app();
+        spawn_bubble_on_player(&mut app, BubbleType::Regular);
+        app.world_mut().run_schedule(FixedUpdate);
+
+        assert!(!app.world().resource::<Events<BubbleHitEvent>>().is_empty());
+        assert_eq!(
+            oxygen_level(&mut app),
+            oxygen_after_idle_tick(5.0 + BUBBLE_EFFECT_OXYGEN_INCREASE)
+        );
+    }
+
+    #[test]
+    fn nothing_is_hit_and_no_oxygen_changes_after_the_game_over() {
+        let mut app = gameplay_test_app();
+        spawn_bubble_on_player(&mut app, BubbleType::Regular);
+        app.insert_resource(IsGameOver(true));
+        app.world_mut().run_schedule(FixedUpdate);
+
+        assert!(app.world().resource::<Events<BubbleHitEvent>>().is_empty());
+        assert_eq!(oxygen_level(&mut app), 5.0);
+    }
+
+    #[test]
+    fn a_restart_after_a_blood_hit_starts_the_next_run_unfrozen() {
+        let mut app = gameplay_test_app();
+        spawn_bubble_on_player(&mut app, BubbleType::Blood);
+        app.world_mut().run_schedule(FixedUpdate);
+        assert!(!app.world().resource::<HitStop>().timer.finished());
+        app.world_mut().resource_mut::<PlayerStats>().radius *= 2.0;
+
+        app.world_mut().run_system_once(reset_run).unwrap();
+        assert!(app.world().resource::<HitStop>().timer.finished());
+        assert_eq!(app.world().resource::<PlayerStats>().radius, PLAYER_RADIUS);
+
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(std::time::Duration::from_secs_f32(1.0 / 64.0));
+        app.world_mut().run_system_once(tick_game_clock).unwrap();
+        assert!(!app.world().resource::<GameClock>().delta().is_zero());
+    }
+
+    //holds the dash key and a direction for one fixed update, the player starts with this much oxygen
+    fn dash_with_oxygen(oxygen: f32, god_mode: bool) -> App {
+        let mut app = gameplay_test_app();
+        app.insert_resource(GodMode(god_mode));
+        let movement_bindings = app.world().resource::<Settings>().movement_bindings.clone();
+        let mut keyboard_input = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        keyboard_input.press(movement_bindings.dash);
+        keyboard_input.press(movement_bindings.right);
+        app.world_mut()
+            .query_filtered::<&mut OxygenLevel, With<Player>>()
+            .single_mut(app.world_mut())
+            .0 = oxygen;
+        app.world_mut().run_schedule(FixedUpdate);
+        app
+    }
+
+    #[test]
+    fn dash_needs_the_full_oxygen_cost() {
+        let dash = Dash::default();
+        assert!(dash.can_dash(DASH_OXYGEN_COST, false));
+        assert!(!dash.can_dash(DASH_OXYGEN_COST - 0.01, false));
+        assert!(!dash.can_dash(0.0, false));
+    }
+
+    #[test]
+    fn dash_takes_its_oxygen_cost() {
+        let mut app = dash_with_oxygen(5.0, false);
+
+        assert!(app.world().resource::<Dash>().is_active());
+        assert_eq!(
+            oxygen_level(&mut app),
+            oxygen_after_idle_tick(5.0 - DASH_OXYGEN_COST)
+        );
+    }
+
+    #[test]
+    fn dash_at_low_oxygen_is_blocked_and_spends_nothing() {
+        let mut app = dash_with_oxygen(DASH_OXYGEN_COST * 0.5, false);
+
+        assert!(!app.world().resource::<Dash>().is_active());
+        assert_eq!(
+            oxygen_level(&mut app),
+            oxygen_after_idle_tick(DASH_OXYGEN_COST * 0.5)
+        );
+    }
+
+    #[test]
+    fn dash_in_god_mode_is_free_even_at_low_oxygen() {
+        let mut app = dash_with_oxygen(DASH_OXYGEN_COST * 0.5, true);
+
+        assert!(app.world().resource::<Dash>().is_active());
+        assert!(oxygen_level(&mut app) >= DASH_OXYGEN_COST * 0.5);
+    }
+
+    //presses the panic button for a single fixed update with the given oxygen
+    fn panic_clear_with_oxygen(oxygen: f32, god_mode: bool) -> App {
+        let mut app = gameplay_test_app();
+        app.insert_resource(GodMode(god_mode));
+        let panic_clear_key = app
+            .world()
+            .resource::<Settings>()
+            .movement_bindings
+            .panic_clear;
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(panic_clear_key);
+        app.world_mut()
+            .query_filtered::<&mut OxygenLevel, With<Player>>()
+            .single_mut(app.world_mut())
+            .0 = oxygen;
+        app.world_mut().run_schedule(FixedUpdate);
+        app
+    }
+
+    #[test]
+    fn panic_clear_takes_its_oxygen_cost() {
+        let mut app = panic_clear_with_oxygen(10.0, false);
+
+        assert!(!app.world().resource::<PanicClear>().cooldown.finished());
+        assert_eq!(
+            oxygen_level(&mut app),
+            oxygen_after_idle_tick(10.0 - PANIC_CLEAR_OXYGEN_COST)
+        );
+    }
+
+    #[test]
+    fn panic_clear_in_god_mode_is_free_even_at_low_oxygen() {
+        let mut app = panic_clear_with_oxygen(PANIC_CLEAR_MIN_OXYGEN_LEFT, true);
+
+        assert!(!app.world().resource::<PanicClear>().cooldown.finished());
+        assert!(oxygen_level(&mut app) >= PANIC_CLEAR_MIN_OXYGEN_LEFT);
+    }
+
+    #[test]
+    fn a_frozen_player_does_not_pop_along_the_dash_path() {
+        let mut app = gameplay_test_app();
+        let mut dash = Dash::default();
+        dash.active.reset();
+        dash.direction = Vec2::X;
+        dash.segment_start = Vec3::new(-3.0, 0.0, 0.0);
+        app.insert_resource(dash);
+        app.world_mut()
+            .resource_mut::<ActiveEffects>()
+            .refresh(EffectKind::Freeze, 10.0);
+        app.world_mut().spawn((
+            Bubble {
+                bubble_type: BubbleType::Regular,
+                value: 1.0,
+                base_radius: BUBBLE_RADIUS,
+            },
+            Transform::from_translation(Vec3::new(-1.5, BUBBLE_HOVER_OFFSET, 0.0)),
+        ));
+        app.world_mut().run_schedule(FixedUpdate);
+
+        assert!(!app.world().resource::<Dash>().is_active());
+        assert!(app.world().resource::<Events<BubbleHitEvent>>().is_empty());
+    }
+
+    #[test]
+    fn bubbles_spawn_while_not_frozen() {
+        let mut app = gameplay_test_app();
+        add_spawner(&mut app);
+
+        assert!(run_spawner_for(&mut app, 2.0) > 0);
+    }
+
+    #[test]
+    fn no_bubbles_spawn_while_frozen() {
+        let mut app = gameplay_test_app();
+        add_spawner(&mut app);
+        app.world_mut()
+            .resource_mut::<ActiveEffects>()
+            .refresh(EffectKind::Freeze, 10.0);
+
+        assert_eq!(run_spawner_for(&mut app, 2.0), 0);
+        assert!(app.world().resource::<SpawnQueue>().0.is_empty());
+        assert!(app
+            .world_mut()
+            .query_filtered::<(), With<Bubble>>()
+            .iter(app.world())
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn bubbles_spawn_while_frozen_when_the_setting_is_off() {
+        let mut app = gameplay_test_app();
+        add_spawner(&mut app);
+        app.world_mut()
+            .resource_mut::<Settings>()
+            .freeze_pauses_spawns = false;
+        app.world_mut()
+            .resource_mut::<ActiveEffects>()
+            .refresh(EffectKind::Freeze, 10.0);
+
+        assert!(run_spawner_for(&mut app, 2.0) > 0);
+    }
+
+    #[test]
+    fn density_fill_stays_within_the_wave_budget() {
+        let mut app = gameplay_test_app();
+        add_spawner(&mut app);
+        app.world_mut().resource_mut::<Settings>().density_fill = true;
+        app.world_mut().resource_mut::<Wave>().bubbles_remaining = 5;
+
+        run_for(&mut app, 1.0);
+
+        let bubbles = app
+            .world_mut()
+            .query_filtered::<(), With<Bubble>>()
+            .iter(app.world())
+            .count();
+        assert_eq!(app.world().resource::<Wave>().bubbles_remaining, 0);
+        assert!(bubbles + app.world().resource::<SpawnQueue>().0.len() <= 5);
+    }
+
+    //runs the fixed chain once with an empty tank, so the run ends, and scores the game over
+    fn game_over_with_reserve(bonus_reserve: f32) -> App {
+        let mut app = gameplay_test_app();
+        app.insert_resource(BonusReserve(bonus_reserve));
+        app.world_mut()
+            .query_filtered::<&mut OxygenLevel, With<Player>>()
+            .single_mut(app.world_mut())
+            .0 = -1.0;
+        app.world_mut().run_schedule(FixedUpdate);
+        app.world_mut()
+            .run_system_once(convert_oxygen_to_score)
+            .unwrap();
+        app
+    }
+
+    #[test]
+    fn reserve_left_at_the_game_over_is_added_to_the_score() {
+        let app = game_over_with_reserve(2.0);
+
+        let oxygen_bonus = app.world().resource::<OxygenBonus>().0;
+        assert!(app.world().resource::<IsGameOver>().0);
+        assert!(oxygen_bonus > 0);
+        assert_eq!(
+            oxygen_bonus,
+            oxygen_bonus_points(app.world().resource::<BonusReserve>().0)
+        );
+        assert_eq!(app.world().resource::<Score>().0, oxygen_bonus);
+    }
+
+    #[test]
+    fn an_empty_reserve_is_no_bonus() {
+        let app = game_over_with_reserve(0.0);
+
+        assert!(app.world().resource::<IsGameOver>().0);
+        assert_eq!(app.world().resource::<OxygenBonus>().0, 0);
+        assert_eq!(app.world().resource::<Score>().0, 0);
+    }
+
+    #[test]
+    fn turning_god_mode_off_before_dying_does_not_set_a_best_time() {
+        let mut app = gameplay_test_app();
+        app.insert_resource(GodMode(true));
+        app.world_mut().run_schedule(FixedUpdate);
+        app.insert_resource(GodMode(false));
+
+        die_after(&mut app, 100.0);
+
+        assert!(app.world().resource::<IsGameOver>().0);
+        assert!(app.world().resource::<Settings>().best_times.is_empty());
+    }
+
+    //ends the run after the given survival time and records it
+    fn die_after(app: &mut App, survival_time: f32) {
+        app.insert_resource(SurvivalTime(survival_time));
+        app.world_mut()
+            .query_filtered::<&mut OxygenLevel, With<Player>>()
+            .single_mut(app.world_mut())
+            .0 = -1.0;
+        app.world_mut().run_schedule(FixedUpdate);
+        app.world_mut()
+            .run_system_once(record_best_survival_time)
+            .unwrap();
+    }
+
+    #[test]
+    fn runs_with_another_starting_oxygen_keep_their_own_best_time() {
+        let mut app = gameplay_test_app();
+        app.insert_resource(StartOxygen(START_OXYGEN_MAX));
+
+        die_after(&mut app, 100.0);
+
+        let settings = app.world().resource::<Settings>();
+        let best_time = |start_oxygen| {
+            settings.best_survival_time(
+                GameMode::default(),
+                PlayfieldMode::default(),
+                DifficultyPreset::default(),
+                start_oxygen,
+            )
+        };
+        assert_eq!(best_time(START_OXYGEN_MAX), Some(100.0));
+        assert_eq!(best_time(START_OXYGEN_MIN), None);
+    }
+
+    #[test]
+    fn playing_a_replay_does_not_set_a_best_time() {
+        let mut app = gameplay_test_app();
+        app.insert_resource(ReplayPlayer {
+            game_mode: GameMode::default(),
+            playfield: PlayfieldMode::default(),
+            difficulty_preset: DifficultyPreset::default(),
+            start_oxygen: PLAYER_OXYGEN_START_SUPPLY,
+            adaptive_difficulty: false,
+            density_fill: false,
+            freeze_pauses_spawns: true,
+            arena_shape: ArenaShape::default(),
+            frames: Vec::new(),
+            next_frame: 0,
+        });
+
+        die_after(&mut app, 100.0);
+
+        assert!(app.world().resource::<IsGameOver>().0);
+        assert!(app.world().resource::<Settings>().best_times.is_empty());
+    }
+
+    #[test]
+    fn reserve_is_rounded_to_whole_points() {
+        assert_eq!(oxygen_bonus_points(1.4 / OXYGEN_BONUS_POINTS_PER_UNIT), 1);
+        assert_eq!(oxygen_bonus_points(1.6 / OXYGEN_BONUS_POINTS_PER_UNIT), 2);
+        assert_eq!(oxygen_bonus_points(-0.4), 0);
+    }
+
+    #[test]
+    fn an_empty_wave_is_announced_as_cleared_once() {
+        let mut app = gameplay_test_app();
+        app.insert_resource(Wave {
+            number: 1,
+            bubbles_remaining: 0,
+            rest_timer: Timer::from_seconds(WAVE_REST_DURATION, TimerMode::Once),
+            resting: false,
+        });
+
+        run_for(&mut app, WAVE_REST_DURATION / 2.0);
+
+        assert_eq!(app.world().resource::<Events<WaveClearedEvent>>().len(), 1);
+    }
+
+    #[test]
+    fn random_point_stays_inside_a_concave_polygon_it_cannot_hit() {
+        //a small u far from the origin, the average of its corners lies in the gap of the u
+        let offset = Vec2::splat(40.0);
+        let arena_shape = ArenaShape::Polygon {
+            points: [
+                (0.0, 0.0),
+                (3.0, 0.0),
+                (3.0, 3.0),
+                (2.0, 3.0),
+                (2.0, 1.0),
+                (1.0, 1.0),
+                (1.0, 3.0),
+                (0.0, 3.0),
+            ]
+            .map(|(x, y)| Vec2::new(x, y) + offset)
+            .to_vec(),
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let point = arena_shape.random_point(&mut rng, Vec2::ZERO, 0.0);
+
+        assert!(arena_shape.point_inside_arena(point), "{point} is outside");
+    }
+
+    #[test]
+    fn bubble_clearly_missing_the_player_is_not_hit() {
+        let bubbles = [(Vec3::new(3.0, 0.0, 0.0), BubbleType::Blood, 0.25)];
+        assert!(bubbles_hitting_player(Vec3::ZERO, 0.5, None, &bubbles).is_empty());
+    }
+
+    #[test]
+    fn bubble_at_exactly_the_radius_sum_is_hit() {
+        //tangent contact counts as a hit
+        let bubbles = [(Vec3::new(0.75, 0.0, 0.0), BubbleType::Blood, 0.25)];
+        assert_eq!(
+            bubbles_hitting_player(Vec3::ZERO, 0.5, None, &bubbles),
+            vec![(0, BubbleType::Blood)]
+        );
+    }
+
+    #[test]
+    fn bubble_just_beyond_the_radius_sum_is_not_hit() {
+        let bubbles = [(Vec3::new(0.76, 0.0, 0.0), BubbleType::Blood, 0.25)];
+        assert!(bubbles_hitting_player(Vec3::ZERO, 0.5, None, &bubbles).is_empty());
+    }
+
+    #[test]
+    fn height_of_a_bubble_does_not_change_a_hit() {
+        //hover offset plus the top of a bob, the x-z distance alone decides
+        let bubbles = [(Vec3::new(0.75, 1.0, 0.0), BubbleType::Blood, 0.25)];
+        assert_eq!(
+            bubbles_hitting_player(Vec3::ZERO, 0.5, None, &bubbles),
+            vec![(0, BubbleType::Blood)]
+        );
+    }
+
+    #[test]
+    fn only_the_hitting_bubbles_are_returned_with_their_index() {
+        let bubbles = [
+            (Vec3::new(5.0, 0.0, 0.0), BubbleType::Blood, 0.25),
+            (Vec3::new(0.0, 0.0, 0.5), BubbleType::Regular, 0.25),
+            (Vec3::new(0.0, 0.0, -5.0), BubbleType::Dirt, 0.25),
+            (Vec3::new(-0.6, 0.0, 0.0), BubbleType::Mine, 0.25),
+        ];
+        assert_eq!(
+            bubbles_hitting_player(Vec3::ZERO, 0.5, None, &bubbles),
+            vec![(1, BubbleType::Regular), (3, BubbleType::Mine)]
+        );
+    }
+
+    #[test]
+    fn dash_sweep_hits_bubbles_along_the_path() {
+        //the player ended the dash at the origin, the bubble was passed halfway
+        let bubbles = [(Vec3::new(-2.0, 0.0, 0.5), BubbleType::Regular, 0.25)];
+        let dash_sweep = Some((Vec3::new(-4.0, 0.0, 0.0), Vec3::ZERO, 0.5));
+        assert!(bubbles_hitting_player(Vec3::ZERO, 0.5, None, &bubbles).is_empty());
+        assert_eq!(
+            bubbles_hitting_player(Vec3::ZERO, 0.5, dash_sweep, &bubbles),
+            vec![(0, BubbleType::Regular)]
+        );
+    }
+
+    //p50 and p99 of the given timings, in milliseconds
+    fn percentiles_ms(mut timings: Vec<std::time::Duration>) -> (f64, f64) {
+        timings.sort();
+        let ms = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+        (
+            ms(timings[timings.len() / 2]),
+            ms(timings[timings.len() * 99 / 100]),
+        )
+    }
+
+    //run with: cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn grid_lookup_is_faster_than_a_linear_scan() {
+        const BUBBLE_COUNT: u32 = 5000;
+        const QUERY_COUNT: usize = 2000;
+        let reach = PLAYER_RADIUS + BUBBLE_MAX_RADIUS;
+        let mut game_rng = GameRng::from_seed(1);
+        let mut random_position = || {
+            Vec3::new(
+                game_rng.rng.gen_range(-60.0..60.0),
+                0.0,
+                game_rng.rng.gen_range(-60.0..60.0),
+            )
+        };
+        let bubbles: Vec<(Entity, Vec3)> = (0..BUBBLE_COUNT)
+            .map(|index| (Entity::from_raw(index), random_position()))
+            .collect();
+        let queries: Vec<Vec3> = (0..QUERY_COUNT).map(|_| random_position()).collect();
+        let mut grid = BubbleGrid::default();
+        for (entity, position) in &bubbles {
+            grid.insert(*entity, *position);
+        }
+        let positions: HashMap<Entity, Vec3> = bubbles.iter().copied().collect();
+
+        let mut grid_timings = Vec::new();
+        let mut linear_timings = Vec::new();
+        for query in queries {
+            let start = std::time::Instant::now();
+            let mut grid_hits: Vec<Entity> = grid
+                .nearby(query, reach)
+                .filter(|entity| positions[entity].distance(query) <= reach)
+                .collect();
+            grid_timings.push(start.elapsed());
+
+            let start = std::time::Instant::now();
+            let mut linear_hits: Vec<Entity> = bubbles
+                .iter()
+                .filter(|(_, position)| position.distance(query) <= reach)
+                .map(|(entity, _)| *entity)
+                .collect();
+            linear_timings.push(start.elapsed());
+
+            grid_hits.sort();
+            linear_hits.sort();
+            assert_eq!(grid_hits, linear_hits);
+        }
+
+        let (grid_p50, grid_p99) = percentiles_ms(grid_timings);
+        let (linear_p50, linear_p99) = percentiles_ms(linear_timings);
+        println!(
+            "{} bubbles: grid p50_ms={:.4} p99_ms={:.4}, linear p50_ms={:.4} p99_ms={:.4}",
+            BUBBLE_COUNT, grid_p50, grid_p99, linear_p50, linear_p99
+        );
+        assert!(grid_p50 < linear_p50);
+    }
+
+    //frame times of a --benchmark run without the rendering: the benchmark bubbles, the real fixed
+    //update chain in god mode and the transform propagation, seen from the follow camera at its
+    //default distance; the warmup is left out like in run_benchmark
+    fn benchmark_frame_timings(offscreen_throttling: bool) -> Vec<std::time::Duration> {
+        let mut app = gameplay_test_app();
+        add_spawner(&mut app);
+        app.insert_resource(GodMode(true))
+            .insert_resource(Benchmark::default())
+            .insert_resource(Settings {
+                offscreen_throttling,
+                ..default()
+            });
+        let camera_transform = camera_local_transform(CAMERA_DISTANCE);
+        let projection = PerspectiveProjection {
+            aspect_ratio: 16.0 / 9.0,
+            ..default()
+        };
+        let frustum = Frustum::from_clip_from_world(
+            &(projection.get_clip_from_view() * camera_transform.compute_matrix().inverse()),
+        );
+        let camera = app
+            .world_mut()
+            .query_filtered::<Entity, With<Camera3d>>()
+            .single(app.world());
+        app.world_mut()
+            .entity_mut(camera)
+            .insert((camera_transform, frustum));
+
+        let mut frame = Schedule::default();
+        frame.add_systems(
+            (
+                top_up_benchmark_bubbles,
+                bevy::transform::systems::sync_simple_transforms,
+                bevy::transform::systems::propagate_transforms,
+            )
+                .chain(),
+        );
+        let warmup_frames = (BENCHMARK_WARMUP * 64.0) as usize;
+        let frames = (BENCHMARK_DURATION * 64.0) as usize;
+        let mut timings: Vec<_> = (0..warmup_frames + frames)
+            .map(|_| {
+                let world = app.world_mut();
+                world
+                    .resource_mut::<Time>()
+                    .advance_by(std::time::Duration::from_secs_f32(1.0 / 64.0));
+                let start = std::time::Instant::now();
+                world.run_schedule(FixedUpdate);
+                frame.run(world);
+                start.elapsed()
+            })
+            .collect();
+        timings.drain(..warmup_frames);
+        timings
+    }
+
+    //run with: cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn offscreen_throttling_in_the_benchmark() {
+        let (off_p50, off_p99) = percentiles_ms(benchmark_frame_timings(false));
+        let (on_p50, on_p99) = percentiles_ms(benchmark_frame_timings(true));
+        println!(
+            "benchmark without rendering: throttling off p50_ms={:.4} p99_ms={:.4}, on p50_ms={:.4} p99_ms={:.4}",
+            off_p50, off_p99, on_p50, on_p99
+        );
+    }
+}
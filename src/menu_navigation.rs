@@ -0,0 +1,111 @@
+//keyboard and gamepad navigation for menus made of MenuItem buttons; the mouse keeps working
+//through Interaction, both end up in the same highlight and the same MenuActivated event
+//a menu only has to give its buttons a MenuItem and read MenuActivated for its own entities
+//space and escape are left alone, they are the default dash and quit keys
+
+use bevy::prelude::*;
+
+use super::{BUTTON_COLOR, BUTTON_HOVERED_COLOR, BUTTON_PRESSED_COLOR};
+
+pub struct MenuNavigationPlugin;
+
+impl Plugin for MenuNavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MenuSelection>()
+            .add_event::<MenuActivated>()
+            .add_systems(Update, (navigate_menu, highlight_menu_items).chain());
+    }
+}
+
+//position of a button in its menu, counted from the top (or the left)
+#[derive(Component)]
+pub struct MenuItem {
+    pub index: usize,
+}
+
+//highlighted as soon as its menu appears, so confirming right away picks it
+#[derive(Component)]
+pub struct MenuDefault;
+
+//the item was clicked or confirmed with enter or the south gamepad button
+#[derive(Event)]
+pub struct MenuActivated(pub Entity);
+
+//index of the highlighted item; nothing is highlighted until a menu is navigated or hovered
+#[derive(Resource, Default)]
+struct MenuSelection(Option<usize>);
+
+fn navigate_menu(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    item_query: Query<(Entity, &MenuItem)>,
+    interaction_query: Query<(Entity, &MenuItem, &Interaction), Changed<Interaction>>,
+    default_query: Query<&MenuItem, Added<MenuDefault>>,
+    mut selection: ResMut<MenuSelection>,
+    mut activated_event_writer: EventWriter<MenuActivated>,
+) {
+    let item_count = item_query.iter().count();
+    if item_count == 0 {
+        selection.0 = None;
+        return;
+    }
+    if let Some(item) = default_query.iter().next() {
+        selection.0 = Some(item.index);
+    }
+
+    let gamepad_pressed =
+        |button: GamepadButton| gamepads.iter().any(|gamepad| gamepad.just_pressed(button));
+    //menus are laid out in a row or a column, both directions work for either
+    let previous = keyboard_input.any_just_pressed([KeyCode::ArrowUp, KeyCode::ArrowLeft])
+        || gamepad_pressed(GamepadButton::DPadUp)
+        || gamepad_pressed(GamepadButton::DPadLeft);
+    let next = keyboard_input.any_just_pressed([KeyCode::ArrowDown, KeyCode::ArrowRight])
+        || gamepad_pressed(GamepadButton::DPadDown)
+        || gamepad_pressed(GamepadButton::DPadRight);
+    let confirm = keyboard_input.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter])
+        || gamepad_pressed(GamepadButton::South);
+
+    //the mouse moves the highlight too, so switching between mouse and keys does not jump
+    for (entity, item, interaction) in &interaction_query {
+        match interaction {
+            Interaction::Hovered => selection.0 = Some(item.index),
+            Interaction::Pressed => {
+                selection.0 = Some(item.index);
+                activated_event_writer.send(MenuActivated(entity));
+            }
+            Interaction::None => {}
+        }
+    }
+
+    if previous || next {
+        selection.0 = Some(match selection.0 {
+            None => 0,
+            Some(index) if previous => (index + item_count - 1) % item_count,
+            Some(index) => (index + 1) % item_count,
+        });
+    }
+    if confirm {
+        if let Some((entity, _)) = item_query
+            .iter()
+            .find(|(_, item)| Some(item.index) == selection.0)
+        {
+            activated_event_writer.send(MenuActivated(entity));
+        }
+    }
+}
+
+fn highlight_menu_items(
+    selection: Res<MenuSelection>,
+    mut item_query: Query<(&MenuItem, &Interaction, &mut BackgroundColor)>,
+) {
+    for (item, interaction, mut background_color) in &mut item_query {
+        let color = if *interaction == Interaction::Pressed {
+            BUTTON_PRESSED_COLOR
+        } else if selection.0 == Some(item.index) {
+            BUTTON_HOVERED_COLOR
+        } else {
+            BUTTON_COLOR
+        };
+        background_color.set_if_neq(BackgroundColor(color));
+    }
+}
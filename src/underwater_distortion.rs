@@ -0,0 +1,203 @@
+use bevy::{
+    core_pipeline::{
+        core_3d::graph::{Core3d, Node3d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        extract_component::{
+            ComponentUniforms, DynamicUniformIndex, ExtractComponentPlugin, UniformComponentPlugin,
+        },
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            binding_types::{sampler, texture_2d, uniform_buffer},
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, CachedRenderPipelineId,
+            ColorTargetState, ColorWrites, FragmentState, MultisampleState, Operations,
+            PipelineCache, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+            RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
+            TextureFormat, TextureSampleType,
+        },
+        renderer::{RenderContext, RenderDevice},
+        view::ViewTarget,
+        RenderApp,
+    },
+};
+
+const UNDERWATER_DISTORTION_SHADER_PATH: &str = "shaders/underwater_distortion.wgsl";
+
+//post process pass that runs UNDERWATER_DISTORTION_SHADER_PATH on every camera with an
+//UnderwaterDistortion component, right after tonemapping
+pub struct UnderwaterDistortionPlugin;
+
+impl Plugin for UnderwaterDistortionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<UnderwaterDistortion>::default(),
+            UniformComponentPlugin::<UnderwaterDistortion>::default(),
+        ));
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .add_render_graph_node::<ViewNodeRunner<UnderwaterDistortionNode>>(
+                Core3d,
+                UnderwaterDistortionLabel,
+            )
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    Node3d::Tonemapping,
+                    UnderwaterDistortionLabel,
+                    Node3d::EndMainPassPostProcessing,
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<UnderwaterDistortionPipeline>();
+    }
+}
+
+pub use uniform::UnderwaterDistortion;
+
+//the ShaderType derive generates layout checks next to the struct that are never called in a
+//binary, so the allow is kept to this module instead of the whole file
+#[allow(dead_code)]
+mod uniform {
+    use bevy::{
+        prelude::*,
+        render::{extract_component::ExtractComponent, render_resource::ShaderType},
+    };
+
+    //uniform for the distortion shader, the layout has to match the struct in the shader
+    #[derive(Component, Default, Clone, Copy, ExtractComponent, ShaderType)]
+    pub struct UnderwaterDistortion {
+        pub strength: f32,
+        pub time: f32,
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct UnderwaterDistortionLabel;
+
+#[derive(Default)]
+struct UnderwaterDistortionNode;
+
+impl ViewNode for UnderwaterDistortionNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static DynamicUniformIndex<UnderwaterDistortion>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, distortion_index): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let distortion_pipeline = world.resource::<UnderwaterDistortionPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        //the shader might still be loading
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(distortion_pipeline.pipeline_id)
+        else {
+            return Ok(());
+        };
+        let distortion_uniforms = world.resource::<ComponentUniforms<UnderwaterDistortion>>();
+        let Some(distortion_binding) = distortion_uniforms.uniforms().binding() else {
+            return Ok(());
+        };
+
+        let post_process = view_target.post_process_write();
+        let bind_group = render_context.render_device().create_bind_group(
+            "underwater_distortion_bind_group",
+            &distortion_pipeline.layout,
+            &BindGroupEntries::sequential((
+                post_process.source,
+                &distortion_pipeline.sampler,
+                distortion_binding,
+            )),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("underwater_distortion_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: post_process.destination,
+                resolve_target: None,
+                ops: Operations::default(),
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[distortion_index.index()]);
+        render_pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct UnderwaterDistortionPipeline {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for UnderwaterDistortionPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "underwater_distortion_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    sampler(SamplerBindingType::Filtering),
+                    uniform_buffer::<UnderwaterDistortion>(true),
+                ),
+            ),
+        );
+        let sampler = render_device.create_sampler(&SamplerDescriptor::default());
+        let shader = world.load_asset(UNDERWATER_DISTORTION_SHADER_PATH);
+
+        let pipeline_id =
+            world
+                .resource_mut::<PipelineCache>()
+                .queue_render_pipeline(RenderPipelineDescriptor {
+                    label: Some("underwater_distortion_pipeline".into()),
+                    layout: vec![layout.clone()],
+                    vertex: fullscreen_shader_vertex_state(),
+                    fragment: Some(FragmentState {
+                        shader,
+                        shader_defs: vec![],
+                        entry_point: "fragment".into(),
+                        targets: vec![Some(ColorTargetState {
+                            format: TextureFormat::bevy_default(),
+                            blend: None,
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    push_constant_ranges: vec![],
+                    zero_initialize_workgroup_memory: false,
+                });
+
+        UnderwaterDistortionPipeline {
+            layout,
+            sampler,
+            pipeline_id,
+        }
+    }
+}